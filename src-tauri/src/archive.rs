@@ -0,0 +1,464 @@
+// Backup archive format.
+//
+// `backup_data` writes newline-delimited JSON, one record per line, so a
+// backup is written and read an item or image at a time:
+//
+//   {"type":"header","format":"ortu-archive","version":1,"exported_at":"…"}
+//   {"type":"group","id":1,"name":"Work","is_system":false}    one per group
+//   {"type":"item","id":7,"content_type":"text",…}             one per item
+//   {"type":"blob","hash":"…","mime":"image/png","data":"<base64>","thumb":null}
+//   {"type":"end","items":1234,"blobs":56}
+//
+// Blob records carry the `blobs` rows that image items reference by hash, so
+// images survive a restore on another machine. Thumbnails are optional; when
+// a record has none, restore rebuilds it. Restore skips hashes that are
+// already stored and drops any image whose pixels don't hash to its name.
+// The closing `end` record counts what came before it, so a truncated file is
+// rejected rather than half-restored. Single-document JSON backups from older
+// versions still restore (see `open`).
+
+use crate::clipboard::{sha256_hex, thumbnail_png};
+use crate::db::{BackupData, Blob, ClipboardItem, Group, RestoreSession};
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io::{BufRead, Read, Write};
+
+const FORMAT: &str = "ortu-archive";
+const VERSION: u32 = 1;
+/// Longest record a reader accepts; longer ones mean a damaged file. The
+/// largest the app writes is an item with 50 MiB of text and as much markup
+/// (more once JSON-escaped or encrypted), or a 40 MiB image in base64.
+#[cfg(not(test))]
+const MAX_RECORD_BYTES: u64 = 256 * 1024 * 1024;
+#[cfg(test)]
+const MAX_RECORD_BYTES: u64 = 64 * 1024;
+const B64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Header {
+        format: String,
+        version: u32,
+        exported_at: String,
+    },
+    Group(Group),
+    Item(ClipboardItem),
+    Blob(BlobRecord),
+    /// Counts of what came before, so truncation is detectable.
+    End {
+        items: u64,
+        blobs: u64,
+    },
+}
+
+/// An archived blob, still base64-encoded.
+#[derive(Serialize, Deserialize)]
+pub struct BlobRecord {
+    pub hash: String,
+    mime: String,
+    data: String,
+    #[serde(default)]
+    thumb: Option<String>,
+}
+
+impl BlobRecord {
+    fn encode(blob: &Blob, thumbnails: bool) -> Self {
+        BlobRecord {
+            hash: blob.hash.clone(),
+            mime: blob.mime.clone(),
+            data: B64.encode(&blob.data),
+            thumb: blob
+                .thumb
+                .as_ref()
+                .filter(|_| thumbnails)
+                .map(|t| B64.encode(t)),
+        }
+    }
+
+    /// Decodes the record, checking that the image hashes to its name (the
+    /// SHA-256 of its RGBA pixels, as on capture) and rebuilding a missing
+    /// thumbnail.
+    pub fn decode(self) -> Result<Blob, String> {
+        let data = B64.decode(&self.data).map_err(|e| e.to_string())?;
+        let img = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+        if sha256_hex(img.to_rgba8().as_raw()) != self.hash {
+            return Err("image doesn't match its hash".to_string());
+        }
+        let thumb = match self.thumb {
+            Some(t) => B64.decode(t).map_err(|e| e.to_string())?,
+            None => thumbnail_png(&img)?,
+        };
+        Ok(Blob {
+            hash: self.hash,
+            mime: self.mime,
+            data,
+            thumb: Some(thumb),
+        })
+    }
+}
+
+/// What happened to the blobs of a restored archive.
+#[derive(Default, Debug)]
+pub struct BlobReport {
+    pub restored: usize,
+    /// Already stored under the same hash.
+    pub skipped: usize,
+    /// Undecodable or not matching their hash.
+    pub damaged: usize,
+}
+
+impl BlobReport {
+    /// Stores one archived blob unless its hash is already present.
+    pub fn restore(&mut self, db: &RestoreSession, record: BlobRecord) -> Result<(), String> {
+        if db.has_blob(&record.hash).map_err(|e| e.to_string())? {
+            self.skipped += 1;
+            return Ok(());
+        }
+        let hash = record.hash.clone();
+        match record.decode() {
+            Ok(blob) => {
+                db.insert_blob(&blob.hash, &blob.mime, &blob.data, blob.thumb.as_deref())
+                    .map_err(|e| e.to_string())?;
+                self.restored += 1;
+            }
+            Err(e) => {
+                eprintln!("Backup: skipping image {}: {}", hash, e);
+                self.damaged += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What an archive holds, after the header.
+pub enum Entry {
+    Group(Group),
+    Item(ClipboardItem),
+    Blob(BlobRecord),
+}
+
+/// Streams records into an archive.
+pub struct Writer<W: Write> {
+    out: W,
+    thumbnails: bool,
+    /// Images referenced by written items, not yet written themselves.
+    hashes: BTreeSet<String>,
+    items: u64,
+    blobs: u64,
+}
+
+impl<W: Write> Writer<W> {
+    /// Starts an archive on `out`. `thumbnails` keeps each blob's preview.
+    pub fn new(mut out: W, exported_at: String, thumbnails: bool) -> Result<Self, String> {
+        write_record(
+            &mut out,
+            &Record::Header {
+                format: FORMAT.to_string(),
+                version: VERSION,
+                exported_at,
+            },
+        )?;
+        Ok(Writer {
+            out,
+            thumbnails,
+            hashes: BTreeSet::new(),
+            items: 0,
+            blobs: 0,
+        })
+    }
+
+    pub fn group(&mut self, group: Group) -> Result<(), String> {
+        write_record(&mut self.out, &Record::Group(group))
+    }
+
+    pub fn item(&mut self, item: ClipboardItem) -> Result<(), String> {
+        if item.content_type == "image" {
+            self.hashes.insert(item.raw_content.clone());
+        }
+        self.items += 1;
+        write_record(&mut self.out, &Record::Item(item))
+    }
+
+    /// Hashes of the images written items point at, each once; write their
+    /// blobs with `blob` once the items are done.
+    pub fn take_image_hashes(&mut self) -> BTreeSet<String> {
+        std::mem::take(&mut self.hashes)
+    }
+
+    pub fn blob(&mut self, blob: &Blob) -> Result<(), String> {
+        self.blobs += 1;
+        write_record(
+            &mut self.out,
+            &Record::Blob(BlobRecord::encode(blob, self.thumbnails)),
+        )
+    }
+
+    /// Writes the closing record, which lets a reader tell a complete archive
+    /// from a truncated one, and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, String> {
+        write_record(
+            &mut self.out,
+            &Record::End {
+                items: self.items,
+                blobs: self.blobs,
+            },
+        )?;
+        self.out.flush().map_err(|e| e.to_string())?;
+        Ok(self.out)
+    }
+}
+
+fn write_record<W: Write>(out: &mut W, record: &Record) -> Result<(), String> {
+    serde_json::to_writer(&mut *out, record).map_err(|e| e.to_string())?;
+    out.write_all(b"\n").map_err(|e| e.to_string())
+}
+
+/// A backup file as opened by `open`.
+pub enum Backup<R: BufRead> {
+    Archive(Reader<R>),
+    /// A single-document JSON backup from before the archive format, parsed
+    /// whole.
+    Legacy(BackupData),
+}
+
+/// Opens a backup: an archive is returned as a `Reader` positioned after its
+/// header; anything else is parsed as an older JSON backup.
+pub fn open<R: BufRead>(mut input: R) -> Result<Backup<R>, String> {
+    let mut first = Vec::new();
+    read_record(&mut input, &mut first)?;
+    match serde_json::from_slice::<Record>(&first) {
+        Ok(Record::Header {
+            format, version, ..
+        }) if format == FORMAT => {
+            if version > VERSION {
+                return Err(format!(
+                    "Backup format version {} is newer than this app supports",
+                    version
+                ));
+            }
+            Ok(Backup::Archive(Reader {
+                input,
+                line: Vec::new(),
+                line_no: 1,
+                items: 0,
+                blobs: 0,
+                ended: false,
+            }))
+        }
+        _ => {
+            let mut json = first;
+            input.read_to_end(&mut json).map_err(|e| e.to_string())?;
+            let data = serde_json::from_slice(&json).map_err(|e| e.to_string())?;
+            Ok(Backup::Legacy(data))
+        }
+    }
+}
+
+/// One line into `line` (emptied first), up to `MAX_RECORD_BYTES` and a
+/// byte past it; returns the bytes read, 0 at the end of the input.
+fn read_record<R: BufRead>(input: &mut R, line: &mut Vec<u8>) -> Result<usize, String> {
+    line.clear();
+    input
+        .take(MAX_RECORD_BYTES + 1)
+        .read_until(b'\n', line)
+        .map_err(|e| e.to_string())
+}
+
+/// Reads an archive one record at a time.
+pub struct Reader<R: BufRead> {
+    input: R,
+    line: Vec<u8>,
+    line_no: usize,
+    items: u64,
+    blobs: u64,
+    ended: bool,
+}
+
+impl<R: BufRead> Reader<R> {
+    /// The next group, item or blob; None at the end of a complete archive.
+    pub fn next_entry(&mut self) -> Result<Option<Entry>, String> {
+        loop {
+            let read = read_record(&mut self.input, &mut self.line)?;
+            if read as u64 > MAX_RECORD_BYTES {
+                return Err(format!(
+                    "Backup line {}: record is too long",
+                    self.line_no + 1
+                ));
+            }
+            if read == 0 {
+                if !self.ended {
+                    return Err("Backup is incomplete (the file ends early)".to_string());
+                }
+                return Ok(None);
+            }
+            self.line_no += 1;
+            if self.line.trim_ascii().is_empty() {
+                continue;
+            }
+            if self.ended {
+                return Err(format!("Backup line {}: data after the end", self.line_no));
+            }
+            let record: Record = serde_json::from_slice(&self.line)
+                .map_err(|e| format!("Backup line {}: {}", self.line_no, e))?;
+            return Ok(Some(match record {
+                Record::Group(group) => Entry::Group(group),
+                Record::Item(item) => {
+                    self.items += 1;
+                    Entry::Item(item)
+                }
+                Record::Blob(blob) => {
+                    self.blobs += 1;
+                    Entry::Blob(blob)
+                }
+                Record::End { items, blobs } => {
+                    if (items, blobs) != (self.items, self.blobs) {
+                        return Err("Backup is incomplete (records are missing)".to_string());
+                    }
+                    self.ended = true;
+                    continue;
+                }
+                Record::Header { .. } => {
+                    return Err(format!("Backup line {}: unexpected header", self.line_no));
+                }
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_and_hash(shade: u8) -> (Vec<u8>, String) {
+        let img = image::RgbaImage::from_pixel(300, 200, image::Rgba([shade, 40, 90, 255]));
+        let hash = sha256_hex(img.as_raw());
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba8(img)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        (png, hash)
+    }
+
+    fn item(id: i64, content_type: &str, raw: &str) -> ClipboardItem {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "content_type": content_type,
+            "raw_content": raw,
+            "category": null,
+            "groups": ["Images"],
+            "is_permanent": true,
+            "created_at": "2024-01-01 00:00:00",
+            "description": null,
+            "is_manual": false,
+        }))
+        .unwrap()
+    }
+
+    fn read_all(file: &[u8]) -> Result<Vec<Entry>, String> {
+        let Backup::Archive(mut reader) = open(file)? else {
+            panic!("expected an archive");
+        };
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn archive_carries_referenced_blobs_once() {
+        let (png, hash) = png_and_hash(200);
+        let mut writer = Writer::new(Vec::new(), "now".to_string(), false).unwrap();
+        writer
+            .group(Group {
+                id: 1,
+                name: "Images".to_string(),
+                is_system: true,
+            })
+            .unwrap();
+        for it in [
+            item(1, "image", &hash),
+            item(2, "text", "hello"),
+            item(3, "image", &hash),
+        ] {
+            writer.item(it).unwrap();
+        }
+        let hashes = writer.take_image_hashes();
+        assert_eq!(hashes.into_iter().collect::<Vec<_>>(), vec![hash.clone()]);
+        writer
+            .blob(&Blob {
+                hash: hash.clone(),
+                mime: "image/png".to_string(),
+                data: png.clone(),
+                thumb: Some(vec![1, 2, 3]),
+            })
+            .unwrap();
+        let file = writer.finish().unwrap();
+
+        let mut entries = read_all(&file).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert!(matches!(&entries[0], Entry::Group(g) if g.name == "Images"));
+        assert!(matches!(&entries[2], Entry::Item(i) if i.raw_content == "hello"));
+        let Some(Entry::Blob(record)) = entries.pop() else {
+            panic!("expected the blob last");
+        };
+
+        // Thumbnails were left out, so decode rebuilds one.
+        let blob = record.decode().unwrap();
+        assert_eq!(blob.data, png);
+        let thumb = image::load_from_memory(&blob.thumb.unwrap()).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (240, 160));
+
+        // Losing the tail (or any record) is caught by the end record.
+        let cut = file[..file.len() - 1]
+            .rsplitn(2, |b| *b == b'\n')
+            .nth(1)
+            .unwrap();
+        assert!(read_all(cut).is_err());
+        let mut missing_item = String::from_utf8(file.clone()).unwrap();
+        missing_item = missing_item.replacen(
+            &serde_json::to_string(&Record::Item(item(2, "text", "hello"))).unwrap(),
+            "",
+            1,
+        );
+        assert!(read_all(missing_item.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn overlong_record_is_rejected() {
+        let mut writer = Writer::new(Vec::new(), "now".to_string(), false).unwrap();
+        writer.item(item(1, "text", "short")).unwrap();
+        let mut file = writer.finish().unwrap();
+        // No newline, as from a damaged stream that inflates without end.
+        file.resize(file.len() + MAX_RECORD_BYTES as usize * 2, b'x');
+        let err = read_all(&file).err().unwrap();
+        assert!(err.contains("too long"), "{err}");
+    }
+
+    #[test]
+    fn older_json_backups_still_open() {
+        let legacy = b"{\n  \"history\": [],\n  \"groups\": [],\n  \"exported_at\": \"then\"\n}";
+        match open(&legacy[..]).unwrap() {
+            Backup::Legacy(data) => assert_eq!(data.exported_at, "then"),
+            Backup::Archive(_) => panic!("legacy JSON read as an archive"),
+        }
+    }
+
+    #[test]
+    fn blob_that_does_not_match_its_hash_is_rejected() {
+        let (png, _) = png_and_hash(10);
+        let (_, other_hash) = png_and_hash(11);
+        let record = BlobRecord {
+            hash: other_hash,
+            mime: "image/png".to_string(),
+            data: B64.encode(&png),
+            thumb: None,
+        };
+        assert!(record.decode().is_err());
+
+        let bad_version = "{\"type\":\"header\",\"format\":\"ortu-archive\",\"version\":99,\"exported_at\":\"x\"}\n";
+        assert!(open(bad_version.as_bytes()).is_err());
+    }
+}
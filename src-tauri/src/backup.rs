@@ -0,0 +1,510 @@
+// Encrypted backup container.
+//
+// `backup_data` with a passphrase wraps the archive (see archive.rs) in:
+//
+//   magic      8 bytes     "ORTU-BAK"
+//   version    u16 LE      format version (1)
+//   kdf        u8          1 = Argon2id
+//   m, t, p    3 × u32 LE  Argon2id memory (KiB), passes, lanes
+//   salt       16 bytes
+//   nonce      7 bytes     STREAM nonce prefix
+//   chunks                 deflate(archive) cut into 64 KiB pieces, each
+//                          sealed with AES-256-GCM, header as associated data
+//
+// Chunks follow the STREAM construction (aead::stream, BE32): a counter and a
+// last-chunk flag are folded into each nonce, so reordered, dropped or
+// truncated chunks fail to open, and both ends hold one chunk at a time
+// however large the backup. Every chunk authenticates the header, so
+// tampering with the KDF parameters fails like any other corruption. Costs
+// above `MAX_M_COST` / `MAX_T_COST` / `MAX_P_COST` are refused before the KDF
+// runs, so a crafted file can't make opening it allocate gigabytes or spin.
+//
+// Sensitive items inside the archive are re-encrypted under the backup key
+// (as `enc:v2:<kid>:` values), so a restore on another machine — with a
+// different local key — recovers them; `restore_data` re-encrypts them under
+// that machine's key.
+
+use crate::crypto::{self, KeyRing};
+use crate::db::ClipboardItem;
+use aes_gcm::{
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        KeyInit, Payload,
+    },
+    Aes256Gcm, Key,
+};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+const MAGIC: &[u8; 8] = b"ORTU-BAK";
+const VERSION: u16 = 1;
+const KDF_ARGON2ID: u8 = 1;
+/// Largest Argon2id costs a backup header may ask for: 1 GiB, 10 passes,
+/// 16 lanes.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 10;
+const MAX_P_COST: u32 = 16;
+/// Header bytes before the nonce.
+const FIXED_LEN: usize = 8 + 2 + 1 + 12 + 16;
+/// STREAM nonce prefix: the AES-GCM nonce minus the 5-byte counter and flag.
+const NONCE_LEN: usize = 7;
+/// Plaintext bytes per sealed chunk.
+const CHUNK_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const DAMAGED: &str = "Wrong passphrase, or the backup is damaged";
+
+/// Returned by `restore_data` for an encrypted backup given no passphrase;
+/// the frontend matches on it to ask for one.
+pub const PASSPHRASE_REQUIRED: &str = "This backup is encrypted; enter its passphrase";
+
+/// True if `bytes` start like an encrypted backup (vs. a plain archive).
+pub fn is_encrypted_backup(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// KDF parameters and key for one backup file.
+pub struct BackupKey {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: [u8; 16],
+    keys: KeyRing,
+}
+
+impl BackupKey {
+    /// Derives a key for a new backup with a fresh salt.
+    pub fn new(passphrase: &str) -> Result<Self, String> {
+        Self::with_cost(
+            passphrase,
+            crypto::ARGON2_M_COST,
+            crypto::ARGON2_T_COST,
+            crypto::ARGON2_P_COST,
+        )
+    }
+
+    fn with_cost(passphrase: &str, m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Self, String> {
+        if passphrase.is_empty() {
+            return Err("Backup passphrase can't be empty".to_string());
+        }
+        let mut salt = [0u8; 16];
+        getrandom::getrandom(&mut salt).map_err(|e| e.to_string())?;
+        Self::derive(passphrase, m_cost, t_cost, p_cost, salt)
+    }
+
+    fn derive(
+        passphrase: &str,
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+        salt: [u8; 16],
+    ) -> Result<Self, String> {
+        let key = crypto::derive_key(passphrase, &salt, m_cost, t_cost, p_cost)?;
+        Ok(BackupKey {
+            m_cost,
+            t_cost,
+            p_cost,
+            salt,
+            keys: KeyRing::from_bytes(&key[..])?,
+        })
+    }
+
+    /// The backup key as a one-key ring, for re-encrypting sensitive items.
+    pub fn keys(&self) -> &KeyRing {
+        &self.keys
+    }
+
+    fn header(&self, nonce: &[u8; NONCE_LEN]) -> Vec<u8> {
+        let mut header = Vec::with_capacity(FIXED_LEN + NONCE_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.push(KDF_ARGON2ID);
+        header.extend_from_slice(&self.m_cost.to_le_bytes());
+        header.extend_from_slice(&self.t_cost.to_le_bytes());
+        header.extend_from_slice(&self.p_cost.to_le_bytes());
+        header.extend_from_slice(&self.salt);
+        header.extend_from_slice(nonce);
+        header
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        let bytes = self.keys.to_bytes();
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes))
+    }
+
+    /// Starts an encrypted backup on `out`: write the archive into the
+    /// returned writer, then `finish` it.
+    pub fn encrypt<W: Write>(&self, mut out: W) -> Result<EncryptWriter<W>, String> {
+        let mut prefix = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut prefix).map_err(|e| e.to_string())?;
+        let header = self.header(&prefix);
+        out.write_all(&header).map_err(|e| e.to_string())?;
+        let sealer = SealWriter {
+            out,
+            stream: EncryptorBE32::from_aead(self.cipher(), (&prefix).into()),
+            header,
+            buf: Vec::with_capacity(CHUNK_LEN * 2),
+        };
+        Ok(EncryptWriter(DeflateEncoder::new(
+            sealer,
+            Compression::default(),
+        )))
+    }
+
+    /// Reads another copy of the backup this key was derived for (e.g. a
+    /// second pass over the file) without re-running the KDF.
+    pub fn reopen<'a, R: Read + 'a>(&self, mut input: R) -> Result<Box<dyn BufRead + 'a>, String> {
+        let header = read_header(&mut input)?;
+        if header.salt != self.salt
+            || (header.m_cost, header.t_cost, header.p_cost)
+                != (self.m_cost, self.t_cost, self.p_cost)
+        {
+            return Err("Backup file changed while it was being read".to_string());
+        }
+        self.decrypt(header, input)
+    }
+
+    fn decrypt<'a, R: Read + 'a>(
+        &self,
+        header: Header,
+        input: R,
+    ) -> Result<Box<dyn BufRead + 'a>, String> {
+        let opener = OpenReader {
+            input: BufReader::new(input),
+            stream: Some(DecryptorBE32::from_aead(
+                self.cipher(),
+                header.nonce.as_slice().into(),
+            )),
+            header: header.raw,
+            plain: Vec::new(),
+            pos: 0,
+        };
+        Ok(Box::new(BufReader::new(DeflateDecoder::new(opener))))
+    }
+}
+
+/// Parsed container header; `raw` is the authenticated bytes.
+struct Header {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: [u8; 16],
+    nonce: [u8; NONCE_LEN],
+    raw: Vec<u8>,
+}
+
+fn read_header<R: Read>(input: &mut R) -> Result<Header, String> {
+    let not_backup = || "Not an Ortu encrypted backup".to_string();
+    let mut raw = vec![0u8; FIXED_LEN];
+    input.read_exact(&mut raw).map_err(|_| not_backup())?;
+    if !is_encrypted_backup(&raw) {
+        return Err(not_backup());
+    }
+    let u32_at = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+
+    let version = u16::from_le_bytes([raw[8], raw[9]]);
+    if version == 0 || version > VERSION {
+        return Err(format!(
+            "Backup format version {} is newer than this app supports",
+            version
+        ));
+    }
+    if raw[10] != KDF_ARGON2ID {
+        return Err("Backup uses an unknown key derivation".to_string());
+    }
+    let (m_cost, t_cost, p_cost) = (u32_at(11), u32_at(15), u32_at(19));
+    if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+        return Err(format!(
+            "Backup asks for key derivation costs this app won't run \
+             (memory {} KiB, {} passes, {} lanes)",
+            m_cost, t_cost, p_cost
+        ));
+    }
+    let salt: [u8; 16] = raw[23..39].try_into().unwrap();
+    let mut nonce = [0u8; NONCE_LEN];
+    input
+        .read_exact(&mut nonce)
+        .map_err(|_| DAMAGED.to_string())?;
+    raw.extend_from_slice(&nonce);
+    Ok(Header {
+        m_cost,
+        t_cost,
+        p_cost,
+        salt,
+        nonce,
+        raw,
+    })
+}
+
+/// Decrypts an encrypted backup, returning its key (for the sensitive items
+/// inside, and `reopen`) and a reader over the archive. A wrong passphrase
+/// surfaces as a read error on the first chunk.
+pub fn open<'a, R: Read + 'a>(
+    mut input: R,
+    passphrase: &str,
+) -> Result<(BackupKey, Box<dyn BufRead + 'a>), String> {
+    let header = read_header(&mut input)?;
+    let key = BackupKey::derive(
+        passphrase,
+        header.m_cost,
+        header.t_cost,
+        header.p_cost,
+        header.salt,
+    )?;
+    let reader = key.decrypt(header, input)?;
+    Ok((key, reader))
+}
+
+/// Writer returned by `BackupKey::encrypt`.
+pub struct EncryptWriter<W: Write>(DeflateEncoder<SealWriter<W>>);
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> EncryptWriter<W> {
+    /// Seals the final chunk and returns the underlying writer.
+    pub fn finish(self) -> Result<W, String> {
+        let sealer = self.0.finish().map_err(|e| e.to_string())?;
+        sealer.finish().map_err(|e| e.to_string())
+    }
+}
+
+struct SealWriter<W: Write> {
+    out: W,
+    stream: EncryptorBE32<Aes256Gcm>,
+    header: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Write for SealWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        // Always hold something back: the last chunk is sealed by `finish`.
+        while self.buf.len() > CHUNK_LEN {
+            let rest = self.buf.split_off(CHUNK_LEN);
+            let sealed = self
+                .stream
+                .encrypt_next(Payload {
+                    msg: &self.buf,
+                    aad: &self.header,
+                })
+                .map_err(|_| io::Error::other("backup encryption failed"))?;
+            self.out.write_all(&sealed)?;
+            self.buf = rest;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> SealWriter<W> {
+    fn finish(mut self) -> io::Result<W> {
+        let sealed = self
+            .stream
+            .encrypt_last(Payload {
+                msg: &self.buf,
+                aad: &self.header,
+            })
+            .map_err(|_| io::Error::other("backup encryption failed"))?;
+        self.out.write_all(&sealed)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+struct OpenReader<R: BufRead> {
+    input: R,
+    /// None once the last chunk has been opened.
+    stream: Option<DecryptorBE32<Aes256Gcm>>,
+    header: Vec<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> Read for OpenReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            let Some(mut stream) = self.stream.take() else {
+                return Ok(0);
+            };
+            let mut sealed = Vec::with_capacity(CHUNK_LEN + TAG_LEN);
+            (&mut self.input)
+                .take((CHUNK_LEN + TAG_LEN) as u64)
+                .read_to_end(&mut sealed)?;
+            let last = sealed.len() < CHUNK_LEN + TAG_LEN || self.input.fill_buf()?.is_empty();
+            let payload = Payload {
+                msg: &sealed,
+                aad: &self.header,
+            };
+            let opened = if last {
+                stream.decrypt_last(payload)
+            } else {
+                let opened = stream.decrypt_next(payload);
+                self.stream = Some(stream);
+                opened
+            };
+            self.plain = opened.map_err(|_| io::Error::new(io::ErrorKind::InvalidData, DAMAGED))?;
+            self.pos = 0;
+        }
+        let n = out.len().min(self.plain.len() - self.pos);
+        out[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Re-encrypts sensitive items from one key ring to another (machine key →
+/// backup key on export, the reverse on restore). Items `from` can't decrypt
+/// are left unchanged; their ids are returned.
+pub fn rekey_items(items: &mut [ClipboardItem], from: &KeyRing, to: &KeyRing) -> Vec<i64> {
+    let mut failed = Vec::new();
+    for item in items
+        .iter_mut()
+        .filter(|i| i.is_sensitive && crypto::is_encrypted(&i.raw_content))
+    {
+        match crypto::decrypt(from, &item.raw_content).and_then(|plain| crypto::encrypt(to, &plain))
+        {
+            Ok(enc) => item.raw_content = enc,
+            Err(_) => failed.push(item.id),
+        }
+    }
+    failed
+}
+
+/// True if any item carries encrypted content that needs a key ring.
+pub fn has_sensitive(items: &[ClipboardItem]) -> bool {
+    items
+        .iter()
+        .any(|i| i.is_sensitive && crypto::is_encrypted(&i.raw_content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Low-cost KDF so the tests don't spend seconds in Argon2.
+    fn test_key(passphrase: &str) -> BackupKey {
+        BackupKey::with_cost(passphrase, 256, 1, 1).unwrap()
+    }
+
+    fn seal(key: &BackupKey, payload: &[u8]) -> Vec<u8> {
+        let mut writer = key.encrypt(Vec::new()).unwrap();
+        // Uneven writes, as the archive writer's line-by-line output would be.
+        for piece in payload.chunks(7_777) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read_all(mut reader: Box<dyn BufRead + '_>) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+
+    fn item(id: i64, raw: &str, sensitive: bool) -> ClipboardItem {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "content_type": "text",
+            "raw_content": raw,
+            "category": null,
+            "groups": [],
+            "is_permanent": false,
+            "created_at": "2024-01-01 00:00:00",
+            "description": null,
+            "is_manual": false,
+            "is_sensitive": sensitive,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn container_round_trips_and_authenticates_its_header() {
+        let key = test_key("correct horse");
+        // Incompressible enough to span several chunks.
+        let mut payload = Vec::new();
+        let mut x: u32 = 1;
+        while payload.len() < 3 * CHUNK_LEN + 123 {
+            x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            payload.extend_from_slice(format!("{{\"n\":{}}}\n", x).as_bytes());
+        }
+        let file = seal(&key, &payload);
+        assert!(is_encrypted_backup(&file));
+
+        let (reopened, reader) = open(file.as_slice(), "correct horse").unwrap();
+        assert_eq!(read_all(reader).unwrap(), payload);
+        assert_eq!(
+            read_all(reopened.reopen(file.as_slice()).unwrap()).unwrap(),
+            payload
+        );
+        assert!(read_all(open(file.as_slice(), "wrong horse").unwrap().1).is_err());
+
+        // Editing the stored KDF cost (say, to weaken it) breaks every tag.
+        let mut tampered = file.clone();
+        tampered[11] ^= 1;
+        assert!(open(tampered.as_slice(), "correct horse")
+            .and_then(|(_, r)| read_all(r))
+            .is_err());
+        // Dropping the last chunk is caught, not read as a shorter backup.
+        let truncated = &file[..file.len() - 40];
+        assert!(open(truncated, "correct horse")
+            .and_then(|(_, r)| read_all(r))
+            .is_err());
+        assert!(!is_encrypted_backup(br#"{"type":"header"}"#));
+    }
+
+    #[test]
+    fn excessive_kdf_costs_are_refused_before_deriving() {
+        for (m_cost, t_cost, p_cost) in [
+            (MAX_M_COST + 1, 1, 1),
+            (256, MAX_T_COST + 1, 1),
+            (256, 1, MAX_P_COST + 1),
+            (u32::MAX, u32::MAX, u32::MAX),
+        ] {
+            let crafted = BackupKey {
+                m_cost,
+                t_cost,
+                p_cost,
+                ..test_key("pass")
+            };
+            // Only the header is needed: nothing past it may be read first.
+            let file = crafted.header(&[0u8; NONCE_LEN]);
+            let err = open(file.as_slice(), "pass").err().unwrap();
+            assert!(err.contains("key derivation costs"), "{err}");
+        }
+    }
+
+    #[test]
+    fn sensitive_items_move_between_machine_and_backup_keys() {
+        let machine = KeyRing::from_bytes(&[5u8; 32]).unwrap();
+        let other_machine = KeyRing::from_bytes(&[6u8; 32]).unwrap();
+        let backup = test_key("pass");
+
+        let mut items = vec![
+            item(1, &crypto::encrypt(&machine, "s3cret").unwrap(), true),
+            item(2, "plain text", false),
+            item(3, "enc:v2:deadbeef:AAAA", true), // key long gone
+        ];
+        assert!(has_sensitive(&items));
+        assert_eq!(rekey_items(&mut items, &machine, backup.keys()), vec![3]);
+        assert_eq!(items[1].raw_content, "plain text");
+
+        // Restoring elsewhere: backup key → the new machine's key.
+        let file = seal(&backup, b"{}");
+        let (reopened, _) = open(file.as_slice(), "pass").unwrap();
+        assert!(rekey_items(&mut items[..1], reopened.keys(), &other_machine).is_empty());
+        assert_eq!(
+            crypto::decrypt(&other_machine, &items[0].raw_content).unwrap(),
+            "s3cret"
+        );
+    }
+}
@@ -0,0 +1,1063 @@
+// Clipboard text classifier.
+//
+// Pure functions only: no database, no app handle, no files. Auto-grouping
+// runs a declarative ruleset over each copied text; the built-in rules are
+// default_rules.toml, compiled in, and `rules.rs` layers the user's
+// rules.toml on top and keeps it fresh.
+//
+// A rule scores `group` at `confidence` (and each of its `also` groups) when
+// all of its matchers match the trimmed text:
+// - prefix: the first word, lowercased, is one of these; `word*` matches
+//   words starting with `word`
+// - regex: the text matches (Rust `regex` syntax)
+// - keywords: at least `min_keywords` (default 1) of these occur; matched
+//   ignoring ASCII case unless `case_sensitive`
+// - lines: line count bounds (`min`, `max`) and how many lines are indented
+//   (`indented`, `indented_share`)
+// - detector: built-in checks for shapes too fiddly for a regex (the
+//   `looks_like_*` functions below, listed in `DETECTORS`)
+// A group keeps its best score, and the rule that gave it is the reason.
+// `fallback` rules run last, in order, and only while nothing has matched.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, OnceLock};
+
+pub(crate) const DEFAULT_RULES: &str = include_str!("default_rules.toml");
+
+type Detector = fn(&str) -> bool;
+
+/// Named built-in checks a rule can use with `detector`, with what a match
+/// means in a reason.
+const DETECTORS: &[(&str, &str, Detector)] = &[
+    ("url", "looks like a URL", looks_like_url),
+    ("email", "looks like an email address", looks_like_email),
+    ("json", "looks like JSON", looks_like_json),
+    ("xml", "looks like XML", looks_like_xml),
+    ("yaml", "looks like YAML", looks_like_yaml),
+    ("csv", "looks like CSV", looks_like_csv),
+    ("markdown", "looks like Markdown", looks_like_markdown),
+    ("path", "looks like a file path", |t| {
+        looks_like_windows_path(t) || looks_like_unix_path(t)
+    }),
+    ("uuid", "looks like a UUID", looks_like_uuid),
+    (
+        "ip_address",
+        "looks like an IP address",
+        looks_like_ip_address,
+    ),
+    ("jwt", "looks like a JWT", looks_like_jwt),
+    ("base64", "looks like Base64", looks_like_base64),
+    (
+        "phone_number",
+        "looks like a phone number",
+        looks_like_phone_number,
+    ),
+    (
+        "env_var",
+        "looks like environment variables",
+        looks_like_env_var,
+    ),
+    (
+        "secret_key",
+        "looks like a secret key",
+        looks_like_secret_key,
+    ),
+    (
+        "ssh_key",
+        "looks like an SSH key or certificate",
+        looks_like_ssh_key,
+    ),
+    ("code", "looks like code", looks_like_code_snippet),
+    // The language the code is in; at most one of these matches.
+    ("typescript", "reads as TypeScript", |t| {
+        detect_language(t) == Some("TypeScript")
+    }),
+    ("python", "reads as Python", |t| {
+        detect_language(t) == Some("Python")
+    }),
+    ("rust", "reads as Rust", |t| {
+        detect_language(t) == Some("Rust")
+    }),
+    ("go", "reads as Go", |t| detect_language(t) == Some("Go")),
+    ("c_cpp", "reads as C/C++", |t| {
+        detect_language(t) == Some("C/C++")
+    }),
+    ("java", "reads as Java", |t| {
+        detect_language(t) == Some("Java")
+    }),
+    ("javascript", "reads as JavaScript", |t| {
+        detect_language(t) == Some("JavaScript")
+    }),
+];
+
+/// A rules file as written.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    replace_defaults: bool,
+    #[serde(default)]
+    disable: Vec<String>,
+    #[serde(default)]
+    rule: Vec<RuleDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+    name: String,
+    group: String,
+    confidence: f32,
+    #[serde(default)]
+    also: BTreeMap<String, f32>,
+    #[serde(default)]
+    prefix: Vec<String>,
+    regex: Option<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    min_keywords: Option<usize>,
+    #[serde(default)]
+    case_sensitive: bool,
+    lines: Option<LineShape>,
+    #[serde(default)]
+    detector: OneOrMany,
+    #[serde(default)]
+    fallback: bool,
+}
+
+/// `detector = "url"` or `detector = ["code", "rust"]`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for OneOrMany {
+    fn default() -> Self {
+        OneOrMany::Many(Vec::new())
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+struct LineShape {
+    min: Option<usize>,
+    max: Option<usize>,
+    /// At least this many lines start with four spaces or a tab…
+    indented: Option<usize>,
+    /// …and they are at least this share of all lines.
+    indented_share: Option<f32>,
+}
+
+/// A validated rule, ready to run.
+struct Rule {
+    name: String,
+    scores: Vec<(String, f32)>,
+    prefixes: Vec<Prefix>,
+    regex: Option<Regex>,
+    keywords: Vec<String>,
+    min_keywords: usize,
+    case_sensitive: bool,
+    lines: Option<LineShape>,
+    /// What a match means, and the check.
+    detectors: Vec<(&'static str, Detector)>,
+    fallback: bool,
+    /// From the user's file rather than the built-in set.
+    custom: bool,
+}
+
+enum Prefix {
+    Word(String),
+    Start(String),
+}
+
+/// The text a rule looks at, with what several matchers need worked out
+/// once.
+struct Subject<'a> {
+    text: &'a str,
+    lower: String,
+    first_word: String,
+}
+
+pub struct Ruleset {
+    rules: Vec<Rule>,
+}
+
+/// A group a text belongs in, and why.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Score {
+    pub group: String,
+    pub confidence: f32,
+    /// Name of the rule that gave this confidence.
+    pub rule: String,
+    /// What the rule saw, e.g. `first word is "kubectl"`.
+    pub reason: String,
+}
+
+impl Ruleset {
+    /// The built-in rules.
+    pub fn defaults() -> Arc<Ruleset> {
+        static DEFAULTS: OnceLock<Arc<Ruleset>> = OnceLock::new();
+        DEFAULTS
+            .get_or_init(|| {
+                let file: RulesFile =
+                    toml::from_str(DEFAULT_RULES).expect("default_rules.toml parses");
+                Arc::new(Ruleset::build(file, None).expect("default_rules.toml is valid"))
+            })
+            .clone()
+    }
+
+    /// The built-in rules combined with a user rules file.
+    pub fn with_user_rules(text: &str) -> Result<Ruleset, String> {
+        let user: RulesFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let defaults: RulesFile = toml::from_str(DEFAULT_RULES).map_err(|e| e.to_string())?;
+        Ruleset::build(defaults, Some(user))
+    }
+
+    fn build(defaults: RulesFile, user: Option<RulesFile>) -> Result<Ruleset, String> {
+        let mut rules = Vec::new();
+        let mut names = HashSet::new();
+        for (i, def) in defaults.rule.into_iter().enumerate() {
+            if !names.insert(def.name.clone()) {
+                return Err(format!(
+                    "rule {} (\"{}\"): another rule has this name",
+                    i + 1,
+                    def.name
+                ));
+            }
+            rules.push(Rule::compile(def, false).map_err(|e| format!("built-in {}", e))?);
+        }
+        let Some(user) = user else {
+            return Ok(Ruleset { rules });
+        };
+
+        if user.replace_defaults {
+            rules.clear();
+        }
+        for name in &user.disable {
+            if !user.replace_defaults && !rules.iter().any(|r| &r.name == name) {
+                return Err(format!("disable: there is no built-in rule \"{}\"", name));
+            }
+        }
+        rules.retain(|r| !user.disable.contains(&r.name));
+
+        let mut user_names = HashSet::new();
+        for (i, def) in user.rule.into_iter().enumerate() {
+            if !user_names.insert(def.name.clone()) {
+                return Err(format!(
+                    "rule {} (\"{}\"): another rule has this name",
+                    i + 1,
+                    def.name
+                ));
+            }
+            let rule = Rule::compile(def, true).map_err(|e| format!("rule {} {}", i + 1, e))?;
+            match rules.iter_mut().find(|r| r.name == rule.name) {
+                Some(existing) => *existing = rule,
+                None => rules.push(rule),
+            }
+        }
+        Ok(Ruleset { rules })
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// How many of the rules come from the user's file.
+    pub fn custom(&self) -> usize {
+        self.rules.iter().filter(|r| r.custom).count()
+    }
+
+    /// Scores clipboard text: one entry per matching group with its best
+    /// confidence and the rule that gave it, best first.
+    pub fn classify(&self, text: &str) -> Vec<Score> {
+        let text = text.trim();
+        let subject = Subject {
+            text,
+            lower: text.to_ascii_lowercase(),
+            first_word: first_token_lowercase(text),
+        };
+        let mut scores: Vec<Score> = Vec::new();
+        let (fallbacks, rules): (Vec<&Rule>, Vec<&Rule>) =
+            self.rules.iter().partition(|r| r.fallback);
+        for rule in rules {
+            if let Some(reason) = rule.check(&subject) {
+                rule.score(&reason, &mut scores);
+            }
+        }
+        for rule in fallbacks {
+            if scores.is_empty() {
+                if let Some(reason) = rule.check(&subject) {
+                    rule.score(&reason, &mut scores);
+                }
+            }
+        }
+        scores.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| a.group.cmp(&b.group))
+        });
+        scores
+    }
+}
+
+impl Rule {
+    /// Validates a rule as written; errors read `("name"): what's wrong`.
+    fn compile(def: RuleDef, custom: bool) -> Result<Rule, String> {
+        let fail = |msg: String| format!("(\"{}\"): {}", def.name, msg);
+        if def.name.trim().is_empty() {
+            return Err("(unnamed): every rule needs a name".to_string());
+        }
+        let mut scores = vec![(def.group.clone(), def.confidence)];
+        scores.extend(def.also.iter().map(|(g, c)| (g.clone(), *c)));
+        for (group, confidence) in &scores {
+            if group.trim().is_empty() {
+                return Err(fail("group names can't be empty".to_string()));
+            }
+            if !(*confidence > 0.0 && *confidence <= 1.0) {
+                return Err(fail(format!(
+                    "confidence for \"{}\" must be above 0 and at most 1, not {}",
+                    group, confidence
+                )));
+            }
+        }
+
+        let prefixes = def
+            .prefix
+            .iter()
+            .map(|p| {
+                let p = p.trim().to_ascii_lowercase();
+                match p.strip_suffix('*') {
+                    Some(start) if !start.is_empty() => Ok(Prefix::Start(start.to_string())),
+                    _ if p.is_empty() || p.contains('*') => {
+                        Err(fail(format!("invalid prefix \"{}\"", p)))
+                    }
+                    _ => Ok(Prefix::Word(p)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let regex = def
+            .regex
+            .as_deref()
+            .map(|r| Regex::new(r).map_err(|e| fail(format!("invalid regex: {}", e))))
+            .transpose()?;
+
+        if def.keywords.iter().any(|k| k.is_empty()) {
+            return Err(fail("keywords can't be empty".to_string()));
+        }
+        let min_keywords = def.min_keywords.unwrap_or(1);
+        if !def.keywords.is_empty() && !(1..=def.keywords.len()).contains(&min_keywords) {
+            return Err(fail(format!(
+                "min_keywords must be between 1 and the number of keywords ({})",
+                def.keywords.len()
+            )));
+        }
+        if def.keywords.is_empty() && def.min_keywords.is_some() {
+            return Err(fail("min_keywords without keywords".to_string()));
+        }
+        let keywords = def
+            .keywords
+            .iter()
+            .map(|k| {
+                if def.case_sensitive {
+                    k.clone()
+                } else {
+                    k.to_ascii_lowercase()
+                }
+            })
+            .collect();
+
+        if let Some(shape) = def.lines {
+            if let (Some(min), Some(max)) = (shape.min, shape.max) {
+                if min > max {
+                    return Err(fail("lines.min is above lines.max".to_string()));
+                }
+            }
+            if shape
+                .indented_share
+                .is_some_and(|s| !(0.0..=1.0).contains(&s))
+            {
+                return Err(fail(
+                    "lines.indented_share must be between 0 and 1".to_string(),
+                ));
+            }
+        }
+
+        let detector_names = match &def.detector {
+            OneOrMany::One(name) => std::slice::from_ref(name),
+            OneOrMany::Many(names) => names.as_slice(),
+        };
+        let detectors = detector_names
+            .iter()
+            .map(|name| {
+                DETECTORS
+                    .iter()
+                    .find(|(n, _, _)| n == name)
+                    .map(|(_, description, f)| (*description, *f))
+                    .ok_or_else(|| {
+                        let known: Vec<&str> = DETECTORS.iter().map(|(n, _, _)| *n).collect();
+                        fail(format!(
+                            "unknown detector \"{}\" (known: {})",
+                            name,
+                            known.join(", ")
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let has_matcher = !prefixes.is_empty()
+            || regex.is_some()
+            || !def.keywords.is_empty()
+            || def.lines.is_some()
+            || !detectors.is_empty();
+        if !has_matcher && !def.fallback {
+            return Err(fail(
+                "needs at least one of prefix, regex, keywords, lines or detector".to_string(),
+            ));
+        }
+
+        Ok(Rule {
+            name: def.name,
+            scores,
+            prefixes,
+            regex,
+            keywords,
+            min_keywords,
+            case_sensitive: def.case_sensitive,
+            lines: def.lines,
+            detectors,
+            fallback: def.fallback,
+            custom,
+        })
+    }
+
+    /// Whether the rule matches, as a description of what each of its
+    /// matchers saw.
+    fn check(&self, s: &Subject<'_>) -> Option<String> {
+        let mut seen = Vec::new();
+        if !self.prefixes.is_empty() {
+            if !self.prefixes.iter().any(|p| match p {
+                Prefix::Word(word) => s.first_word == *word,
+                Prefix::Start(start) => s.first_word.starts_with(start.as_str()),
+            }) {
+                return None;
+            }
+            seen.push(format!("first word is \"{}\"", s.first_word));
+        }
+        if let Some(re) = &self.regex {
+            if !re.is_match(s.text) {
+                return None;
+            }
+            seen.push(format!("matches '{}'", re.as_str()));
+        }
+        if !self.keywords.is_empty() {
+            let haystack = if self.case_sensitive {
+                s.text
+            } else {
+                s.lower.as_str()
+            };
+            let found: Vec<&str> = self
+                .keywords
+                .iter()
+                .filter(|k| haystack.contains(k.as_str()))
+                .map(|k| k.trim())
+                .collect();
+            if found.len() < self.min_keywords {
+                return None;
+            }
+            let quoted: Vec<String> = found.iter().map(|k| format!("\"{}\"", k)).collect();
+            seen.push(format!("contains {}", quoted.join(", ")));
+        }
+        if let Some(shape) = self.lines {
+            seen.push(shape.check(s.text)?);
+        }
+        for (description, detect) in &self.detectors {
+            if !detect(s.text) {
+                return None;
+            }
+            seen.push(description.to_string());
+        }
+        if seen.is_empty() {
+            // A fallback without matchers.
+            seen.push("nothing more specific matched".to_string());
+        }
+        Some(seen.join("; "))
+    }
+
+    fn score(&self, reason: &str, scores: &mut Vec<Score>) {
+        for (group, confidence) in &self.scores {
+            match scores.iter_mut().find(|s| s.group == *group) {
+                Some(best) if best.confidence >= *confidence => {}
+                Some(best) => {
+                    best.confidence = *confidence;
+                    best.rule = self.name.clone();
+                    best.reason = reason.to_string();
+                }
+                None => scores.push(Score {
+                    group: group.clone(),
+                    confidence: *confidence,
+                    rule: self.name.clone(),
+                    reason: reason.to_string(),
+                }),
+            }
+        }
+    }
+}
+
+impl LineShape {
+    /// Whether the text has this shape, as its line counts.
+    fn check(&self, text: &str) -> Option<String> {
+        let count = text.lines().count();
+        if self.min.is_some_and(|min| count < min) || self.max.is_some_and(|max| count > max) {
+            return None;
+        }
+        let lines = if count == 1 {
+            "1 line".to_string()
+        } else {
+            format!("{} lines", count)
+        };
+        if self.indented.is_none() && self.indented_share.is_none() {
+            return Some(lines);
+        }
+        let indented = text
+            .lines()
+            .filter(|l| l.starts_with("    ") || l.starts_with('\t'))
+            .count();
+        let shaped = self.indented.is_none_or(|n| indented >= n)
+            && self
+                .indented_share
+                .is_none_or(|share| indented as f32 >= share * count as f32);
+        shaped.then(|| format!("{}, {} indented", lines, indented))
+    }
+}
+
+/// The language `text` is written in, when it looks like code (for tagging
+/// Markdown exports).
+pub(crate) fn code_language(text: &str) -> Option<&'static str> {
+    let text = text.trim();
+    if looks_like_code_snippet(text) {
+        detect_language(text)
+    } else {
+        None
+    }
+}
+
+fn first_token_lowercase(text: &str) -> String {
+    text.split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '(' | '['))
+        .to_ascii_lowercase()
+}
+
+// ── Structural / format detectors ──────────────────────────────────────────────
+
+fn looks_like_url(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    lower.contains("http://") || lower.contains("https://") || lower.contains("ftp://")
+}
+
+fn looks_like_email(text: &str) -> bool {
+    text.split_whitespace().any(|token| {
+        let trimmed = token.trim_matches(|c: char| ",;:()[]{}<>\"'".contains(c));
+        let mut parts = trimmed.split('@');
+        let local = parts.next().unwrap_or_default();
+        let domain = parts.next().unwrap_or_default();
+        parts.next().is_none()
+            && !local.is_empty()
+            && domain.len() >= 3
+            && domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.')
+    })
+}
+
+fn looks_like_json(text: &str) -> bool {
+    let trimmed = text.trim();
+    (trimmed.starts_with('{') && trimmed.ends_with('}'))
+        || (trimmed.starts_with('[') && trimmed.ends_with(']'))
+}
+
+fn looks_like_xml(text: &str) -> bool {
+    let trimmed = text.trim_start();
+    // Require a closing tag to reduce false positives from HTML fragments
+    trimmed.starts_with("<?xml")
+        || (trimmed.starts_with('<') && trimmed.contains("</") && trimmed.contains('>'))
+}
+
+fn looks_like_yaml(text: &str) -> bool {
+    if text.len() < 10 {
+        return false;
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() < 3 {
+        return false;
+    }
+    let kv_lines = lines
+        .iter()
+        .filter(|l| {
+            let s = l.trim();
+            !s.is_empty()
+                && !s.starts_with('#')
+                && (s.contains(": ") || s.ends_with(':') || s.starts_with("- "))
+        })
+        .count();
+    let has_structure = text.contains("---") || text.contains("- ") || text.contains("  ");
+    kv_lines >= 3 && has_structure && kv_lines * 10 >= lines.len() * 5
+}
+
+fn looks_like_csv(text: &str) -> bool {
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() < 2 {
+        return false;
+    }
+    let sample = &lines[..lines.len().min(5)];
+    let comma_counts: Vec<usize> = sample.iter().map(|l| l.matches(',').count()).collect();
+    if comma_counts[0] < 2 {
+        return false;
+    }
+    let consistent = comma_counts
+        .iter()
+        .filter(|&&c| c == comma_counts[0])
+        .count();
+    consistent * 2 >= sample.len()
+}
+
+fn looks_like_markdown(text: &str) -> bool {
+    let signals = [
+        text.contains("\n# ") || text.starts_with("# "),
+        text.contains("\n## ") || text.starts_with("## "),
+        text.contains("\n- ") || text.contains("\n* "),
+        text.contains("**") || text.contains("__"),
+        text.contains("```"),
+        text.contains("]("),
+    ];
+    signals.iter().filter(|&&x| x).count() >= 2
+}
+
+fn looks_like_windows_path(text: &str) -> bool {
+    let trimmed = text.trim();
+    let bytes = trimmed.as_bytes();
+    bytes.len() > 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/')
+}
+
+fn looks_like_unix_path(text: &str) -> bool {
+    let trimmed = text.trim();
+    (trimmed.starts_with('/') && trimmed.len() > 1)
+        || trimmed.starts_with("~/")
+        || trimmed.starts_with("../")
+        || trimmed.starts_with("./")
+}
+
+fn looks_like_uuid(text: &str) -> bool {
+    let trimmed = text.trim();
+    if trimmed.len() != 36 {
+        return false;
+    }
+    let parts: Vec<&str> = trimmed.split('-').collect();
+    parts.len() == 5
+        && [8usize, 4, 4, 4, 12]
+            .iter()
+            .zip(&parts)
+            .all(|(&len, part)| part.len() == len && part.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn looks_like_ip_address(text: &str) -> bool {
+    // Handles plain IPs and CIDR notation (e.g. 192.168.1.0/24)
+    let trimmed = text.trim();
+    let addr = trimmed.split('/').next().unwrap_or(trimmed);
+    let parts: Vec<&str> = addr.split('.').collect();
+    parts.len() == 4 && parts.iter().all(|p| p.parse::<u8>().is_ok())
+}
+
+fn looks_like_jwt(text: &str) -> bool {
+    let trimmed = text.trim();
+    // JWTs always start with "eyJ" (base64 of '{"')
+    if !trimmed.starts_with("eyJ") {
+        return false;
+    }
+    let parts: Vec<&str> = trimmed.split('.').collect();
+    parts.len() == 3
+        && parts.iter().all(|p| {
+            p.len() >= 8
+                && p.chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '=')
+        })
+}
+
+fn looks_like_base64(text: &str) -> bool {
+    let trimmed = text.trim();
+    if trimmed.len() < 40 || trimmed.contains(' ') || trimmed.contains('\n') {
+        return false;
+    }
+    // Pure hex strings (git hashes, etc.) are not base64
+    if trimmed.chars().all(|c| c.is_ascii_hexdigit()) {
+        return false;
+    }
+    let stripped = trimmed.trim_end_matches('=');
+    stripped.len() >= 32
+        && stripped
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '+' || c == '/' || c == '-' || c == '_')
+}
+
+fn looks_like_phone_number(text: &str) -> bool {
+    let trimmed = text.trim();
+    if trimmed.len() < 7 || trimmed.len() > 20 {
+        return false;
+    }
+    let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 7 || digits.len() > 15 {
+        return false;
+    }
+    // IP addresses have 4 octet groups — skip them
+    if looks_like_ip_address(trimmed) {
+        return false;
+    }
+    trimmed
+        .chars()
+        .all(|c| c.is_ascii_digit() || " -.+()/".contains(c))
+        && (trimmed.starts_with('+')
+            || trimmed.starts_with('(')
+            || trimmed
+                .chars()
+                .next()
+                .map(|c| c.is_ascii_digit())
+                .unwrap_or(false))
+}
+
+fn looks_like_env_var(text: &str) -> bool {
+    // Matches: KEY=value  or  export KEY=value  (UPPER_SNAKE_CASE keys)
+    let trimmed = text.trim();
+    let content = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+    if let Some(eq_pos) = content.find('=') {
+        let key = &content[..eq_pos];
+        !key.is_empty()
+            && key.len() >= 2
+            && key
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c == '_' || c.is_ascii_digit())
+            && key
+                .chars()
+                .next()
+                .map(|c| c.is_ascii_uppercase())
+                .unwrap_or(false)
+    } else {
+        false
+    }
+}
+
+fn looks_like_secret_key(text: &str) -> bool {
+    let t = text.trim();
+    t.starts_with("sk-")           // OpenAI / Anthropic
+        || t.starts_with("sk-ant-")
+        || t.starts_with("gh_")    // GitHub fine-grained PAT
+        || t.starts_with("ghp_")   // GitHub personal access token
+        || t.starts_with("ghs_")
+        || t.starts_with("gho_")
+        || t.starts_with("AKIA")   // AWS Access Key ID
+        || t.starts_with("ASIA")   // AWS temporary credentials
+        || t.starts_with("AIza")   // Google API key
+        || t.starts_with("xoxb-")  // Slack bot token
+        || t.starts_with("xoxp-")  // Slack user token
+        || (t.starts_with("Bearer ") && t.len() > 20)
+}
+
+fn looks_like_ssh_key(text: &str) -> bool {
+    text.starts_with("ssh-rsa ")
+        || text.starts_with("ssh-ed25519 ")
+        || text.starts_with("ssh-ecdsa ")
+        || text.starts_with("ecdsa-sha2-")
+        || (text.starts_with("-----BEGIN") && text.contains("PRIVATE KEY-----"))
+        || (text.starts_with("-----BEGIN") && text.contains("PUBLIC KEY-----"))
+        || (text.starts_with("-----BEGIN") && text.contains("CERTIFICATE-----"))
+}
+
+// ── Code detection ─────────────────────────────────────────────────────────────
+
+fn looks_like_code_snippet(text: &str) -> bool {
+    if text.contains("```") {
+        return true;
+    }
+    if text.contains("function ") || text.contains("class ") || text.contains("def ") {
+        return true;
+    }
+    if text.contains("#include") || text.contains("using namespace") {
+        return true;
+    }
+    // Arrow functions (JS/TS)
+    if text.contains("=>") && (text.contains("const ") || text.contains("let ")) {
+        return true;
+    }
+    // Rust function body
+    if text.contains("fn ") && text.contains('{') && text.contains('}') {
+        return true;
+    }
+    // Java / C# method or field
+    if (text.contains("public ") || text.contains("private ") || text.contains("protected "))
+        && (text.contains("void ") || text.contains("static "))
+    {
+        return true;
+    }
+    // ES module imports
+    if text.contains("import ")
+        && (text.contains(" from '") || text.contains(" from \"") || text.contains("import {"))
+    {
+        return true;
+    }
+    false
+}
+
+fn detect_language(text: &str) -> Option<&'static str> {
+    // Order matters: check more specific patterns first
+    if text.contains(": string")
+        || text.contains(": number")
+        || text.contains(": boolean")
+        || text.contains("interface ")
+        || text.contains(": void")
+        || text.contains("<T>")
+    {
+        return Some("TypeScript");
+    }
+    if (text.contains("def ") && text.contains(':'))
+        || text.contains("self.")
+        || (text.contains("import ") && text.contains("from "))
+        || text.to_ascii_lowercase().contains("print(")
+    {
+        return Some("Python");
+    }
+    if (text.contains("fn ") || text.contains("pub fn") || text.contains("let mut"))
+        && (text.contains("->") || text.contains("impl ") || text.contains("use "))
+    {
+        return Some("Rust");
+    }
+    if text.contains("package ") && text.contains("func ") {
+        return Some("Go");
+    }
+    if text.contains("#include") || text.contains("std::") || text.contains("int main") {
+        return Some("C/C++");
+    }
+    if text.contains("public class") || text.contains("@Override") || text.contains("System.out") {
+        return Some("Java");
+    }
+    if (text.contains("const ") || text.contains("let ") || text.contains("var "))
+        && (text.contains("=>") || text.contains("===") || text.contains("!=="))
+    {
+        return Some("JavaScript");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups(ruleset: &Ruleset, text: &str) -> Vec<String> {
+        let mut groups: Vec<String> = ruleset
+            .classify(text)
+            .into_iter()
+            .map(|s| s.group)
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    #[test]
+    fn built_in_rules_load_and_classify() {
+        let rules = Ruleset::defaults();
+        assert_eq!(groups(&rules, "docker compose up -d"), ["DevOps", "Docker"]);
+        assert_eq!(groups(&rules, "go build ./..."), ["Package Management"]);
+        assert_eq!(groups(&rules, "Get-ChildItem"), ["Shell / OS"]);
+        let sql = &rules.classify("SELECT id FROM users WHERE name = 'x' ORDER BY id")[0];
+        assert_eq!((sql.group.as_str(), sql.confidence), ("Database", 0.92));
+        assert_eq!(sql.rule, "sql");
+        assert_eq!(
+            sql.reason,
+            r#"contains "select", "from", "where", "order by""#
+        );
+        assert_eq!(
+            rules.classify("  lunch at noon?\n"),
+            [Score {
+                group: "Text".to_string(),
+                confidence: 0.4,
+                rule: "text".to_string(),
+                reason: "nothing more specific matched".to_string(),
+            }]
+        );
+        assert_eq!(
+            groups(&rules, "QUJDREVGR0hJSktMTU5PUFFSU1RVVldYWVphYmNkZWZnaA=="),
+            ["Base64"]
+        );
+        assert_eq!(
+            groups(&rules, "fn main() -> i32 {\n    let mut x = 1;\n    x\n}"),
+            ["Code Snippet", "Rust"]
+        );
+    }
+
+    #[test]
+    fn user_rules_add_replace_and_disable() {
+        let rules = Ruleset::with_user_rules(
+            r#"
+            disable = ["phone-number"]
+
+            [[rule]]
+            name = "ticket-id"
+            group = "Tickets"
+            confidence = 0.95
+            regex = '^[A-Z]{2,10}-\d+$'
+
+            [[rule]]
+            name = "docker-command"
+            group = "Containers"
+            confidence = 0.9
+            prefix = ["docker", "podman*"]
+
+            [[rule]]
+            name = "incident"
+            group = "Incidents"
+            confidence = 0.8
+            keywords = ["outage", "sev1", "pager"]
+            min_keywords = 2
+            lines = { max = 1 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(rules.custom(), 3);
+        assert_eq!(groups(&rules, "OPS-1234"), ["Tickets"]);
+        assert_eq!(groups(&rules, "podman-remote ps"), ["Containers"]);
+        assert_eq!(groups(&rules, "docker ps"), ["Containers"]);
+        assert_eq!(groups(&rules, "+1 555 123 4567"), ["Text"]);
+        assert_eq!(groups(&rules, "SEV1 outage, check pager"), ["Incidents"]);
+        assert_eq!(groups(&rules, "SEV1 outage\ncheck pager"), ["Text"]);
+
+        let only = Ruleset::with_user_rules(
+            "replace_defaults = true\n[[rule]]\nname = \"all\"\ngroup = \"Inbox\"\nconfidence = 0.5\nfallback = true\n",
+        )
+        .unwrap();
+        assert_eq!(only.rule_count(), 1);
+        assert_eq!(groups(&only, "docker ps"), ["Inbox"]);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected_with_a_reason() {
+        let error = |text: &str| Ruleset::with_user_rules(text).err().unwrap();
+        let rule = |body: &str| format!("[[rule]]\nname = \"x\"\ngroup = \"X\"\n{body}\n");
+        assert!(error(&rule("confidence = 1.5\nprefix = [\"x\"]")).contains("at most 1"));
+        assert!(error(&rule("confidence = 0.5\nregex = '('")).contains("invalid regex"));
+        assert!(error(&rule("confidence = 0.5\ndetector = \"nope\""))
+            .contains("unknown detector \"nope\""));
+        assert!(error(&rule("confidence = 0.5")).contains("needs at least one"));
+        assert!(error(&rule(
+            "confidence = 0.5\nkeywords = [\"a\"]\nmin_keywords = 2"
+        ))
+        .contains("min_keywords"));
+        assert!(error(&rule("confidence = 0.5\nprefx = [\"x\"]")).contains("prefx"));
+        assert!(error("disable = [\"nope\"]").contains("no built-in rule \"nope\""));
+        assert!(error("[[rule]]\nname = \"x\"\n").contains("missing field"));
+    }
+
+    // The golden corpus: `=== label`, the text, then one `=> ` line per
+    // score. After an intended change, rewrite it with
+    // `ORTU_BLESS=1 cargo test golden` and review the diff.
+    const GOLDEN: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/classifier.golden"
+    );
+
+    struct Case {
+        label: String,
+        text: String,
+        expected: Vec<String>,
+    }
+
+    fn read_golden(file: &str) -> (String, Vec<Case>) {
+        let start = file.find("\n=== ").expect("golden file has cases") + 1;
+        let mut cases: Vec<Case> = Vec::new();
+        for line in file[start..].lines() {
+            if let Some(label) = line.strip_prefix("=== ") {
+                cases.push(Case {
+                    label: label.to_string(),
+                    text: String::new(),
+                    expected: Vec::new(),
+                });
+            } else if let Some(case) = cases.last_mut() {
+                match line.strip_prefix("=> ") {
+                    Some(score) => case.expected.push(score.to_string()),
+                    None if case.expected.is_empty() => {
+                        case.text.push_str(line);
+                        case.text.push('\n');
+                    }
+                    None => assert!(line.is_empty(), "text after scores in \"{}\"", case.label),
+                }
+            }
+        }
+        (file[..start].to_string(), cases)
+    }
+
+    fn describe(score: &Score) -> String {
+        format!(
+            "{:.2} {} ({}: {})",
+            score.confidence, score.group, score.rule, score.reason
+        )
+    }
+
+    #[test]
+    fn golden_corpus() {
+        let file = std::fs::read_to_string(GOLDEN).unwrap();
+        let (header, cases) = read_golden(&file);
+        let rules = Ruleset::defaults();
+        let actual: Vec<Vec<String>> = cases
+            .iter()
+            .map(|c| rules.classify(&c.text).iter().map(describe).collect())
+            .collect();
+
+        if std::env::var_os("ORTU_BLESS").is_some() {
+            let mut out = header;
+            for (case, scores) in cases.iter().zip(&actual) {
+                out.push_str(&format!("=== {}\n{}", case.label, case.text.trim_end()));
+                for score in scores {
+                    out.push_str(&format!("\n=> {}", score));
+                }
+                out.push_str("\n\n");
+            }
+            std::fs::write(GOLDEN, out.trim_end().to_string() + "\n").unwrap();
+            return;
+        }
+        let wrong: Vec<String> = cases
+            .iter()
+            .zip(&actual)
+            .filter(|(case, scores)| case.expected != **scores)
+            .map(|(case, scores)| {
+                format!(
+                    "=== {}\n  expected: {:#?}\n  got: {:#?}",
+                    case.label, case.expected, scores
+                )
+            })
+            .collect();
+        assert!(wrong.is_empty(), "{}", wrong.join("\n"));
+
+        // Every detector has a case it recognises.
+        let detectors = DETECTORS
+            .iter()
+            .map(|(name, _, detect)| (*name, *detect))
+            .chain([
+                ("windows_path", looks_like_windows_path as Detector),
+                ("unix_path", looks_like_unix_path),
+            ]);
+        for (name, detect) in detectors {
+            assert!(
+                cases.iter().any(|c| detect(c.text.trim())),
+                "no golden case for the {} detector",
+                name
+            );
+        }
+    }
+}
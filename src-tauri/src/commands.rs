@@ -1,10 +1,17 @@
-use crate::db::{ClipboardDB, ClipboardItem, Snippet};
+use crate::db::{
+    ClipboardDB, ClipboardItem, HistoryCursor, HistoryPage, RestoreReport, SecretDetection, Snippet,
+};
+use crate::query::{self, QueryError};
 #[cfg(target_os = "macos")]
 use crate::PopupPasteTarget;
 use crate::PasteStack;
 use base64::Engine as _;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tauri::{AppHandle, Emitter, Manager};
 
 #[cfg(target_os = "macos")]
@@ -45,10 +52,55 @@ fn validate_path(path_str: &str) -> Result<PathBuf, String> {
     Ok(path)
 }
 
+/// History filters accept one capture source: "clipboard" or "primary" (the
+/// Linux middle-click selection).
+fn validate_selection(selection: Option<&str>) -> Result<(), String> {
+    match selection {
+        None | Some("clipboard") | Some("primary") => Ok(()),
+        Some(other) => Err(format!("Unknown selection '{}'", other)),
+    }
+}
+
+#[tauri::command]
+pub fn get_history(
+    app: AppHandle,
+    search: Option<String>,
+    selection: Option<String>,
+) -> Result<Vec<ClipboardItem>, String> {
+    validate_selection(selection.as_deref())?;
+    let query = query::parse(search.as_deref().unwrap_or("")).map_err(|e| e.to_string())?;
+    let db = app.state::<ClipboardDB>();
+    db.get_history(&query, selection).map_err(|e| e.to_string())
+}
+
+/// One page of history for infinite scroll. Pass the previous page's
+/// `next_cursor` to continue; `page_size` defaults to 100 (max 500).
 #[tauri::command]
-pub fn get_history(app: AppHandle, search: Option<String>) -> Result<Vec<ClipboardItem>, String> {
+pub fn get_history_page(
+    app: AppHandle,
+    search: Option<String>,
+    selection: Option<String>,
+    cursor: Option<HistoryCursor>,
+    page_size: Option<usize>,
+) -> Result<HistoryPage, String> {
+    validate_selection(selection.as_deref())?;
+    let query = query::parse(search.as_deref().unwrap_or("")).map_err(|e| e.to_string())?;
+    let page_size = page_size.unwrap_or(100).clamp(1, 500);
     let db = app.state::<ClipboardDB>();
-    db.get_history(search).map_err(|e| e.to_string())
+    db.get_history_page(&query, selection, cursor, page_size)
+        .map_err(|e| e.to_string())
+}
+
+/// Validates a search string without running it, so the UI can underline the
+/// offending term (`start`..`end` are character offsets). With `regex`, the
+/// whole string is checked as one pattern (the search box's regex mode).
+#[tauri::command]
+pub fn check_search_query(search: String, regex: Option<bool>) -> Result<(), QueryError> {
+    if regex.unwrap_or(false) {
+        query::parse_regex(&search).map(|_| ())
+    } else {
+        query::parse(&search).map(|_| ())
+    }
 }
 
 #[tauri::command]
@@ -102,41 +154,643 @@ pub fn rename_group(app: AppHandle, old_name: String, new_name: String) -> Resul
         .map_err(|e| e.to_string())
 }
 
+/// Exports group `name` as text, JSON Lines, CSV or Markdown (see
+/// exports.rs); without `format`, the file extension decides.
 #[tauri::command]
-pub async fn export_group(app: AppHandle, name: String, path: String) -> Result<(), String> {
+pub async fn export_group(
+    app: AppHandle,
+    name: String,
+    path: String,
+    format: Option<String>,
+) -> Result<(), String> {
     let validated_path = validate_path(&path)?;
-    let db = app.state::<ClipboardDB>();
-    db.export_group(name, validated_path)
-        .map_err(|e| e.to_string())
+    let format = crate::exports::Format::pick(format.as_deref(), &validated_path)?;
+    write_export(&app, &validated_path, format, Some(&name))
 }
 
+/// Imports an export of any format into group `name`, merged into history
+/// the way `import_history` does.
 #[tauri::command]
 pub async fn import_group(app: AppHandle, name: String, path: String) -> Result<(), String> {
     let validated_path = validate_path(&path)?;
+    let mut imported = crate::importers::read(&validated_path, Some(crate::importers::Source::Ortu))?;
+    imported.add_to_group(&name);
+    write_imported(&app, imported).map(|_| ())
+}
+
+fn write_export(
+    app: &AppHandle,
+    path: &Path,
+    format: crate::exports::Format,
+    group: Option<&str>,
+) -> Result<(), String> {
     let db = app.state::<ClipboardDB>();
-    db.import_group(name, validated_path)
-        .map_err(|e| e.to_string())
+    let groups: Vec<String> = group.map(str::to_string).into_iter().collect();
+    let (mut items, mut after_id) = (Vec::new(), 0);
+    loop {
+        let batch = db
+            .get_backup_items(&groups, after_id, BACKUP_BATCH)
+            .map_err(|e| e.to_string())?;
+        let Some(last) = batch.last() else { break };
+        after_id = last.id;
+        items.extend(batch);
+    }
+    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    let mut out = std::io::BufWriter::new(file);
+    crate::exports::write(&mut out, format, group, &items)?;
+    std::io::Write::flush(&mut out).map_err(|e| e.to_string())
+}
+
+/// What `import_history` would bring in from another clipboard manager.
+#[derive(serde::Serialize)]
+pub struct ImportPreview {
+    pub source: crate::importers::Source,
+    /// Importable entries, and entries left out (images and the like).
+    pub total: u64,
+    pub skipped: u64,
+    /// The newest entries, with sensitive content blanked.
+    pub sample: Vec<ClipboardItem>,
+    /// How they compare with the current history (merge mode).
+    pub report: RestoreReport,
+}
+
+const IMPORT_SAMPLE: usize = 20;
+
+/// "copyq", "maccy", … or None / "auto" to detect the format.
+fn import_source(source: Option<String>) -> Result<Option<crate::importers::Source>, String> {
+    source
+        .filter(|s| !s.is_empty() && s != "auto")
+        .map(|s| {
+            serde_json::from_value(serde_json::Value::String(s.clone()))
+                .map_err(|_| format!("Unknown import source: {}", s))
+        })
+        .transpose()
 }
 
+#[tauri::command]
+pub async fn preview_import(
+    app: AppHandle,
+    path: String,
+    source: Option<String>,
+) -> Result<ImportPreview, String> {
+    let validated_path = validate_path(&path)?;
+    let imported = crate::importers::read(&validated_path, import_source(source)?)?;
+    let db = app.state::<ClipboardDB>();
+    let mut report = RestoreReport::default();
+    db.preview_restore_groups(&imported.groups, "merge", &mut report)
+        .map_err(|e| e.to_string())?;
+    for batch in imported.items.chunks(BACKUP_BATCH) {
+        db.preview_restore_items(batch, "merge", &mut report)
+            .map_err(|e| e.to_string())?;
+    }
+    let sample = imported
+        .items
+        .iter()
+        .rev()
+        .take(IMPORT_SAMPLE)
+        .map(|item| ClipboardItem {
+            raw_content: if item.is_sensitive { String::new() } else { item.raw_content.clone() },
+            rich_content: None,
+            ..item.clone()
+        })
+        .collect();
+    Ok(ImportPreview {
+        source: imported.source,
+        total: imported.items.len() as u64,
+        skipped: imported.skipped,
+        sample,
+        report,
+    })
+}
+
+/// Imports another clipboard manager's history (see `importers`), merged into
+/// the current history the way a backup is. Returns how many entries were
+/// read.
+#[tauri::command]
+pub async fn import_history(app: AppHandle, path: String, source: Option<String>) -> Result<u64, String> {
+    let validated_path = validate_path(&path)?;
+    let imported = crate::importers::read(&validated_path, import_source(source)?)?;
+    write_imported(&app, imported)
+}
+
+/// Merges imported items into history in one transaction, encrypting
+/// sensitive ones that arrive in plain text. Returns how many items were read.
+fn write_imported(app: &AppHandle, mut imported: crate::importers::Imported) -> Result<u64, String> {
+    let is_plain_secret =
+        |item: &ClipboardItem| item.is_sensitive && !crate::crypto::is_encrypted(&item.raw_content);
+    if imported.items.iter().any(is_plain_secret) {
+        // Fails with VAULT_LOCKED before anything is written.
+        let key = crate::crypto::get_or_create_key(app)?;
+        for item in imported.items.iter_mut().filter(|item| is_plain_secret(item)) {
+            item.raw_content = crate::crypto::encrypt(&key, &item.raw_content)?;
+        }
+    }
+    let db = app.state::<ClipboardDB>();
+    let session = db.begin_restore().map_err(|e| e.to_string())?;
+    session
+        .groups(&imported.groups, "merge")
+        .map_err(|e| e.to_string())?;
+    for batch in imported.items.chunks(BACKUP_BATCH) {
+        session
+            .items(batch.to_vec(), "merge")
+            .map_err(|e| e.to_string())?;
+    }
+    session.commit("merge").map_err(|e| e.to_string())?;
+    crate::expiry::reschedule(app);
+    Ok(imported.items.len() as u64)
+}
+
+/// Items per page when streaming history into or out of a backup.
+const BACKUP_BATCH: usize = 500;
+
+/// Returned by `backup_data` / `restore_data` when `cancel_backup` stopped
+/// them.
+pub const BACKUP_CANCELLED: &str = "Backup cancelled";
+
+/// Payload of the "backup-progress" event.
+#[derive(Clone, Serialize)]
+pub struct BackupProgress {
+    /// "export" and "images" (items, then images, written), "verify" (bytes
+    /// of the file checked before a restore writes anything) or "restore"
+    /// (items and images written).
+    phase: &'static str,
+    done: u64,
+    total: u64,
+}
+
+fn backup_progress(app: &AppHandle, phase: &'static str, done: u64, total: u64) {
+    let total = total.max(done);
+    let _ = app.emit("backup-progress", BackupProgress { phase, done, total });
+}
+
+fn check_cancelled(app: &AppHandle) -> Result<(), String> {
+    match app.try_state::<crate::BackupCancel>() {
+        Some(cancel) if cancel.0.load(Ordering::Relaxed) => Err(BACKUP_CANCELLED.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Stops the running backup or restore at its next batch.
+#[tauri::command]
+pub fn cancel_backup(app: AppHandle) -> Result<(), String> {
+    if let Some(cancel) = app.try_state::<crate::BackupCancel>() {
+        cancel.0.store(true, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+fn start_backup_job(app: &AppHandle) {
+    if let Some(cancel) = app.try_state::<crate::BackupCancel>() {
+        cancel.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Writes a backup archive (see archive.rs) with the images its items
+/// reference, paging through history so memory stays flat however large it
+/// is. With a passphrase the archive is streamed through an encrypted
+/// container (see backup.rs) whose sensitive items are readable on any
+/// machine; without one it's plain, and sensitive items only restore on this
+/// machine. Emits "backup-progress"; `path` is only replaced once the new
+/// file is complete.
 #[tauri::command]
 pub async fn backup_data(
     app: AppHandle,
     path: String,
     groups: Option<Vec<String>>,
+    passphrase: Option<String>,
+    thumbnails: Option<bool>,
 ) -> Result<(), String> {
     let validated_path = validate_path(&path)?;
+    start_backup_job(&app);
+    let mut partial = validated_path.clone().into_os_string();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let written = write_backup(
+        &app,
+        &partial,
+        &groups.unwrap_or_default(),
+        passphrase.as_deref().filter(|p| !p.is_empty()),
+        thumbnails.unwrap_or(false),
+    );
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    std::fs::rename(&partial, &validated_path).map_err(|e| e.to_string())
+}
+
+fn write_backup(
+    app: &AppHandle,
+    path: &Path,
+    groups: &[String],
+    passphrase: Option<&str>,
+    thumbnails: bool,
+) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    let out = std::io::BufWriter::new(file);
+    let out = match passphrase {
+        None => write_archive(app, out, groups, thumbnails, None)?,
+        Some(passphrase) => {
+            let key = crate::backup::BackupKey::new(passphrase)?;
+            write_archive(app, key.encrypt(out)?, groups, thumbnails, Some(&key))?.finish()?
+        }
+    };
+    let file = out.into_inner().map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())
+}
+
+/// Streams history, then the images it references, into an archive on
+/// `out`. With `backup_key`, sensitive items are re-encrypted under it.
+fn write_archive<W: std::io::Write>(
+    app: &AppHandle,
+    out: W,
+    groups: &[String],
+    thumbnails: bool,
+    backup_key: Option<&crate::backup::BackupKey>,
+) -> Result<W, String> {
     let db = app.state::<ClipboardDB>();
-    let json = db.get_all_data_json(groups).map_err(|e| e.to_string())?;
-    std::fs::write(validated_path, json).map_err(|e| e.to_string())
+    let total = db.count_backup_items(groups).map_err(|e| e.to_string())?;
+    let exported_at = chrono::Local::now().to_rfc3339();
+    let mut writer = crate::archive::Writer::new(out, exported_at, thumbnails)?;
+    for group in db.get_backup_groups(groups).map_err(|e| e.to_string())? {
+        writer.group(group)?;
+    }
+
+    let (mut after_id, mut done, mut kept) = (0, 0, 0);
+    loop {
+        check_cancelled(app)?;
+        let mut batch = db
+            .get_backup_items(groups, after_id, BACKUP_BATCH)
+            .map_err(|e| e.to_string())?;
+        let Some(last) = batch.last() else { break };
+        after_id = last.id;
+        if let Some(key) = backup_key.filter(|_| crate::backup::has_sensitive(&batch)) {
+            let local = crate::crypto::get_or_create_key(app)?;
+            kept += crate::backup::rekey_items(&mut batch, &local, key.keys()).len();
+        }
+        done += batch.len() as u64;
+        for item in batch {
+            writer.item(item)?;
+        }
+        backup_progress(app, "export", done, total);
+    }
+    if kept > 0 {
+        eprintln!("Backup: {} sensitive item(s) kept under their old key", kept);
+    }
+
+    let hashes = writer.take_image_hashes();
+    let images = hashes.len() as u64;
+    for (i, hash) in hashes.iter().enumerate() {
+        check_cancelled(app)?;
+        // A hash with no blob is already dangling here; nothing to carry.
+        if let Some(blob) = db.get_blob_record(hash).map_err(|e| e.to_string())? {
+            writer.blob(&blob)?;
+        }
+        backup_progress(app, "images", i as u64 + 1, images);
+    }
+    writer.finish()
+}
+
+/// Counts bytes read from a backup file, for "verify" progress.
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
 }
 
+impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Restores a backup archive, an older single-document JSON backup, or
+/// either inside an encrypted container; an encrypted one without a
+/// passphrase fails with `backup::PASSPHRASE_REQUIRED`.
+///
+/// An archive is read twice: a "verify" pass checks the whole file — and
+/// that the vault is unlocked if it holds secrets — before anything is
+/// written, then a "restore" pass applies it a batch at a time inside one
+/// transaction (see `RestoreSession`). Cancelling, or any error, at any point
+/// leaves the database as it was.
+///
+/// `mode` is "merge" (keep local pins and descriptions, add the backup's
+/// groups), "newest" (as merge, but whichever side edited an item last wins)
+/// or "replace". With `dry_run` only the verify pass runs and the returned
+/// report says what the restore would add, skip and change.
 #[tauri::command]
-pub async fn restore_data(app: AppHandle, path: String, mode: String) -> Result<(), String> {
+pub async fn restore_data(
+    app: AppHandle,
+    path: String,
+    mode: String,
+    passphrase: Option<String>,
+    dry_run: Option<bool>,
+) -> Result<Option<RestoreReport>, String> {
+    use crate::archive::{Backup, Entry};
+    use std::io::BufRead;
+
+    if !matches!(mode.as_str(), "merge" | "replace" | "newest") {
+        return Err(format!("Unknown restore mode: {}", mode));
+    }
+    let dry_run = dry_run.unwrap_or(false);
     let validated_path = validate_path(&path)?;
+    start_backup_job(&app);
     let db = app.state::<ClipboardDB>();
-    let json = std::fs::read_to_string(validated_path).map_err(|e| e.to_string())?;
-    db.restore_from_json(&json, &mode)
-        .map_err(|e| e.to_string())
+    let size = std::fs::metadata(&validated_path)
+        .map_err(|e| e.to_string())?
+        .len();
+    let read = Arc::new(AtomicU64::new(0));
+    let file = std::fs::File::open(&validated_path).map_err(|e| e.to_string())?;
+    let mut file = std::io::BufReader::new(CountingReader {
+        inner: file,
+        count: read.clone(),
+    });
+
+    let encrypted = crate::backup::is_encrypted_backup(file.fill_buf().map_err(|e| e.to_string())?);
+    let (input, backup_key): (Box<dyn BufRead>, _) = if encrypted {
+        let passphrase = passphrase
+            .filter(|p| !p.is_empty())
+            .ok_or_else(|| crate::backup::PASSPHRASE_REQUIRED.to_string())?;
+        let (key, reader) = crate::backup::open(file, &passphrase)?;
+        (reader, Some(key))
+    } else {
+        (Box::new(file), None)
+    };
+
+    let mut reader = match crate::archive::open(input)? {
+        Backup::Archive(reader) => reader,
+        Backup::Legacy(mut data) => {
+            if dry_run {
+                let mut report = RestoreReport::default();
+                db.preview_restore_groups(&data.groups, &mode, &mut report)
+                    .map_err(|e| e.to_string())?;
+                for batch in data.history.chunks(BACKUP_BATCH) {
+                    db.preview_restore_items(batch, &mode, &mut report)
+                        .map_err(|e| e.to_string())?;
+                }
+                return Ok(Some(report));
+            }
+            if let Some(key) = backup_key.filter(|_| crate::backup::has_sensitive(&data.history)) {
+                // Fails with VAULT_LOCKED before anything is written.
+                let local = crate::crypto::get_or_create_key(&app)?;
+                crate::backup::rekey_items(&mut data.history, key.keys(), &local);
+            }
+            db.restore_backup(data, &mode).map_err(|e| e.to_string())?;
+            crate::expiry::reschedule(&app);
+            return Ok(None);
+        }
+    };
+
+    // Verify pass; for a dry run it also works out the report.
+    let (mut total, mut sensitive) = (0u64, false);
+    let mut report = RestoreReport::default();
+    let (mut groups, mut batch) = (Vec::new(), Vec::new());
+    loop {
+        let entry = reader.next_entry()?;
+        let finished = entry.is_none();
+        match entry {
+            Some(Entry::Group(group)) => {
+                if dry_run {
+                    groups.push(group);
+                }
+                continue;
+            }
+            Some(Entry::Item(item)) => {
+                sensitive |= item.is_sensitive && crate::crypto::is_encrypted(&item.raw_content);
+                if dry_run {
+                    batch.push(item);
+                }
+            }
+            Some(Entry::Blob(record)) if dry_run => {
+                match db.has_blob(&record.hash).map_err(|e| e.to_string())? {
+                    true => report.existing_images += 1,
+                    false => report.new_images += 1,
+                }
+            }
+            Some(Entry::Blob(_)) | None => {}
+        }
+        if batch.len() >= BACKUP_BATCH || (finished && !batch.is_empty()) {
+            db.preview_restore_items(&std::mem::take(&mut batch), &mode, &mut report)
+                .map_err(|e| e.to_string())?;
+        }
+        if finished {
+            break;
+        }
+        total += 1;
+        if total % 256 == 0 {
+            check_cancelled(&app)?;
+            backup_progress(&app, "verify", read.load(Ordering::Relaxed), size);
+        }
+    }
+    drop(reader);
+    if dry_run {
+        db.preview_restore_groups(&groups, &mode, &mut report)
+            .map_err(|e| e.to_string())?;
+        return Ok(Some(report));
+    }
+    let local = match &backup_key {
+        // Fails with VAULT_LOCKED before anything is written.
+        Some(_) if sensitive => Some(crate::crypto::get_or_create_key(&app)?),
+        _ => None,
+    };
+
+    // Restore pass.
+    let file = std::fs::File::open(&validated_path).map_err(|e| e.to_string())?;
+    let input: Box<dyn BufRead> = match &backup_key {
+        Some(key) => key.reopen(file)?,
+        None => Box::new(std::io::BufReader::new(file)),
+    };
+    let Backup::Archive(mut reader) = crate::archive::open(input)? else {
+        return Err("Backup file changed while it was being read".to_string());
+    };
+    let rekey = backup_key.as_ref().zip(local.as_ref());
+    let session = db.begin_restore().map_err(|e| e.to_string())?;
+    // Groups come first in an archive; they're written with the first item.
+    let mut groups = Some(Vec::new());
+    let mut batch = Vec::with_capacity(BACKUP_BATCH);
+    let mut blobs = crate::archive::BlobReport::default();
+    let mut done = 0u64;
+    loop {
+        let entry = reader.next_entry()?;
+        let finished = entry.is_none();
+        if !matches!(entry, Some(Entry::Group(_))) {
+            if let Some(groups) = groups.take() {
+                session.groups(&groups, &mode).map_err(|e| e.to_string())?;
+            }
+        }
+        match entry {
+            Some(Entry::Group(group)) => {
+                match groups.as_mut() {
+                    Some(groups) => groups.push(group),
+                    // Out of order; just add it.
+                    None => session
+                        .groups(&[group], "merge")
+                        .map_err(|e| e.to_string())?,
+                }
+                continue;
+            }
+            Some(Entry::Item(item)) => {
+                batch.push(item);
+                if batch.len() < BACKUP_BATCH {
+                    continue;
+                }
+            }
+            Some(Entry::Blob(record)) => {
+                blobs.restore(&session, record)?;
+                done += 1;
+            }
+            None => {}
+        }
+        if !batch.is_empty() {
+            if let Some((key, local)) = rekey {
+                crate::backup::rekey_items(&mut batch, key.keys(), local);
+            }
+            done += batch.len() as u64;
+            session
+                .items(std::mem::take(&mut batch), &mode)
+                .map_err(|e| e.to_string())?;
+        }
+        if finished {
+            break;
+        }
+        check_cancelled(&app)?;
+        backup_progress(&app, "restore", done, total);
+    }
+    session.commit(&mode).map_err(|e| e.to_string())?;
+    if blobs.damaged > 0 {
+        eprintln!(
+            "Backup: restored {} image(s), {} already present, {} damaged",
+            blobs.restored, blobs.skipped, blobs.damaged
+        );
+    }
+    crate::expiry::reschedule(&app);
+    Ok(None)
+}
+
+#[tauri::command]
+pub fn auto_backup_status(app: AppHandle) -> crate::snapshots::SnapshotStatus {
+    crate::snapshots::status(&app.state::<ClipboardDB>())
+}
+
+/// Takes a scheduled-style snapshot right away ("Back up now" in settings).
+#[tauri::command]
+pub async fn run_auto_backup(app: AppHandle) -> Result<crate::snapshots::SnapshotStatus, String> {
+    let db = app.state::<ClipboardDB>();
+    crate::snapshots::run(&app, &db)?;
+    Ok(crate::snapshots::status(&db))
+}
+
+#[tauri::command]
+pub fn classifier_rules_status(app: AppHandle) -> crate::rules::RulesStatus {
+    crate::rules::status(&app)
+}
+
+/// Opens the user's classifier rules file in the default editor, creating it
+/// from a template first if needed; with `builtin`, a copy of the built-in
+/// rules instead.
+#[tauri::command]
+pub fn open_classifier_rules(app: AppHandle, builtin: Option<bool>) -> Result<String, String> {
+    use tauri_plugin_opener::OpenerExt;
+    let path = crate::rules::file_to_edit(&app, builtin.unwrap_or(false))?;
+    let path = path.to_string_lossy().into_owned();
+    app.opener()
+        .open_path(path.clone(), None::<&str>)
+        .map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Why an item is in its automatic groups: each group the current rules
+/// give its text, with the confidence and the rule behind it.
+#[tauri::command]
+pub fn classify_preview(app: AppHandle, id: i64) -> Result<Vec<crate::classifier::Score>, String> {
+    let db = app.state::<ClipboardDB>();
+    let (content_type, raw_content) = db.get_item_payload(id).map_err(|e| e.to_string())?;
+    if !matches!(content_type.as_str(), "text" | "html" | "rtf") {
+        return Err("Only text items are sorted into groups".to_string());
+    }
+    let text = if crate::crypto::is_encrypted(&raw_content) {
+        let key = crate::crypto::get_or_create_key(&app)?;
+        crate::crypto::decrypt(&key, &raw_content)?
+    } else {
+        raw_content
+    };
+    Ok(crate::clipboard::score_text(&db, &text))
+}
+
+#[derive(Serialize)]
+pub struct LearnedGroup {
+    pub name: String,
+    pub examples: i64,
+}
+
+#[derive(Serialize)]
+pub struct LearningStatus {
+    pub enabled: bool,
+    /// Groups with at least this many examples are suggested.
+    pub min_examples: i64,
+    pub groups: Vec<LearnedGroup>,
+}
+
+/// What the learned grouping knows: whether it's on and how many examples
+/// each user group has given it.
+#[tauri::command]
+pub fn learning_status(app: AppHandle) -> Result<LearningStatus, String> {
+    let db = app.state::<ClipboardDB>();
+    let enabled = db.get_setting("learn_groups").map_err(|e| e.to_string())?.as_deref() != Some("0");
+    let groups = db
+        .learning_summary()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|(name, examples)| LearnedGroup { name, examples })
+        .collect();
+    Ok(LearningStatus {
+        enabled,
+        min_examples: crate::learning::MIN_EXAMPLES,
+        groups,
+    })
+}
+
+#[derive(Serialize)]
+pub struct GroupConfidence {
+    pub name: String,
+    pub items: i64,
+    pub average: f64,
+    /// Items whose confidence in this group is under the `below` asked for.
+    pub below: i64,
+}
+
+/// How sure the classifier has been of each automatic group, so a rule that
+/// misfires stands out (search `group:X confidence:<N` to see its items).
+#[tauri::command]
+pub fn group_confidence_summary(app: AppHandle, below: f64) -> Result<Vec<GroupConfidence>, String> {
+    if !(0.0..=1.0).contains(&below) {
+        return Err("Confidence must be between 0 and 1".to_string());
+    }
+    let summary = app
+        .state::<ClipboardDB>()
+        .group_confidence_summary(below)
+        .map_err(|e| e.to_string())?;
+    Ok(summary
+        .into_iter()
+        .map(|(name, items, average, below)| GroupConfidence {
+            name,
+            items,
+            average,
+            below,
+        })
+        .collect())
+}
+
+/// Forgets what the learned grouping picked up from regrouping and, if it is
+/// on, relearns from the user groups as they are now.
+#[tauri::command]
+pub fn reset_learning(app: AppHandle) -> Result<LearningStatus, String> {
+    app.state::<ClipboardDB>()
+        .reset_learning()
+        .map_err(|e| e.to_string())?;
+    learning_status(app)
 }
 
 #[tauri::command]
@@ -462,11 +1116,28 @@ pub fn copy_item_to_clipboard(app: AppHandle, id: i64) -> Result<(), String> {
                 clipboard.set_text(paths.join("\n")).map_err(|e| e.to_string())?;
             }
         }
+        "html" | "rtf" => {
+            // Restore the formatted flavor together with its plain-text
+            // fallback; the paste target picks the richest one it supports.
+            let rich = db.get_rich_content(id).map_err(|e| e.to_string())?;
+            let mut clipboard = Clipboard::new().map_err(|e| e.to_string())?;
+            match (content_type.as_str(), rich) {
+                ("html", Some(html)) => clipboard
+                    .set()
+                    .html(html, Some(raw_content))
+                    .map_err(|e| e.to_string())?,
+                ("rtf", Some(rtf)) if crate::write_clipboard_rtf(&rtf, &raw_content) => {}
+                _ => clipboard.set_text(raw_content).map_err(|e| e.to_string())?,
+            }
+        }
         _ => {
             // Sensitive items are stored encrypted; decrypt before copying.
             let text = if crate::crypto::is_encrypted(&raw_content) {
                 let key = crate::crypto::get_or_create_key(&app)?;
-                crate::crypto::decrypt(&key, &raw_content)?
+                let text = crate::crypto::decrypt(&key, &raw_content)?;
+                let plain_hash = crate::clipboard::sha256_hex(text.trim().as_bytes());
+                crate::expiry::remember(&app, id, plain_hash);
+                text
             } else {
                 raw_content
             };
@@ -504,7 +1175,7 @@ pub fn copy_as(app: AppHandle, id: i64, transform: String) -> Result<(), String>
     use arboard::Clipboard;
     let db = app.state::<ClipboardDB>();
     let (content_type, raw) = db.get_item_payload(id).map_err(|e| e.to_string())?;
-    if content_type != "text" {
+    if !matches!(content_type.as_str(), "text" | "html" | "rtf") {
         return Err("Transforms apply to text items only".to_string());
     }
     let plain = resolve_plaintext(&app, &raw)?;
@@ -519,7 +1190,7 @@ pub fn copy_as(app: AppHandle, id: i64, transform: String) -> Result<(), String>
 #[tauri::command]
 pub fn set_item_sensitive(app: AppHandle, id: i64, sensitive: bool) -> Result<(), String> {
     let db = app.state::<ClipboardDB>();
-    let (_content_type, raw_content) = db.get_item_payload(id).map_err(|e| e.to_string())?;
+    let (content_type, raw_content) = db.get_item_payload(id).map_err(|e| e.to_string())?;
     let key = crate::crypto::get_or_create_key(&app)?;
 
     if sensitive {
@@ -527,18 +1198,46 @@ pub fn set_item_sensitive(app: AppHandle, id: i64, sensitive: bool) -> Result<()
             return Ok(()); // already encrypted
         }
         let enc = crate::crypto::encrypt(&key, &raw_content)?;
-        db.set_raw_and_sensitive(id, &enc, true).map_err(|e| e.to_string())?;
+        let findings = if matches!(content_type.as_str(), "text" | "html" | "rtf") {
+            crate::secrets::scan(&raw_content)
+        } else {
+            Vec::new()
+        };
+        let preview = (!findings.is_empty()).then(|| crate::secrets::redact(&raw_content, &findings));
+        db.set_raw_and_sensitive(id, &enc, true, preview.as_deref())
+            .map_err(|e| e.to_string())?;
+        let plain_hash = crate::clipboard::sha256_hex(raw_content.trim().as_bytes());
+        crate::expiry::remember(&app, id, plain_hash);
+        crate::expiry::reschedule(&app);
     } else {
         let plain = if crate::crypto::is_encrypted(&raw_content) {
             crate::crypto::decrypt(&key, &raw_content)?
         } else {
             raw_content
         };
-        db.set_raw_and_sensitive(id, &plain, false).map_err(|e| e.to_string())?;
+        db.set_raw_and_sensitive(id, &plain, false, None)
+            .map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
+/// The most recent secrets found in captured clips: which detector, where it
+/// came from and whether the clip was masked. The secrets themselves are
+/// never recorded.
+#[tauri::command]
+pub fn secret_detections(app: AppHandle, limit: Option<u32>) -> Result<Vec<SecretDetection>, String> {
+    app.state::<ClipboardDB>()
+        .secret_detections(limit.unwrap_or(50).min(1000))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn clear_secret_detections(app: AppHandle) -> Result<(), String> {
+    app.state::<ClipboardDB>()
+        .clear_secret_detections()
+        .map_err(|e| e.to_string())
+}
+
 /// Returns the decrypted plaintext of a sensitive item, for an explicit reveal.
 #[tauri::command]
 pub fn reveal_item(app: AppHandle, id: i64) -> Result<String, String> {
@@ -552,6 +1251,139 @@ pub fn reveal_item(app: AppHandle, id: i64) -> Result<String, String> {
     }
 }
 
+// ── Vault (passphrase-protected key) ────────────────────────────────────────
+
+#[derive(Serialize)]
+pub struct VaultStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+}
+
+/// How long an unlock lasts, from the `vault_unlock_minutes` setting
+/// (default 15; 0 keeps it unlocked until locked or the app quits).
+fn vault_unlock_period(app: &AppHandle) -> Option<Duration> {
+    let minutes = app
+        .state::<ClipboardDB>()
+        .get_setting("vault_unlock_minutes")
+        .ok()
+        .flatten()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(15);
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+#[tauri::command]
+pub fn vault_status(app: AppHandle) -> VaultStatus {
+    VaultStatus {
+        enabled: crate::crypto::vault_enabled(&app),
+        unlocked: crate::crypto::vault_unlocked(&app),
+    }
+}
+
+/// Protects the sensitive-item key with a passphrase. Async because Argon2id
+/// deliberately takes a noticeable moment.
+#[tauri::command]
+pub async fn enable_vault(app: AppHandle, passphrase: String) -> Result<(), String> {
+    crate::crypto::enable_vault(&app, &passphrase, vault_unlock_period(&app))
+}
+
+#[tauri::command]
+pub async fn disable_vault(app: AppHandle, passphrase: String) -> Result<(), String> {
+    crate::crypto::disable_vault(&app, &passphrase)
+}
+
+#[tauri::command]
+pub async fn unlock_vault(app: AppHandle, passphrase: String) -> Result<(), String> {
+    crate::crypto::unlock_vault(&app, &passphrase, vault_unlock_period(&app))
+}
+
+#[tauri::command]
+pub fn lock_vault(app: AppHandle) {
+    crate::crypto::lock_vault(&app);
+}
+
+/// Changes the vault passphrase; only the wrapped key is rewritten.
+#[tauri::command]
+pub async fn change_vault_passphrase(
+    app: AppHandle,
+    old_passphrase: String,
+    new_passphrase: String,
+) -> Result<(), String> {
+    crate::crypto::change_passphrase(&app, &old_passphrase, &new_passphrase)
+}
+
+/// Per-item outcome of a key rotation that couldn't re-encrypt the item.
+#[derive(Serialize)]
+pub struct RotationFailure {
+    pub id: i64,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct RotationReport {
+    /// Id of the new current key (the `<kid>` in `enc:v2:<kid>:`).
+    pub key_id: String,
+    pub rotated: usize,
+    pub failed: Vec<RotationFailure>,
+}
+
+#[derive(Clone, Serialize)]
+struct RotationProgress {
+    done: usize,
+    total: usize,
+}
+
+/// Replaces the sensitive-item key and re-encrypts every sensitive item with
+/// the new one in a single transaction. Retired keys are kept, so old backups
+/// still decrypt. Items that fail to decrypt, or that were changed while the
+/// rotation ran, are left as they were and reported; progress is emitted as
+/// `key-rotation-progress`.
+#[tauri::command]
+pub async fn rotate_sensitive_key(
+    app: AppHandle,
+    passphrase: Option<String>,
+) -> Result<RotationReport, String> {
+    let db = app.state::<ClipboardDB>();
+    let items = db.get_sensitive_payloads().map_err(|e| e.to_string())?;
+    // The new ring is persisted before any item changes: if the rewrite below
+    // fails, every item is still readable with the (now retired) old key.
+    let keys = crate::crypto::rotate_key(&app, passphrase.as_deref())?;
+
+    let total = items.len();
+    let mut updates = Vec::with_capacity(total);
+    let mut failed = Vec::new();
+    for (i, (id, raw)) in items.into_iter().enumerate() {
+        let plain = if crate::crypto::is_encrypted(&raw) {
+            crate::crypto::decrypt(&keys, &raw)
+        } else {
+            Ok(raw.clone())
+        };
+        match plain.and_then(|plain| crate::crypto::encrypt(&keys, &plain)) {
+            Ok(enc) => updates.push((id, raw, enc)),
+            Err(error) => failed.push(RotationFailure { id, error }),
+        }
+        let done = i + 1;
+        if done % 25 == 0 || done == total {
+            let _ = app.emit("key-rotation-progress", RotationProgress { done, total });
+        }
+    }
+
+    let (rotated, changed) = db
+        .replace_sensitive_payloads(&updates)
+        .map_err(|e| e.to_string())?;
+    // Still readable: whatever wrote them used the old or the new key.
+    failed.extend(changed.into_iter().map(|id| RotationFailure {
+        id,
+        error: "Changed during the rotation; left as it was".to_string(),
+    }));
+    failed.sort_by_key(|f| f.id);
+    Ok(RotationReport {
+        key_id: keys.current_id(),
+        rotated,
+        failed,
+    })
+}
+
 // ── Global shortcut configuration ───────────────────────────────────────────
 
 /// Current accelerator (user-set or default) for every rebindable global
@@ -655,10 +1487,52 @@ pub fn get_setting(app: AppHandle, key: String) -> Result<Option<String>, String
     db.get_setting(&key).map_err(|e| e.to_string())
 }
 
+/// Rejects malformed per-app settings up front; the readers would otherwise
+/// silently ignore them.
+fn validate_setting(key: &str, value: &str) -> Result<(), String> {
+    let result = match key {
+        "app_group_rules" => serde_json::from_str::<HashMap<String, String>>(value).map(|_| ()),
+        "retention_app_days" => serde_json::from_str::<HashMap<String, u32>>(value).map(|_| ()),
+        "ignored_apps" => serde_json::from_str::<Vec<String>>(value).map(|_| ()),
+        "auto_backup_interval" => {
+            return match value {
+                "off" | "daily" | "weekly" => Ok(()),
+                _ => Err(format!("Invalid {}: expected off, daily or weekly", key)),
+            };
+        }
+        "auto_backup_dir" if !value.trim().is_empty() => {
+            return validate_path(value.trim()).map(|_| ());
+        }
+        "auto_backup_keep" => {
+            return match value.trim().parse::<u32>() {
+                Ok(n) if n > 0 => Ok(()),
+                _ => Err(format!("Invalid {}: expected a number above 0", key)),
+            };
+        }
+        "sensitive_ttl_seconds" | "vault_unlock_minutes" => {
+            return value
+                .trim()
+                .parse::<u32>()
+                .map(|_| ())
+                .map_err(|_| format!("Invalid {}: expected a whole number", key));
+        }
+        _ => Ok(()),
+    };
+    result.map_err(|e| format!("Invalid {}: {}", key, e))
+}
+
 #[tauri::command]
 pub fn set_setting(app: AppHandle, key: String, value: String) -> Result<(), String> {
+    validate_setting(&key, &value)?;
     let db = app.state::<ClipboardDB>();
-    db.set_setting(&key, &value).map_err(|e| e.to_string())
+    db.set_setting(&key, &value).map_err(|e| e.to_string())?;
+    if key == "sensitive_ttl_seconds" {
+        crate::expiry::reschedule(&app);
+    }
+    if key.starts_with("auto_backup_") {
+        crate::snapshots::reschedule(&app);
+    }
+    Ok(())
 }
 
 // ── Paste stack (multi-paste queue) ─────────────────────────────────────────
@@ -847,15 +1721,16 @@ pub async fn copy_item_and_paste_from_popup(app: AppHandle, id: i64) -> Result<(
     #[cfg(not(target_os = "macos"))]
     {
         tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
-        return send_paste_shortcut();
+        send_paste_shortcut()
     }
 }
 
+/// Exports the whole history; despite the name, in any `export_group` format.
 #[tauri::command]
-pub async fn export_all_txt(app: AppHandle, path: String) -> Result<(), String> {
+pub async fn export_all_txt(app: AppHandle, path: String, format: Option<String>) -> Result<(), String> {
     let validated_path = validate_path(&path)?;
-    let db = app.state::<ClipboardDB>();
-    db.export_all_txt(validated_path).map_err(|e| e.to_string())
+    let format = crate::exports::Format::pick(format.as_deref(), &validated_path)?;
+    write_export(&app, &validated_path, format, None)
 }
 
 #[tauri::command]
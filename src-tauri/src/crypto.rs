@@ -1,86 +1,516 @@
 // Field-level encryption for sensitive (masked) clipboard items.
 //
 // Only items the user marks sensitive (or auto-masked secrets) are encrypted —
-// not the whole database. The key lives in a 0600 file in the app data dir.
-// Encrypted values are tagged with an `enc:v1:` prefix so they're detectable
+// not the whole database. The key is kept in the OS credential store (see
+// keystore.rs) and cached in memory (`KeyCache`) once loaded.
+// Encrypted values are tagged with an `enc:` prefix so they're detectable
 // without a separate flag. If the key is ever lost, only sensitive items become
 // unreadable; everything else is untouched.
+//
+// The stored key material is a `KeyRing`: the current key followed by every
+// key it replaced, so rotating (`rotate_key`) never strands a value — including
+// ones in old backups. Values are written as `enc:v2:<kid>:<base64>`, where
+// `kid` names the key; the original `enc:v1:<base64>` format (no key id) is
+// still read by trying each key in turn.
+//
+// Vault mode replaces the stored key with `.sensitive_vault`: the same key
+// ring, wrapped (AES-GCM) under a key derived from the user's passphrase with
+// Argon2id. The unwrapped key is only held in `KeyCache` for the unlock
+// period; until then anything that needs it gets `VAULT_LOCKED`. Changing the
+// passphrase re-wraps the data key, so stored items are never re-encrypted.
 
+use crate::keystore;
 use aes_gcm::{
     aead::{Aead, KeyInit},
     Aes256Gcm, Key, Nonce,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::Engine as _;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Manager};
+use zeroize::Zeroizing;
 
-const PREFIX: &str = "enc:v1:";
+const PREFIX_V1: &str = "enc:v1:";
+const PREFIX_V2: &str = "enc:v2:";
 const B64: base64::engine::general_purpose::GeneralPurpose = base64::engine::general_purpose::STANDARD;
 
-fn key_path(app: &AppHandle) -> Option<PathBuf> {
-    app.path()
-        .app_data_dir()
-        .ok()
-        .map(|dir| dir.join(".sensitive_key"))
+/// Error returned wherever the data key is needed while the vault is locked.
+/// The frontend matches on it to prompt for the passphrase.
+pub const VAULT_LOCKED: &str = "Vault is locked";
+
+const VAULT_FILE: &str = ".sensitive_vault";
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+// Argon2id cost for new vaults and backups (64 MiB, 3 passes). Stored with
+// each vault/backup so it can be raised later without breaking old ones.
+pub(crate) const ARGON2_M_COST: u32 = 64 * 1024;
+pub(crate) const ARGON2_T_COST: u32 = 3;
+pub(crate) const ARGON2_P_COST: u32 = 1;
+
+/// On-disk vault: the data key sealed under an Argon2id-derived key.
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    wrapped_key: String,
 }
 
-/// Loads the 256-bit sensitive-item key, creating it on first use.
-pub fn get_or_create_key(app: &AppHandle) -> Result<[u8; 32], String> {
-    let path = key_path(app).ok_or_else(|| "no app data dir".to_string())?;
+/// The sensitive-item keys: the current one first, then retired ones newest
+/// to oldest. Serialized as the keys' bytes back to back, so a store written
+/// before rotation existed (a single 32-byte key) is a one-key ring.
+#[derive(Clone)]
+pub struct KeyRing(Zeroizing<Vec<[u8; 32]>>);
 
-    if let Ok(bytes) = std::fs::read(&path) {
-        if bytes.len() == 32 {
-            let mut key = [0u8; 32];
-            key.copy_from_slice(&bytes);
-            return Ok(key);
+impl KeyRing {
+    fn generate() -> Result<Self, String> {
+        Ok(KeyRing(Zeroizing::new(vec![random_key()?])))
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(32) {
+            return Err("stored key has the wrong length".to_string());
         }
+        let keys = bytes
+            .chunks_exact(32)
+            .map(|chunk| <[u8; 32]>::try_from(chunk).expect("32-byte chunk"))
+            .collect();
+        Ok(KeyRing(Zeroizing::new(keys)))
+    }
+
+    pub(crate) fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(self.0.concat())
+    }
+
+    fn current(&self) -> &[u8; 32] {
+        &self.0[0]
+    }
+
+    /// Id of the key new values are encrypted with.
+    pub fn current_id(&self) -> String {
+        key_id(self.current())
+    }
+
+    fn find(&self, kid: &str) -> Option<&[u8; 32]> {
+        self.0.iter().find(|key| key_id(key) == kid)
     }
 
+    /// A ring with a fresh current key; the old keys stay on as retired.
+    fn rotated(&self) -> Result<Self, String> {
+        let mut keys = Zeroizing::new(Vec::with_capacity(self.0.len() + 1));
+        keys.push(random_key()?);
+        keys.extend_from_slice(&self.0);
+        Ok(KeyRing(keys))
+    }
+}
+
+/// Short, stable identifier for a key: the first 4 bytes of its SHA-256.
+fn key_id(key: &[u8; 32]) -> String {
+    Sha256::digest(key)[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn random_key() -> Result<[u8; 32], String> {
     let mut key = [0u8; 32];
     getrandom::getrandom(&mut key).map_err(|e| e.to_string())?;
-    if let Some(parent) = path.parent() {
-        let _ = std::fs::create_dir_all(parent);
+    Ok(key)
+}
+
+struct Unlocked {
+    keys: KeyRing,
+    /// None = stay unlocked until locked explicitly or the app quits.
+    until: Option<Instant>,
+}
+
+/// The key ring once loaded or unlocked (managed state), so the credential
+/// store isn't queried on every capture.
+#[derive(Default)]
+pub struct KeyCache(Mutex<Option<Unlocked>>);
+
+fn data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map_err(|_| "no app data dir".to_string())
+}
+
+fn vault_path(app: &AppHandle) -> Option<PathBuf> {
+    data_dir(app).ok().map(|dir| dir.join(VAULT_FILE))
+}
+
+/// Loads the sensitive-item key ring, creating it on first use. In vault
+/// mode it comes from memory and fails with `VAULT_LOCKED` while locked.
+pub fn get_or_create_key(app: &AppHandle) -> Result<KeyRing, String> {
+    if vault_enabled(app) {
+        return unlocked_key(app);
+    }
+    if let Some(keys) = cached_key(app) {
+        return Ok(keys);
     }
-    std::fs::write(&path, key).map_err(|e| e.to_string())?;
+
+    let dir = data_dir(app)?;
+    let backend = keystore::Backend::from_env();
+    let keys = match keystore::load(&dir, backend)? {
+        Some(bytes) => KeyRing::from_bytes(&bytes)?,
+        None => {
+            let keys = KeyRing::generate()?;
+            keystore::store(&dir, &keys.to_bytes(), backend)?;
+            keys
+        }
+    };
+    cache_key(app, keys.clone(), None);
+    Ok(keys)
+}
+
+/// Replaces the current key with a fresh one, keeping the old keys so
+/// existing values still decrypt, and returns the new ring. Re-encrypting
+/// stored items is up to the caller. A vault needs its passphrase to re-wrap.
+pub fn rotate_key(app: &AppHandle, passphrase: Option<&str>) -> Result<KeyRing, String> {
+    let keys = get_or_create_key(app)?.rotated()?;
+    if vault_enabled(app) {
+        let passphrase = passphrase
+            .ok_or_else(|| "Enter your vault passphrase to rotate the key".to_string())?;
+        // Checks the passphrase before anything is overwritten.
+        unwrap_key(&read_vault(app)?, passphrase)?;
+        write_vault(app, &wrap_key(&keys.to_bytes(), passphrase)?)?;
+    } else {
+        keystore::store(
+            &data_dir(app)?,
+            &keys.to_bytes(),
+            keystore::Backend::from_env(),
+        )?;
+    }
+    replace_cached_key(app, keys.clone());
+    Ok(keys)
+}
+
+/// Writes a secret file readable only by the current user.
+pub(crate) fn write_private(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    // Write-then-rename so a crash never leaves a truncated key or vault.
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
     #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
-        let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+        let _ = std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600));
     }
-    Ok(key)
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
 }
 
 /// True if `value` is an Ortu-encrypted payload.
 pub fn is_encrypted(value: &str) -> bool {
-    value.starts_with(PREFIX)
+    value.starts_with(PREFIX_V1) || value.starts_with(PREFIX_V2)
+}
+
+/// Encrypts plaintext with the current key into a tagged, base64
+/// (nonce || ciphertext) string.
+pub fn encrypt(keys: &KeyRing, plaintext: &str) -> Result<String, String> {
+    Ok(format!(
+        "{}{}:{}",
+        PREFIX_V2,
+        keys.current_id(),
+        B64.encode(seal(keys.current(), plaintext.as_bytes())?)
+    ))
 }
 
-/// Encrypts plaintext into a tagged, base64 (nonce || ciphertext) string.
-pub fn encrypt(key: &[u8; 32], plaintext: &str) -> Result<String, String> {
+/// Decrypts a value produced by `encrypt` (or the older `enc:v1:` format).
+/// Errors if its key isn't in the ring or the data is corrupt.
+pub fn decrypt(keys: &KeyRing, stored: &str) -> Result<String, String> {
+    const FAILED: &str = "decryption failed (key unavailable or data corrupt)";
+    let plaintext = if let Some(rest) = stored.strip_prefix(PREFIX_V2) {
+        let (kid, b64) = rest
+            .split_once(':')
+            .ok_or_else(|| "malformed encrypted value".to_string())?;
+        let key = keys
+            .find(kid)
+            .ok_or_else(|| format!("decryption failed (unknown key {})", kid))?;
+        let data = B64.decode(b64).map_err(|e| e.to_string())?;
+        open(key, &data).map_err(|_| FAILED.to_string())?
+    } else if let Some(b64) = stored.strip_prefix(PREFIX_V1) {
+        // v1 values don't name their key; it's normally the oldest.
+        let data = B64.decode(b64).map_err(|e| e.to_string())?;
+        keys.0
+            .iter()
+            .rev()
+            .find_map(|key| open(key, &data).ok())
+            .ok_or_else(|| FAILED.to_string())?
+    } else {
+        return Err("value is not encrypted".to_string());
+    };
+    String::from_utf8(plaintext).map_err(|e| e.to_string())
+}
+
+/// AES-256-GCM with a random nonce; returns nonce || ciphertext.
+fn seal(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, String> {
     let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
     let mut nonce = [0u8; 12];
     getrandom::getrandom(&mut nonce).map_err(|e| e.to_string())?;
     let ciphertext = cipher
-        .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
         .map_err(|e| e.to_string())?;
     let mut combined = nonce.to_vec();
     combined.extend_from_slice(&ciphertext);
-    Ok(format!("{}{}", PREFIX, B64.encode(combined)))
+    Ok(combined)
 }
 
-/// Decrypts a value produced by `encrypt`. Errors if the key is wrong/missing.
-pub fn decrypt(key: &[u8; 32], stored: &str) -> Result<String, String> {
-    let b64 = stored
-        .strip_prefix(PREFIX)
-        .ok_or_else(|| "value is not encrypted".to_string())?;
-    let data = B64.decode(b64).map_err(|e| e.to_string())?;
+/// Inverse of `seal`.
+fn open(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, String> {
     if data.len() < 12 {
         return Err("ciphertext too short".to_string());
     }
     let (nonce, ciphertext) = data.split_at(12);
     let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
-    let plaintext = cipher
+    cipher
         .decrypt(Nonce::from_slice(nonce), ciphertext)
-        .map_err(|_| "decryption failed (key unavailable or data corrupt)".to_string())?;
-    String::from_utf8(plaintext).map_err(|e| e.to_string())
+        .map_err(|_| "decryption failed".to_string())
+}
+
+// ── Vault mode ──────────────────────────────────────────────────────────────
+
+/// True when the data key is passphrase-protected.
+pub fn vault_enabled(app: &AppHandle) -> bool {
+    vault_path(app).is_some_and(|p| p.exists())
+}
+
+/// True when the vault's data key is currently in memory.
+pub fn vault_unlocked(app: &AppHandle) -> bool {
+    cached_key(app).is_some()
+}
+
+fn unlocked_key(app: &AppHandle) -> Result<KeyRing, String> {
+    cached_key(app).ok_or_else(|| VAULT_LOCKED.to_string())
+}
+
+fn cached_key(app: &AppHandle) -> Option<KeyRing> {
+    let cache = app.try_state::<KeyCache>()?;
+    let mut slot = cache.0.lock().ok()?;
+    match slot.as_ref() {
+        Some(u) if u.until.is_none_or(|t| Instant::now() < t) => Some(u.keys.clone()),
+        _ => {
+            *slot = None; // timed out: drop (and zero) the key
+            None
+        }
+    }
+}
+
+fn cache_key(app: &AppHandle, keys: KeyRing, unlock_for: Option<Duration>) {
+    if let Some(cache) = app.try_state::<KeyCache>() {
+        if let Ok(mut slot) = cache.0.lock() {
+            *slot = Some(Unlocked {
+                keys,
+                until: unlock_for.map(|d| Instant::now() + d),
+            });
+        }
+    }
+}
+
+/// Swaps in a new ring without touching the unlock deadline.
+fn replace_cached_key(app: &AppHandle, keys: KeyRing) {
+    if let Some(cache) = app.try_state::<KeyCache>() {
+        if let Ok(mut slot) = cache.0.lock() {
+            let until = slot.as_ref().and_then(|u| u.until);
+            *slot = Some(Unlocked { keys, until });
+        }
+    }
+}
+
+fn check_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!(
+            "Passphrase must be at least {} characters",
+            MIN_PASSPHRASE_LEN
+        ));
+    }
+    Ok(())
+}
+
+/// Argon2id: a 256-bit key from a passphrase, salt and cost parameters.
+pub(crate) fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> Result<Zeroizing<[u8; 32]>, String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32)).map_err(|e| e.to_string())?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+fn derive_kek(
+    passphrase: &str,
+    salt: &[u8],
+    vault: &VaultFile,
+) -> Result<Zeroizing<[u8; 32]>, String> {
+    derive_key(passphrase, salt, vault.m_cost, vault.t_cost, vault.p_cost)
+}
+
+/// Seals the key ring bytes under a fresh salt and the current Argon2id cost.
+fn wrap_key(data_key: &[u8], passphrase: &str) -> Result<VaultFile, String> {
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt).map_err(|e| e.to_string())?;
+    let mut vault = VaultFile {
+        version: 1,
+        m_cost: ARGON2_M_COST,
+        t_cost: ARGON2_T_COST,
+        p_cost: ARGON2_P_COST,
+        salt: B64.encode(salt),
+        wrapped_key: String::new(),
+    };
+    let kek = derive_kek(passphrase, &salt, &vault)?;
+    vault.wrapped_key = B64.encode(seal(&kek, data_key)?);
+    Ok(vault)
+}
+
+fn unwrap_key(vault: &VaultFile, passphrase: &str) -> Result<KeyRing, String> {
+    let salt = B64.decode(&vault.salt).map_err(|e| e.to_string())?;
+    let wrapped = B64.decode(&vault.wrapped_key).map_err(|e| e.to_string())?;
+    let kek = derive_kek(passphrase, &salt, vault)?;
+    let bytes = Zeroizing::new(open(&kek, &wrapped).map_err(|_| "Wrong passphrase".to_string())?);
+    KeyRing::from_bytes(&bytes).map_err(|_| "vault is corrupt".to_string())
+}
+
+fn read_vault(app: &AppHandle) -> Result<VaultFile, String> {
+    let path = data_dir(app)?.join(VAULT_FILE);
+    let json = std::fs::read_to_string(path).map_err(|_| "Vault is not enabled".to_string())?;
+    serde_json::from_str(&json).map_err(|e| format!("vault is corrupt: {}", e))
+}
+
+fn write_vault(app: &AppHandle, vault: &VaultFile) -> Result<(), String> {
+    let path = data_dir(app)?.join(VAULT_FILE);
+    let json = serde_json::to_vec(vault).map_err(|e| e.to_string())?;
+    write_private(&path, &json)
+}
+
+/// Moves the existing data key into a passphrase-protected vault and removes
+/// it from the key store. The vault starts out unlocked. If the key store
+/// can't drop its copy, the vault is taken away again and this fails.
+pub fn enable_vault(
+    app: &AppHandle,
+    passphrase: &str,
+    unlock_for: Option<Duration>,
+) -> Result<(), String> {
+    if vault_enabled(app) {
+        return Err("Vault is already enabled".to_string());
+    }
+    check_passphrase(passphrase)?;
+    let keys = get_or_create_key(app)?;
+    let dir = data_dir(app)?;
+    write_vault(app, &wrap_key(&keys.to_bytes(), passphrase)?)?;
+    if let Err(e) = keystore::delete(&dir) {
+        // Part of the key store may be gone: put the key back before
+        // dropping the vault, so it is never left in neither.
+        if keystore::store(&dir, &keys.to_bytes(), keystore::Backend::from_env()).is_ok() {
+            let _ = std::fs::remove_file(dir.join(VAULT_FILE));
+        }
+        return Err(format!("Couldn't remove the unprotected key: {}", e));
+    }
+    cache_key(app, keys, unlock_for);
+    Ok(())
+}
+
+/// Turns vault mode off, handing the data key back to the key store.
+pub fn disable_vault(app: &AppHandle, passphrase: &str) -> Result<(), String> {
+    let keys = unwrap_key(&read_vault(app)?, passphrase)?;
+    keystore::store(
+        &data_dir(app)?,
+        &keys.to_bytes(),
+        keystore::Backend::from_env(),
+    )?;
+    if let Some(path) = vault_path(app) {
+        std::fs::remove_file(path).map_err(|e| e.to_string())?;
+    }
+    lock_vault(app);
+    Ok(())
+}
+
+/// Unwraps the data key and keeps it in memory for `unlock_for`.
+pub fn unlock_vault(
+    app: &AppHandle,
+    passphrase: &str,
+    unlock_for: Option<Duration>,
+) -> Result<(), String> {
+    let keys = unwrap_key(&read_vault(app)?, passphrase)?;
+    cache_key(app, keys, unlock_for);
+    Ok(())
+}
+
+/// Forgets the in-memory data key.
+pub fn lock_vault(app: &AppHandle) {
+    if let Some(cache) = app.try_state::<KeyCache>() {
+        if let Ok(mut slot) = cache.0.lock() {
+            *slot = None;
+        }
+    }
+}
+
+/// Re-wraps the data key under a new passphrase. Stored items are untouched.
+pub fn change_passphrase(app: &AppHandle, old: &str, new: &str) -> Result<(), String> {
+    check_passphrase(new)?;
+    let keys = unwrap_key(&read_vault(app)?, old)?;
+    write_vault(app, &wrap_key(&keys.to_bytes(), new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vault_wraps_and_rewraps_the_same_key_ring() {
+        let keys = KeyRing::from_bytes(&[7u8; 32]).unwrap();
+        let vault = wrap_key(&keys.to_bytes(), "correct horse").unwrap();
+        let unwrapped = unwrap_key(&vault, "correct horse").unwrap();
+        assert_eq!(unwrapped.current_id(), keys.current_id());
+        assert_eq!(
+            unwrap_key(&vault, "wrong horse").err().unwrap(),
+            "Wrong passphrase"
+        );
+
+        // A passphrase change yields a different vault around the same ring,
+        // so items encrypted before the change still decrypt.
+        let item = encrypt(&keys, "hunter2").unwrap();
+        let rewrapped = wrap_key(&unwrapped.to_bytes(), "battery staple").unwrap();
+        assert_ne!(rewrapped.salt, vault.salt);
+        let keys = unwrap_key(&rewrapped, "battery staple").unwrap();
+        assert_eq!(decrypt(&keys, &item).unwrap(), "hunter2");
+    }
+
+    #[test]
+    fn rotated_ring_still_reads_retired_keys() {
+        let old = KeyRing::from_bytes(&[1u8; 32]).unwrap();
+        // A value from before key ids existed, and one from the old key.
+        let v1 = format!(
+            "{}{}",
+            PREFIX_V1,
+            B64.encode(seal(old.current(), b"legacy").unwrap())
+        );
+        let v2 = encrypt(&old, "before").unwrap();
+        assert!(v2.starts_with(&format!("enc:v2:{}:", old.current_id())));
+
+        let new = old.rotated().unwrap();
+        assert_ne!(new.current_id(), old.current_id());
+        assert_eq!(decrypt(&new, &v1).unwrap(), "legacy");
+        assert_eq!(decrypt(&new, &v2).unwrap(), "before");
+
+        let fresh = encrypt(&new, "after").unwrap();
+        assert!(fresh.starts_with(&format!("enc:v2:{}:", new.current_id())));
+        // The retired ring can't read what the new key wrote.
+        assert!(decrypt(&old, &fresh).unwrap_err().contains("unknown key"));
+
+        // Rings round-trip through their stored form, current key first.
+        let reloaded = KeyRing::from_bytes(&new.to_bytes()).unwrap();
+        assert_eq!(reloaded.current_id(), new.current_id());
+        assert_eq!(decrypt(&reloaded, &v1).unwrap(), "legacy");
+        assert!(KeyRing::from_bytes(&[0u8; 33]).is_err());
+    }
 }
@@ -1,6 +1,12 @@
+use crate::learning;
+use crate::migrations;
+use crate::query::{self, Query};
+use crate::secrets;
+use rusqlite::types::Value;
 use rusqlite::{params, Connection, Result};
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Manager};
 
 pub struct ClipboardDB {
@@ -17,6 +23,10 @@ pub struct ClipboardItem {
     pub raw_content: String,
     pub category: Option<String>,
     pub groups: Vec<String>,
+    // How sure the classifier was of each automatic group it attached, by
+    // group name; groups added by hand have no entry.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub group_confidence: BTreeMap<String, f32>,
     pub is_permanent: bool,
     pub created_at: String,
     pub description: Option<String>,
@@ -24,6 +34,237 @@ pub struct ClipboardItem {
     // Marked sensitive: content is encrypted at rest and masked in the UI.
     #[serde(default)]
     pub is_sensitive: bool,
+    // System selection the item was captured from: 'clipboard' or 'primary'
+    // (X11/Wayland middle-click selection).
+    #[serde(default = "default_selection")]
+    pub selection: String,
+    // HTML/RTF markup of 'html' and 'rtf' items. Only loaded for backups; the
+    // UI works from the plain-text raw_content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rich_content: Option<String>,
+    // Frontmost application at capture time (bundle id, WM_CLASS or
+    // executable name); None for manual items and older captures.
+    #[serde(default)]
+    pub source_app: Option<String>,
+    // When the pin or description last changed; None if never edited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+/// A freshly captured clipboard payload, as stored by
+/// `insert_auto_grouped_content`.
+pub struct NewItem<'a> {
+    /// 'text', 'html', 'rtf', 'image' or 'files'.
+    pub content_type: &'a str,
+    /// Plain text (the only part search indexes), blob hash, or JSON path list.
+    pub content: String,
+    /// Markup for 'html' / 'rtf' items.
+    pub rich_content: Option<String>,
+    pub is_sensitive: bool,
+    /// 'clipboard' or 'primary'.
+    pub selection: &'a str,
+    /// See `crate::frontmost_app`.
+    pub source_app: Option<String>,
+    /// For sensitive items, the text with only its secrets masked.
+    pub masked_preview: Option<String>,
+    /// Secrets found in the text, recorded in `secret_detections`.
+    pub secrets: &'a [secrets::Finding],
+}
+
+/// Position in the history timeline: the (created_at, id) of the last item on
+/// the previous page.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct HistoryCursor {
+    pub created_at: String,
+    pub id: i64,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct HistoryPage {
+    pub items: Vec<ClipboardItem>,
+    /// None on the last page.
+    pub next_cursor: Option<HistoryCursor>,
+}
+
+/// How long a regex search may scan before returning what it has found, so a
+/// slow pattern over a large history can't freeze the popup.
+const REGEX_TIME_BUDGET: Duration = Duration::from_millis(500);
+
+/// Result cap for regex searches (they are listed by date, not ranked).
+const REGEX_MAX_RESULTS: usize = 100;
+
+/// Retention only ever removes "ephemeral" history rows: NOT pinned and NOT put
+/// in a user-defined (non-system) group by the user. Memberships attached
+/// automatically (app rules, similar items, learned groups) carry an
+/// `item_group_confidence` row and don't count. Pinned and hand-grouped items
+/// are kept forever.
+const NOT_CURATED: &str = "is_permanent = 0
+     AND NOT EXISTS (
+         SELECT 1 FROM item_groups ig
+         JOIN groups g ON g.id = ig.group_id
+         WHERE ig.item_id = history.id AND g.is_system = 0
+           AND NOT EXISTS (
+               SELECT 1 FROM item_group_confidence c
+               WHERE c.item_id = ig.item_id AND c.group_id = ig.group_id
+           )
+     )";
+
+/// ANDed WHERE conditions with positional `?` parameters, for queries on
+/// `history h`.
+struct HistoryFilter {
+    conditions: Vec<String>,
+    values: Vec<Value>,
+    /// Free-text query to fuzzy-rank results by, if any.
+    fuzzy_query: Option<String>,
+}
+
+impl HistoryFilter {
+    fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", self.conditions.join(" AND "))
+        }
+    }
+}
+
+/// Reads a per-application rule setting (`app_group_rules`: app → group name,
+/// `retention_app_days`: app → days). Both are JSON objects keyed by source app
+/// as recorded in `history.source_app`, matched case-insensitively. An invalid
+/// value is ignored rather than failing the capture or sweep.
+fn parse_app_rules<T: serde::de::DeserializeOwned>(
+    key: &str,
+    json: Option<&str>,
+) -> Vec<(String, T)> {
+    let Some(json) = json.filter(|j| !j.trim().is_empty()) else {
+        return Vec::new();
+    };
+    match serde_json::from_str::<HashMap<String, T>>(json) {
+        Ok(rules) => rules.into_iter().collect(),
+        Err(e) => {
+            eprintln!("DB: ignoring invalid {} setting: {}", key, e);
+            Vec::new()
+        }
+    }
+}
+
+fn default_selection() -> String {
+    "clipboard".to_string()
+}
+
+/// Column list for queries that build a `ClipboardItem` via `item_from_row`;
+/// the history table must be aliased `h`.
+const ITEM_COLUMNS: &str = "h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0), COALESCE(h.selection, 'clipboard'), h.rich_content, h.source_app, h.updated_at, h.masked_preview";
+
+/// Maps a row selected with `ITEM_COLUMNS`; `groups` and `group_confidence`
+/// are left empty. For the UI, encrypted content never leaves the backend: it
+/// is replaced by the masked preview (the text with its secrets hidden) or
+/// blanked, and rich markup is omitted; `for_backup` returns everything as
+/// stored.
+fn item_from_row(row: &rusqlite::Row<'_>, for_backup: bool) -> Result<ClipboardItem> {
+    let is_sensitive: bool = row.get(8)?;
+    let raw_content: String = if is_sensitive && !for_backup {
+        row.get::<_, Option<String>>(13)?.unwrap_or_default()
+    } else {
+        row.get(2)?
+    };
+    Ok(ClipboardItem {
+        id: row.get(0)?,
+        content_type: row.get(1)?,
+        raw_content,
+        category: row.get(3)?,
+        groups: Vec::new(),
+        group_confidence: BTreeMap::new(),
+        is_permanent: row.get(4)?,
+        created_at: row.get(5)?,
+        description: row.get(6)?,
+        is_manual: row.get(7)?,
+        is_sensitive,
+        selection: row.get(9)?,
+        rich_content: if for_backup { row.get(10)? } else { None },
+        source_app: row.get(11)?,
+        updated_at: row.get(12)?,
+    })
+}
+
+/// When an item's pin or description last changed, for "newest" restores.
+fn edited_at_of(item: &ClipboardItem) -> String {
+    db_time(item.updated_at.as_deref().unwrap_or(&item.created_at))
+}
+
+/// Puts a timestamp in SQLite's UTC `YYYY-MM-DD HH:MM:SS` form so it compares
+/// as a string: backups written elsewhere may carry RFC 3339 times, where the
+/// `T` separator would sort after any space-separated time on the same day.
+fn db_time(at: &str) -> String {
+    match chrono::DateTime::parse_from_rfc3339(at) {
+        Ok(at) => at.with_timezone(&chrono::Utc).format("%Y-%m-%d %H:%M:%S").to_string(),
+        Err(_) => at.replacen('T', " ", 1),
+    }
+}
+
+fn non_empty(text: &Option<String>) -> Option<&str> {
+    text.as_deref().filter(|t| !t.is_empty())
+}
+
+/// Fills in `groups` and `group_confidence` for each item. Chunked to stay
+/// under SQLite's bound variable limit (usually 999).
+fn attach_groups(conn: &Connection, items: &mut [ClipboardItem]) -> Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    let item_ids: Vec<i64> = items.iter().map(|i| i.id).collect();
+    let mut groups_map: HashMap<i64, Vec<(String, Option<f32>)>> = HashMap::new();
+
+    for chunk in item_ids.chunks(900) {
+        let placeholders: Vec<String> = chunk.iter().map(|_| "?".to_string()).collect();
+        let sql = format!(
+            "SELECT ig.item_id, g.name, c.confidence
+             FROM item_groups ig
+             JOIN groups g ON ig.group_id = g.id
+             LEFT JOIN item_group_confidence c
+                ON c.item_id = ig.item_id AND c.group_id = ig.group_id
+             WHERE ig.item_id IN ({})",
+            placeholders.join(",")
+        );
+
+        let mut stmt = conn.prepare(&sql)?;
+        let mut group_rows = stmt.query(rusqlite::params_from_iter(chunk.iter()))?;
+        while let Some(row) = group_rows.next()? {
+            let item_id: i64 = row.get(0)?;
+            let group_name: String = row.get(1)?;
+            let confidence: Option<f64> = row.get(2)?;
+            groups_map
+                .entry(item_id)
+                .or_default()
+                .push((group_name, confidence.map(|c| c as f32)));
+        }
+    }
+
+    for item in items.iter_mut() {
+        if let Some(g_list) = groups_map.remove(&item.id) {
+            for (name, confidence) in g_list {
+                if let Some(confidence) = confidence {
+                    item.group_confidence.insert(name.clone(), confidence);
+                }
+                item.groups.push(name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A secret the scanner found in a captured clip (see secrets.rs). Only the
+/// detector's name is kept, never the secret.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct SecretDetection {
+    pub id: i64,
+    /// None once the item has been deleted.
+    pub item_id: Option<i64>,
+    pub detector: String,
+    /// Whether the item was stored masked (auto-masking was on).
+    pub masked: bool,
+    pub source_app: Option<String>,
+    pub detected_at: String,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
@@ -40,6 +281,57 @@ pub struct BackupData {
     pub exported_at: String,
 }
 
+/// What a restore would do, worked out without writing anything.
+#[derive(serde::Serialize, Default, Debug)]
+pub struct RestoreReport {
+    /// Items that would be added.
+    pub new_items: u64,
+    /// Backup items already present with the same pin, description and groups
+    /// (including repeats within the backup).
+    pub duplicates: u64,
+    /// Backup items already present whose pin, description or groups differ.
+    pub conflicts: u64,
+    /// The first `CONFLICT_SAMPLES` conflicts, for display.
+    pub conflict_samples: Vec<RestoreConflict>,
+    /// Group names the backup would create.
+    pub new_groups: Vec<String>,
+    /// "replace" only: what would be wiped first.
+    pub removed_items: u64,
+    pub removed_groups: Vec<String>,
+    /// Image blobs the backup would add / already has locally.
+    pub new_images: u64,
+    pub existing_images: u64,
+    /// Content hashes of backup items counted so far, so a repeat within the
+    /// backup counts as a duplicate of the first rather than as new.
+    #[serde(skip)]
+    seen: HashSet<u64>,
+}
+
+const CONFLICT_SAMPLES: usize = 50;
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct RestoreConflict {
+    pub existing_id: i64,
+    pub content_type: String,
+    /// Start of the text; empty for sensitive items and images.
+    pub preview: String,
+    /// Which of "pinned", "description" and "groups" differ.
+    pub fields: Vec<&'static str>,
+    /// Whether the backup's pin and description would replace the local ones
+    /// (only in "newest" mode, when the backup's edit is newer). Groups are
+    /// always combined.
+    pub backup_wins: bool,
+}
+
+/// One row of the content-addressed `blobs` table.
+#[derive(Clone, Debug)]
+pub struct Blob {
+    pub hash: String,
+    pub mime: String,
+    pub data: Vec<u8>,
+    pub thumb: Option<Vec<u8>>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct Snippet {
     pub id: i64,
@@ -72,22 +364,6 @@ fn fuzzy_score(query: &str, text: &str) -> i32 {
     score
 }
 
-/// Builds an FTS5 MATCH query from free user text. Each alphanumeric token
-/// becomes a quoted prefix term so partial words match. Returns None when there
-/// are no usable tokens (caller falls back to LIKE).
-fn build_fts_query(input: &str) -> Option<String> {
-    let tokens: Vec<String> = input
-        .split(|c: char| !c.is_alphanumeric())
-        .filter(|t| !t.is_empty())
-        .map(|t| format!("\"{}\"*", t.replace('"', "\"\"")))
-        .collect();
-    if tokens.is_empty() {
-        None
-    } else {
-        Some(tokens.join(" "))
-    }
-}
-
 impl ClipboardDB {
     pub fn new(app_handle: &AppHandle) -> Result<Self> {
         let conn = {
@@ -112,7 +388,12 @@ impl ClipboardDB {
                 Self::open_fallback_connection()?
             }
         };
+        Self::from_connection(conn)
+    }
 
+    /// Sets up an opened database: pragmas, migrations, SQL functions and the
+    /// full-text index.
+    fn from_connection(mut conn: Connection) -> Result<Self> {
         // Performance pragmas: WAL + relaxed sync, in-memory temp tables, an
         // 8 MB page cache, 256 MB memory-mapped I/O, and bounded WAL checkpoints.
         conn.execute_batch(
@@ -125,145 +406,11 @@ impl ClipboardDB {
              PRAGMA wal_autocheckpoint = 256;",
         )?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS groups (
-                id INTEGER PRIMARY KEY,
-                name TEXT UNIQUE NOT NULL,
-                is_system BOOLEAN DEFAULT 0
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS history (
-                id INTEGER PRIMARY KEY,
-                content_type TEXT NOT NULL,
-                raw_content TEXT NOT NULL,
-                category TEXT,
-                is_permanent BOOLEAN DEFAULT 0,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
-
-        // Migration: Fix incorrect item_groups foreign key reference if it exists
-        let needs_fix = {
-            let mut stmt = conn.prepare("PRAGMA foreign_key_list('item_groups')")?;
-            let mut rows = stmt.query([])?;
-            let mut found = false;
-            while let Some(row) = rows.next()? {
-                let referenced_table: String = row.get(2)?;
-                if referenced_table == "clipboard_items" {
-                    found = true;
-                    break;
-                }
-            }
-            found
-        };
-
-        if needs_fix {
-            println!("DB: Fixing incorrect item_groups schema...");
-            conn.execute("DROP TABLE item_groups", [])?;
-        }
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS item_groups (
-                item_id INTEGER NOT NULL,
-                group_id INTEGER NOT NULL,
-                PRIMARY KEY (item_id, group_id),
-                FOREIGN KEY(item_id) REFERENCES history(id) ON DELETE CASCADE,
-                FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS item_group_confidence (
-                item_id INTEGER NOT NULL,
-                group_id INTEGER NOT NULL,
-                confidence REAL NOT NULL,
-                PRIMARY KEY (item_id, group_id),
-                FOREIGN KEY(item_id) REFERENCES history(id) ON DELETE CASCADE,
-                FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS app_meta (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS snippets (
-                id INTEGER PRIMARY KEY,
-                name TEXT UNIQUE NOT NULL,
-                body TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
-
-        // Content-addressed binary store for clipboard images. A history row of
-        // content_type 'image' references a blob by storing its hash in
-        // raw_content; identical images are deduplicated by hash.
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS blobs (
-                hash TEXT PRIMARY KEY,
-                mime TEXT NOT NULL,
-                data BLOB NOT NULL,
-                thumb BLOB,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
-
-        // Migrate: add description, is_manual and is_sensitive columns if not present
-        let _ = conn.execute("ALTER TABLE history ADD COLUMN description TEXT", []);
-        let _ = conn.execute("ALTER TABLE history ADD COLUMN is_manual BOOLEAN DEFAULT 0", []);
-        let _ = conn.execute("ALTER TABLE history ADD COLUMN is_sensitive BOOLEAN DEFAULT 0", []);
-
-        // Migrate existing categories into groups table
-        conn.execute(
-            "INSERT OR IGNORE INTO groups (name) 
-             SELECT DISTINCT category FROM history WHERE category IS NOT NULL",
-            [],
-        )?;
-
-        // Migrate existing category column to item_groups
-        match conn.execute(
-            "INSERT OR IGNORE INTO item_groups (item_id, group_id)
-             SELECT h.id, g.id 
-             FROM history h
-             JOIN groups g ON h.category = g.name
-             WHERE h.category IS NOT NULL",
-            [],
-        ) {
-            Ok(_) => {}                                         // Migration successful
-            Err(e) => println!("DB: Migration warning: {}", e), // Log warning but verify app continues
-        }
-
-        // Add index for performance
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_created_at ON history(created_at DESC)",
-            [],
-        )?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_item_groups_item_id ON item_groups(item_id)",
-            [],
-        )?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_item_groups_group_id ON item_groups(group_id)",
-            [],
-        )?;
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_item_group_confidence_item_id ON item_group_confidence(item_id)",
-            [],
-        )?;
+        // Schema: versioned migrations (see migrations.rs). Refuses a database
+        // written by a newer build instead of falling back to another one.
+        migrations::run(&mut conn)?;
+        query::register_sql_functions(&conn)?;
+        Self::seed_learning(&mut conn)?;
 
         // Full-text search index (FTS5), mirroring `history` via triggers. May be
         // absent on some SQLite builds; degrade gracefully to LIKE search.
@@ -402,20 +549,9 @@ impl ClipboardDB {
         if changed {
             if wipe_on_reboot {
                 // Clear ALL ungrouped + unpinned items (every content type,
-                // including images/files). Pinned items and items in a user
-                // (non-system) group are always kept.
-                tx.execute(
-                    "DELETE FROM history
-                     WHERE is_permanent = 0
-                       AND NOT EXISTS (
-                         SELECT 1
-                         FROM item_groups ig
-                         JOIN groups g ON g.id = ig.group_id
-                         WHERE ig.item_id = history.id
-                           AND g.is_system = 0
-                       )",
-                    [],
-                )?;
+                // including images/files). Pinned items and items the user put
+                // in a user (non-system) group are always kept.
+                tx.execute(&format!("DELETE FROM history WHERE {NOT_CURATED}"), [])?;
                 wiped = true;
             }
             // Always advance the boot marker so each reboot is detected once,
@@ -468,409 +604,222 @@ impl ClipboardDB {
         Ok(())
     }
 
-    pub fn export_group(&self, name: String, path: std::path::PathBuf) -> Result<()> {
-        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
-
-        let mut stmt = conn.prepare(
-            "SELECT h.raw_content, h.description
-             FROM history h
-             JOIN item_groups ig ON h.id = ig.item_id
-             JOIN groups g ON ig.group_id = g.id
-             WHERE g.name = ?1
-             ORDER BY h.created_at DESC",
-        )?;
-
-        let rows: Vec<(String, Option<String>)> = stmt
-            .query_map(params![name], |row| Ok((row.get(0)?, row.get(1)?)))?
-            .filter_map(|r| r.ok())
-            .collect();
-
-        let total = rows.len();
-        let exported_at = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        let divider = "═".repeat(72);
-        let thin = "─".repeat(72);
-
-        let mut out = String::new();
-        out.push('\u{FEFF}'); // UTF-8 BOM so text editors detect encoding correctly
-
-        out.push_str(&format!("{divider}\n"));
-        out.push_str(&format!("  ORTU GROUP EXPORT  —  {name}\n"));
-        out.push_str(&format!("  Exported : {exported_at}\n"));
-        out.push_str(&format!("  Items    : {total}\n"));
-        out.push_str(&format!("{divider}\n"));
-
-        for (i, (content, description)) in rows.iter().enumerate() {
-            if i > 0 {
-                out.push_str(&format!("{thin}\n"));
-            }
-
-            if let Some(desc) = description {
-                if !desc.trim().is_empty() {
-                    out.push_str(&format!("Description: {desc}\n"));
-                }
-            }
-
-            out.push('\n');
-            for line in content.lines() {
-                out.push_str(&format!("  {line}\n"));
-            }
-            out.push('\n');
-        }
-
-        out.push_str(&format!("{divider}\n"));
-        out.push_str(&format!("  End of export  —  {name}  ({total} items)\n"));
-        out.push_str(&format!("{divider}\n"));
-
-        std::fs::write(path, out)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-        Ok(())
-    }
-
-    pub fn export_all_txt(&self, path: std::path::PathBuf) -> Result<()> {
-        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
-
-        let mut stmt = conn.prepare(
-            "SELECT h.raw_content, h.description
-             FROM history h
-             ORDER BY h.created_at DESC",
-        )?;
-
-        let rows: Vec<(String, Option<String>)> = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
-            .filter_map(|r| r.ok())
-            .collect();
-
-        let total = rows.len();
-        let exported_at = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        let divider = "═".repeat(72);
-        let thin = "─".repeat(72);
-
-        let mut out = String::new();
-        out.push('\u{FEFF}'); // UTF-8 BOM so text editors detect encoding correctly
-
-        out.push_str(&format!("{divider}\n"));
-        out.push_str("  ORTU CLIPBOARD EXPORT\n");
-        out.push_str(&format!("  Exported : {exported_at}\n"));
-        out.push_str(&format!("  Total    : {total} item(s)\n"));
-        out.push_str(&format!("{divider}\n"));
-
-        for (i, (content, description)) in rows.iter().enumerate() {
-            if i > 0 {
-                out.push_str(&format!("{thin}\n"));
-            }
-
-            if let Some(desc) = description {
-                if !desc.trim().is_empty() {
-                    out.push_str(&format!("Description: {desc}\n"));
-                }
-            }
-
-            out.push('\n');
-            for line in content.lines() {
-                out.push_str(&format!("  {line}\n"));
-            }
-            out.push('\n');
-        }
-
-        out.push_str(&format!("{divider}\n"));
-        out.push_str(&format!("  End of export — {total} item(s)\n"));
-        out.push_str(&format!("{divider}\n"));
-
-        std::fs::write(path, out)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-        Ok(())
-    }
-
-    pub fn import_group(&self, name: String, path: std::path::PathBuf) -> Result<()> {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-        let items: Vec<&str> = content.split("\n---\n").collect();
-
-        // Ensure group exists
-        let _ = self.create_group(name.clone());
-
-        for item in items {
-            if !item.trim().is_empty() {
-                let _ = self.insert_item(item.to_string(), Some(name.clone()));
-            }
-        }
-        Ok(())
-    }
-
     pub fn insert_item(&self, content: String, category: Option<String>) -> Result<i64> {
         let mut groups: Vec<(String, f32)> = Vec::new();
-        if let Some(cat) = category.clone() {
+        if let Some(cat) = category {
             groups.push((cat, 1.0));
         }
-        self.insert_item_with_groups("text", content, category, groups, false, false)
+        let item = NewItem {
+            content_type: "text",
+            content,
+            rich_content: None,
+            is_sensitive: false,
+            selection: "clipboard",
+            source_app: None,
+            masked_preview: None,
+            secrets: &[],
+        };
+        self.insert_item_with_groups(item, groups, false).map(|(id, _)| id)
     }
 
-    pub fn insert_auto_grouped_content(
-        &self,
-        content_type: &str,
-        content: String,
-        groups: Vec<(String, f32)>,
-        is_sensitive: bool,
-    ) -> Result<i64> {
-        let primary = groups.first().map(|(name, _)| name.clone());
-        self.insert_item_with_groups(content_type, content, primary, groups, true, is_sensitive)
+    /// Stores a capture; returns the row id and whether the row is new (false
+    /// when existing content was bumped).
+    pub fn insert_auto_grouped_content(&self, item: NewItem<'_>, groups: Vec<(String, f32)>) -> Result<(i64, bool)> {
+        self.insert_item_with_groups(item, groups, true)
     }
 
+    /// Inserts (or bumps, when the content already exists) a history row. The
+    /// first group becomes the row's primary category. Automatic groups below
+    /// the `min_group_confidence` setting are left out. Items from an app with
+    /// an `app_group_rules` entry also join that (user) group. Returns the row
+    /// id and whether it was inserted.
     fn insert_item_with_groups(
         &self,
-        content_type: &str,
-        content: String,
-        primary_category: Option<String>,
-        groups: Vec<(String, f32)>,
+        item: NewItem<'_>,
+        mut groups: Vec<(String, f32)>,
         system_groups: bool,
-        is_sensitive: bool,
-    ) -> Result<i64> {
+    ) -> Result<(i64, bool)> {
         let mut conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
         let tx = conn.transaction()?;
 
+        if system_groups {
+            let min_confidence = Self::get_meta_value(&tx, "min_group_confidence")?
+                .and_then(|v| v.trim().parse::<f32>().ok())
+                .unwrap_or(0.0);
+            groups.retain(|(_, confidence)| *confidence >= min_confidence);
+        }
+        let primary_category = groups.first().map(|(name, _)| name.clone());
+
         let existing_item_id: Option<i64> = tx
             .query_row(
                 "SELECT id FROM history WHERE raw_content = ?1 ORDER BY created_at DESC LIMIT 1",
-                params![content],
+                params![item.content],
                 |row| row.get(0),
             )
             .ok();
 
+        // Re-capturing existing content bumps it. An explicit copy upgrades a
+        // PRIMARY-only row to 'clipboard'; a later highlight never downgrades.
+        // Likewise a capture without markup keeps the row's HTML/RTF, unless
+        // it is sensitive. The source app follows the latest capture.
         let item_id = if let Some(id) = existing_item_id {
             tx.execute(
                 "UPDATE history
                  SET created_at = CURRENT_TIMESTAMP,
                      category = COALESCE(?1, category),
-                     content_type = ?2,
-                     is_sensitive = ?3
+                     content_type = CASE WHEN ?3 OR ?6 IS NOT NULL THEN ?2 ELSE content_type END,
+                     is_sensitive = ?3,
+                     sensitive_at = CASE WHEN ?3 THEN CURRENT_TIMESTAMP END,
+                     selection = CASE WHEN ?5 = 'clipboard' THEN 'clipboard' ELSE selection END,
+                     rich_content = CASE WHEN ?3 THEN NULL ELSE COALESCE(?6, rich_content) END,
+                     source_app = COALESCE(?7, source_app),
+                     masked_preview = ?8
                  WHERE id = ?4",
-                params![primary_category, content_type, is_sensitive, id],
+                params![
+                    primary_category,
+                    item.content_type,
+                    item.is_sensitive,
+                    id,
+                    item.selection,
+                    item.rich_content,
+                    item.source_app,
+                    item.masked_preview
+                ],
             )?;
             id
         } else {
             tx.execute(
-                "INSERT INTO history (content_type, raw_content, category, is_manual, is_sensitive) VALUES (?1, ?2, ?3, 0, ?4)",
-                params![content_type, content, primary_category, is_sensitive],
+                "INSERT INTO history (content_type, raw_content, category, is_manual, is_sensitive, sensitive_at, selection, rich_content, source_app, masked_preview) VALUES (?1, ?2, ?3, 0, ?4, CASE WHEN ?4 THEN CURRENT_TIMESTAMP END, ?5, ?6, ?7, ?8)",
+                params![
+                    item.content_type,
+                    item.content,
+                    primary_category,
+                    item.is_sensitive,
+                    item.selection,
+                    item.rich_content,
+                    item.source_app,
+                    item.masked_preview
+                ],
             )?;
             tx.last_insert_rowid()
         };
 
+        for finding in item.secrets {
+            tx.execute(
+                "INSERT INTO secret_detections (item_id, detector, span_start, span_end, masked, source_app)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    item_id,
+                    finding.detector,
+                    finding.start as i64,
+                    finding.end as i64,
+                    item.is_sensitive,
+                    item.source_app
+                ],
+            )?;
+        }
+
+        if let Some(app) = item.source_app.as_deref() {
+            let rules = Self::get_meta_value(&tx, "app_group_rules")?;
+            let group = parse_app_rules::<String>("app_group_rules", rules.as_deref())
+                .into_iter()
+                .find(|(rule_app, name)| rule_app.eq_ignore_ascii_case(app) && !name.trim().is_empty());
+            if let Some((_, group_name)) = group {
+                let group_id = Self::ensure_group_with_type(&tx, group_name.trim(), false)?;
+                let added = tx.execute(
+                    "INSERT OR IGNORE INTO item_groups (item_id, group_id) VALUES (?1, ?2)",
+                    params![item_id, group_id],
+                )?;
+                // Filed by the rule, not the user: doesn't keep the item.
+                if added > 0 {
+                    tx.execute(
+                        "INSERT OR IGNORE INTO item_group_confidence (item_id, group_id, confidence)
+                         VALUES (?1, ?2, 1.0)",
+                        params![item_id, group_id],
+                    )?;
+                }
+            }
+        }
+
         for (group_name, confidence) in groups {
             let trimmed = group_name.trim();
             if trimmed.is_empty() {
                 continue;
             }
             let group_id = Self::ensure_group_with_type(&tx, trimmed, system_groups)?;
-            tx.execute(
+            let added = tx.execute(
                 "INSERT OR IGNORE INTO item_groups (item_id, group_id) VALUES (?1, ?2)",
                 params![item_id, group_id],
             )?;
-            tx.execute(
+            // A membership the user made stays theirs (no confidence row);
+            // only new or automatic ones take the score.
+            let sql = if added > 0 {
                 "INSERT INTO item_group_confidence (item_id, group_id, confidence)
                  VALUES (?1, ?2, ?3)
-                 ON CONFLICT(item_id, group_id) DO UPDATE SET confidence = excluded.confidence",
-                params![item_id, group_id, confidence.max(0.0)],
-            )?;
+                 ON CONFLICT(item_id, group_id) DO UPDATE SET confidence = excluded.confidence"
+            } else {
+                "UPDATE item_group_confidence SET confidence = ?3
+                 WHERE item_id = ?1 AND group_id = ?2"
+            };
+            tx.execute(sql, params![item_id, group_id, confidence.max(0.0)])?;
         }
 
         tx.commit()?;
-        Ok(item_id)
+        Ok((item_id, existing_item_id.is_none()))
     }
 
-    pub fn get_history(&self, search: Option<String>) -> Result<Vec<ClipboardItem>> {
-        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
-        let mut stmt;
-        let mut rows;
-        let mut fuzzy_query: Option<String> = None;
-
-        if let Some(s) = search {
-            if s.starts_with("group:") || s.starts_with("category:") {
-                let parts: Vec<&str> = s.splitn(2, ' ').collect();
-                let group_name = parts[0]
-                    .trim_start_matches("group:")
-                    .trim_start_matches("category:")
-                    .to_string();
-                let search_term = if parts.len() > 1 { parts[1] } else { "" };
-                let search_pattern = format!("%{}%", search_term);
-
-                if group_name.eq_ignore_ascii_case("text") {
-                    stmt = conn.prepare(
-                        "SELECT h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0)
-                         FROM history h
-                         WHERE h.content_type = 'text' AND h.raw_content LIKE ?1
-                         ORDER BY h.is_permanent DESC, h.created_at DESC
-                         LIMIT 100",
-                    )?;
-                    rows = stmt.query(params![search_pattern])?;
-                } else if group_name.eq_ignore_ascii_case("images") {
-                    stmt = conn.prepare(
-                        "SELECT h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0)
-                         FROM history h
-                         WHERE h.content_type = 'image' AND h.raw_content LIKE ?1
-                         ORDER BY h.is_permanent DESC, h.created_at DESC
-                         LIMIT 100",
-                    )?;
-                    rows = stmt.query(params![search_pattern])?;
-                } else if group_name.eq_ignore_ascii_case("files") {
-                    stmt = conn.prepare(
-                        "SELECT h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0)
-                         FROM history h
-                         WHERE h.content_type = 'files' AND h.raw_content LIKE ?1
-                         ORDER BY h.is_permanent DESC, h.created_at DESC
-                         LIMIT 100",
-                    )?;
-                    rows = stmt.query(params![search_pattern])?;
-                } else if group_name.eq_ignore_ascii_case("url")
-                    || group_name.eq_ignore_ascii_case("urls")
-                {
-                    stmt = conn.prepare(
-                        "SELECT DISTINCT h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0)
-                         FROM history h
-                         LEFT JOIN item_groups ig ON h.id = ig.item_id
-                         LEFT JOIN groups g ON ig.group_id = g.id
-                         WHERE (
-                            g.name = 'URL'
-                            OR h.raw_content LIKE 'http://%'
-                            OR h.raw_content LIKE 'https://%'
-                            OR h.raw_content LIKE 'ftp://%'
-                         ) AND h.raw_content LIKE ?1
-                         ORDER BY h.is_permanent DESC, h.created_at DESC
-                         LIMIT 100",
-                    )?;
-                    rows = stmt.query(params![search_pattern])?;
-                } else {
-                    stmt = conn.prepare(
-                        "SELECT DISTINCT h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0)
-                         FROM history h
-                         JOIN item_groups ig ON h.id = ig.item_id
-                         JOIN groups g ON ig.group_id = g.id
-                         WHERE g.name = ?1 AND (h.raw_content LIKE ?2 OR h.description LIKE ?2)
-                         ORDER BY h.is_permanent DESC, h.created_at DESC
-                         LIMIT 100",
-                    )?;
-                    rows = stmt.query(params![group_name, search_pattern])?;
-                }
-            } else {
-                fuzzy_query = Some(s.clone());
-                let pattern = format!("%{}%", s);
-                // Fast path: FTS5 retrieves content/description candidates; we
-                // still match category/group names for parity, then the Rust
-                // fuzzy reranker orders the candidate set.
-                match (self.fts_enabled, build_fts_query(&s)) {
-                    (true, Some(fts)) => {
-                        stmt = conn.prepare(
-                            "SELECT id, content_type, raw_content, category, is_permanent, created_at, description, COALESCE(is_manual, 0), COALESCE(is_sensitive, 0)
-                             FROM history
-                             WHERE id IN (SELECT rowid FROM history_fts WHERE history_fts MATCH ?1)
-                                OR category LIKE ?2
-                                OR EXISTS (
-                                    SELECT 1
-                                    FROM item_groups ig
-                                    JOIN groups g ON ig.group_id = g.id
-                                    WHERE ig.item_id = history.id AND g.name LIKE ?2
-                                )
-                             ORDER BY is_permanent DESC, created_at DESC
-                             LIMIT 500",
-                        )?;
-                        rows = stmt.query(params![fts, pattern])?;
-                    }
-                    _ => {
-                        // Fallback: LIKE scan (FTS unavailable or no usable tokens).
-                        stmt = conn.prepare(
-                            "SELECT id, content_type, raw_content, category, is_permanent, created_at, description, COALESCE(is_manual, 0), COALESCE(is_sensitive, 0)
-                             FROM history
-                             WHERE raw_content LIKE ?1
-                                OR description LIKE ?1
-                                OR category LIKE ?1
-                                OR EXISTS (
-                                    SELECT 1
-                                    FROM item_groups ig
-                                    JOIN groups g ON ig.group_id = g.id
-                                    WHERE ig.item_id = history.id AND g.name LIKE ?1
-                                )
-                             ORDER BY is_permanent DESC, created_at DESC
-                             LIMIT 500",
-                        )?;
-                        rows = stmt.query(params![pattern])?;
-                    }
-                }
-            }
-        } else {
-            stmt = conn.prepare(
-                "SELECT id, content_type, raw_content, category, is_permanent, created_at, description, COALESCE(is_manual, 0), COALESCE(is_sensitive, 0)
-                 FROM history
-                 ORDER BY is_permanent DESC, created_at DESC
-                 LIMIT 100",
-            )?;
-            rows = stmt.query([])?;
+    /// Builds the WHERE conditions shared by `get_history` and
+    /// `get_history_page` from a parsed search query (see query.rs) and a
+    /// capture selection ('clipboard' or 'primary').
+    fn history_filter(&self, query: &Query, selection: Option<String>) -> HistoryFilter {
+        let (mut conditions, mut values) = query.to_sql(self.fts_enabled);
+
+        if let Some(selection) = selection {
+            conditions.push("COALESCE(h.selection, 'clipboard') = ?".to_string());
+            values.push(Value::Text(selection));
         }
 
-        let mut items = Vec::new();
-        let mut item_ids = Vec::new();
+        HistoryFilter {
+            conditions,
+            values,
+            fuzzy_query: query.free_text(),
+        }
+    }
 
-        while let Some(row) = rows.next()? {
-            let id: i64 = row.get(0)?;
-            item_ids.push(id);
-            let is_sensitive: bool = row.get(8)?;
+    /// Latest history items (pinned first), optionally narrowed by a search
+    /// query and capture selection; see `history_filter`. Free-text searches
+    /// are fuzzy-ranked, so they return the best 100 matches rather than the
+    /// newest.
+    pub fn get_history(
+        &self,
+        query: &Query,
+        selection: Option<String>,
+    ) -> Result<Vec<ClipboardItem>> {
+        let filter = self.history_filter(query, selection);
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut items = if query.uses_regex() {
+            Self::regex_history(&conn, &filter, REGEX_MAX_RESULTS)?
+        } else {
+            let limit = if filter.fuzzy_query.is_some() { 500 } else { 100 };
+            let sql = format!(
+                "SELECT {ITEM_COLUMNS}
+                 FROM history h
+                 {}
+                 ORDER BY h.is_permanent DESC, h.created_at DESC
+                 LIMIT {limit}",
+                filter.where_clause()
+            );
+            let mut stmt = conn.prepare(&sql)?;
             // Sensitive items are never sent to the UI in clear; the content
             // stays encrypted at rest and is only revealed on explicit request.
-            let raw_content: String = if is_sensitive {
-                String::new()
-            } else {
-                row.get(2)?
-            };
-            items.push(ClipboardItem {
-                id,
-                content_type: row.get(1)?,
-                raw_content,
-                category: row.get(3)?,
-                groups: Vec::new(),
-                is_permanent: row.get(4)?,
-                created_at: row.get(5)?,
-                description: row.get(6)?,
-                is_manual: row.get(7)?,
-                is_sensitive,
-            });
-        }
-
-        // Fetch groups for these items
-        if !item_ids.is_empty() {
-            // SQLite has a limit on the number of variables (usually 999).
-            // For larger sets, we can use a temporary table or chunk the requests.
-            // Since this is for a UI list (limited to 100), we are safe for now,
-            // but for backup/restore we need to be careful.
-            // Let's implement chunking as a safety measure.
-            let mut groups_map: HashMap<i64, Vec<String>> = HashMap::new();
-
-            for chunk in item_ids.chunks(900) {
-                let placeholders: Vec<String> = chunk.iter().map(|_| "?".to_string()).collect();
-                let sql = format!(
-                    "SELECT ig.item_id, g.name 
-                     FROM item_groups ig
-                     JOIN groups g ON ig.group_id = g.id
-                     WHERE ig.item_id IN ({})",
-                    placeholders.join(",")
-                );
-
-                let mut stmt = conn.prepare(&sql)?;
-                let params = rusqlite::params_from_iter(chunk.iter());
-                let mut group_rows = stmt.query(params)?;
-
-                while let Some(row) = group_rows.next()? {
-                    let item_id: i64 = row.get(0)?;
-                    let group_name: String = row.get(1)?;
-                    groups_map.entry(item_id).or_default().push(group_name);
-                }
-            }
-
-            for item in &mut items {
-                if let Some(g_list) = groups_map.get(&item.id) {
-                    item.groups = g_list.clone();
-                }
-            }
-        }
+            stmt.query_map(rusqlite::params_from_iter(filter.values.iter()), |row| {
+                item_from_row(row, false)
+            })?
+            .collect::<Result<Vec<_>>>()?
+        };
+        attach_groups(&conn, &mut items)?;
 
-        if let Some(query) = fuzzy_query {
+        if let Some(query) = filter.fuzzy_query {
             let mut ranked: Vec<(i32, ClipboardItem)> = items
                 .into_iter()
                 .filter_map(|item| {
@@ -889,16 +838,132 @@ impl ClipboardDB {
                     }
                 })
                 .collect();
-            ranked.sort_by(|a, b| b.0.cmp(&a.0));
+            ranked.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
             items = ranked.into_iter().take(100).map(|(_, item)| item).collect();
         }
 
         Ok(items)
     }
 
-    pub fn add_to_group(&self, item_id: i64, group_name: String) -> Result<()> {
-        let mut conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
-        let tx = conn.transaction()?;
+    /// Runs a filter containing a regex, which has to test every candidate row
+    /// in Rust. Rows are scanned newest first (ignoring pins) so that hitting
+    /// `REGEX_TIME_BUDGET` still returns the most recent matches found so far
+    /// rather than nothing; at most `limit` are returned.
+    fn regex_history(
+        conn: &Connection,
+        filter: &HistoryFilter,
+        limit: usize,
+    ) -> Result<Vec<ClipboardItem>> {
+        let sql = format!(
+            "SELECT {ITEM_COLUMNS}
+             FROM history h
+             {}
+             ORDER BY h.created_at DESC, h.id DESC
+             LIMIT {}",
+            filter.where_clause(),
+            limit
+        );
+        let mut stmt = conn.prepare(&sql)?;
+
+        let deadline = Instant::now() + REGEX_TIME_BUDGET;
+        conn.progress_handler(1000, Some(move || Instant::now() >= deadline));
+        let mut items = Vec::new();
+        let mut result = Ok(());
+        match stmt.query_map(rusqlite::params_from_iter(filter.values.iter()), |row| {
+            item_from_row(row, false)
+        }) {
+            Ok(rows) => {
+                for row in rows {
+                    match row {
+                        Ok(item) => items.push(item),
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => result = Err(e),
+        }
+        conn.progress_handler(0, None::<fn() -> bool>);
+
+        match result {
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::OperationInterrupted =>
+            {
+                log::warn!(
+                    "DB: regex search stopped after {:?} with {} matches",
+                    REGEX_TIME_BUDGET,
+                    items.len()
+                );
+                Ok(items)
+            }
+            Err(e) => Err(e),
+            Ok(()) => Ok(items),
+        }
+    }
+
+    /// One page of history, newest first, after `cursor` (exclusive). Takes the
+    /// same filters as `get_history`, but free-text matches stay in time order
+    /// so pages are stable. Keyset pagination over (created_at, id) keeps deep
+    /// pages as cheap as the first one. A regex filter goes through
+    /// `regex_history`, so each page gets the same time budget and result cap;
+    /// a page cut short by the budget ends the listing.
+    pub fn get_history_page(
+        &self,
+        query: &Query,
+        selection: Option<String>,
+        cursor: Option<HistoryCursor>,
+        page_size: usize,
+    ) -> Result<HistoryPage> {
+        let mut filter = self.history_filter(query, selection);
+        if let Some(cursor) = cursor {
+            filter.conditions.push("(h.created_at, h.id) < (?, ?)".to_string());
+            filter.values.push(Value::Text(cursor.created_at));
+            filter.values.push(Value::Integer(cursor.id));
+        }
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let page_size = if query.uses_regex() {
+            page_size.min(REGEX_MAX_RESULTS)
+        } else {
+            page_size
+        };
+        // One extra row tells us whether another page exists.
+        let mut items = if query.uses_regex() {
+            Self::regex_history(&conn, &filter, page_size + 1)?
+        } else {
+            let sql = format!(
+                "SELECT {ITEM_COLUMNS}
+                 FROM history h
+                 {}
+                 ORDER BY h.created_at DESC, h.id DESC
+                 LIMIT {}",
+                filter.where_clause(),
+                page_size + 1
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            stmt.query_map(rusqlite::params_from_iter(filter.values.iter()), |row| {
+                item_from_row(row, false)
+            })?
+            .collect::<Result<Vec<_>>>()?
+        };
+
+        let next_cursor = if items.len() > page_size {
+            items.truncate(page_size);
+            items.last().map(|last| HistoryCursor {
+                created_at: last.created_at.clone(),
+                id: last.id,
+            })
+        } else {
+            None
+        };
+        attach_groups(&conn, &mut items)?;
+        Ok(HistoryPage { items, next_cursor })
+    }
+
+    pub fn add_to_group(&self, item_id: i64, group_name: String) -> Result<()> {
+        let mut conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let tx = conn.transaction()?;
 
         // Check if item exists to avoid generic FK error
         let item_exists: bool = tx.query_row(
@@ -914,10 +979,19 @@ impl ClipboardDB {
         // Ensure group exists
         let group_id = Self::ensure_group_with_type(&tx, &group_name, false)?;
 
-        tx.execute(
+        let added = tx.execute(
             "INSERT OR IGNORE INTO item_groups (item_id, group_id) VALUES (?1, ?2)",
             params![item_id, group_id],
         )?;
+        // Adding an automatically filed item by hand makes the membership the
+        // user's own.
+        let confirmed = tx.execute(
+            "DELETE FROM item_group_confidence WHERE item_id = ?1 AND group_id = ?2",
+            params![item_id, group_id],
+        )?;
+        if (added > 0 || confirmed > 0) && Self::learning_enabled(&tx)? {
+            Self::learn_example(&tx, item_id, group_id)?;
+        }
 
         tx.commit()?;
         Ok(())
@@ -938,6 +1012,11 @@ impl ClipboardDB {
                 "DELETE FROM item_groups WHERE item_id = ?1 AND group_id = ?2",
                 params![item_id, group_id],
             )?;
+            tx.execute(
+                "DELETE FROM item_group_confidence WHERE item_id = ?1 AND group_id = ?2",
+                params![item_id, group_id],
+            )?;
+            Self::unlearn_example(&tx, item_id, group_id)?;
         }
         tx.commit()?;
         Ok(())
@@ -1034,6 +1113,24 @@ impl ClipboardDB {
         Ok(())
     }
 
+    /// Deletes a PRIMARY-selection row that a continuing highlight drag has
+    /// superseded. Rows the user has kept (pinned, manual, put in a user group)
+    /// or that were also copied to the clipboard are left alone.
+    pub fn discard_partial_selection(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let deleted = conn.execute(
+            &format!(
+                "DELETE FROM history
+                 WHERE id = ?1
+                   AND selection = 'primary'
+                   AND COALESCE(is_manual, 0) = 0
+                   AND {NOT_CURATED}"
+            ),
+            params![id],
+        )?;
+        Ok(deleted > 0)
+    }
+
     pub fn get_item_payload(&self, id: i64) -> Result<(String, String)> {
         let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
         conn.query_row(
@@ -1043,17 +1140,89 @@ impl ClipboardDB {
         )
     }
 
+    /// HTML/RTF markup stored with an 'html' / 'rtf' item, if any.
+    pub fn get_rich_content(&self, id: i64) -> Result<Option<String>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        conn.query_row(
+            "SELECT rich_content FROM history WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+    }
+
     /// Replaces an item's stored content and sets its sensitive flag. Used when
     /// marking (store ciphertext) or unmarking (store plaintext) an item.
-    pub fn set_raw_and_sensitive(&self, id: i64, content: &str, is_sensitive: bool) -> Result<()> {
+    /// Marking also drops any rich-text flavor, which would otherwise keep the
+    /// secret in clear; the item becomes plain text. `masked_preview` is only
+    /// kept while the item is sensitive.
+    pub fn set_raw_and_sensitive(
+        &self,
+        id: i64,
+        content: &str,
+        is_sensitive: bool,
+        masked_preview: Option<&str>,
+    ) -> Result<()> {
         let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
         conn.execute(
-            "UPDATE history SET raw_content = ?1, is_sensitive = ?2 WHERE id = ?3",
-            params![content, is_sensitive, id],
+            "UPDATE history
+             SET raw_content = ?1,
+                 is_sensitive = ?2,
+                 sensitive_at = CASE WHEN ?2 THEN CURRENT_TIMESTAMP END,
+                 rich_content = CASE WHEN ?2 THEN NULL ELSE rich_content END,
+                 content_type = CASE WHEN ?2 AND content_type IN ('html', 'rtf') THEN 'text' ELSE content_type END,
+                 masked_preview = CASE WHEN ?2 THEN ?4 END
+             WHERE id = ?3",
+            params![content, is_sensitive, id, masked_preview],
         )?;
         Ok(())
     }
 
+    /// Ids and stored (encrypted) content of every sensitive item, for key
+    /// rotation.
+    pub fn get_sensitive_payloads(&self) -> Result<Vec<(i64, String)>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut stmt =
+            conn.prepare("SELECT id, raw_content FROM history WHERE is_sensitive = 1 ORDER BY id")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(rows)
+    }
+
+    /// Rewrites the content of sensitive items in one transaction, so a
+    /// rotation is applied to all of them or none. Each update is given as
+    /// (id, content read before, new content) and only applies while the row
+    /// still holds what was read: an item edited, unmasked or re-masked in the
+    /// meantime keeps its newer content. Returns how many rows were rewritten
+    /// and the ids of those that changed underneath (deleted rows are neither).
+    /// Unlike `set_raw_and_sensitive` this leaves `sensitive_at` (and so any
+    /// expiry countdown) alone.
+    pub fn replace_sensitive_payloads(
+        &self,
+        updates: &[(i64, String, String)],
+    ) -> Result<(usize, Vec<i64>)> {
+        let mut conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let tx = conn.transaction()?;
+        let mut replaced = 0;
+        let mut changed = Vec::new();
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE history SET raw_content = ?1
+                 WHERE id = ?2 AND is_sensitive = 1 AND raw_content = ?3",
+            )?;
+            let mut exists = tx.prepare("SELECT 1 FROM history WHERE id = ?1")?;
+            for (id, old, new) in updates {
+                if stmt.execute(params![new, id, old])? > 0 {
+                    replaced += 1;
+                } else if exists.exists(params![id])? {
+                    changed.push(*id);
+                }
+            }
+        }
+        tx.commit()?;
+        Ok((replaced, changed))
+    }
+
     /// Fetches items by id (for the paste stack), masking sensitive content and
     /// returning them in the same order as `ids`.
     pub fn get_items_by_ids(&self, ids: &[i64]) -> Result<Vec<ClipboardItem>> {
@@ -1063,26 +1232,12 @@ impl ClipboardDB {
         let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
         let placeholders: Vec<String> = ids.iter().map(|_| "?".to_string()).collect();
         let sql = format!(
-            "SELECT id, content_type, raw_content, category, is_permanent, created_at, description, COALESCE(is_manual, 0), COALESCE(is_sensitive, 0)
-             FROM history WHERE id IN ({})",
+            "SELECT {ITEM_COLUMNS} FROM history h WHERE h.id IN ({})",
             placeholders.join(",")
         );
         let mut stmt = conn.prepare(&sql)?;
         let rows = stmt.query_map(rusqlite::params_from_iter(ids.iter()), |row| {
-            let is_sensitive: bool = row.get(8)?;
-            let raw_content: String = if is_sensitive { String::new() } else { row.get(2)? };
-            Ok(ClipboardItem {
-                id: row.get(0)?,
-                content_type: row.get(1)?,
-                raw_content,
-                category: row.get(3)?,
-                groups: Vec::new(),
-                is_permanent: row.get(4)?,
-                created_at: row.get(5)?,
-                description: row.get(6)?,
-                is_manual: row.get(7)?,
-                is_sensitive,
-            })
+            item_from_row(row, false)
         })?;
         let mut by_id: HashMap<i64, ClipboardItem> = HashMap::new();
         for r in rows {
@@ -1092,6 +1247,263 @@ impl ClipboardDB {
         Ok(ids.iter().filter_map(|id| by_id.remove(id)).collect())
     }
 
+    // --- Secret detections (see secrets.rs) ---
+
+    /// The most recent secret detections, newest first.
+    pub fn secret_detections(&self, limit: u32) -> Result<Vec<SecretDetection>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, item_id, detector, masked, source_app, detected_at
+             FROM secret_detections
+             ORDER BY id DESC
+             LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit], |row| {
+            Ok(SecretDetection {
+                id: row.get(0)?,
+                item_id: row.get(1)?,
+                detector: row.get(2)?,
+                masked: row.get(3)?,
+                source_app: row.get(4)?,
+                detected_at: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn clear_secret_detections(&self) -> Result<()> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        conn.execute("DELETE FROM secret_detections", [])?;
+        Ok(())
+    }
+
+    // --- Group confidence ---
+
+    /// For each group the classifier attaches automatically: how many items it
+    /// holds with a recorded confidence, their average confidence and how many
+    /// are below `below`. Worst groups first.
+    pub fn group_confidence_summary(&self, below: f64) -> Result<Vec<(String, i64, f64, i64)>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut stmt = conn.prepare(
+            "SELECT g.name, COUNT(*), AVG(c.confidence), SUM(c.confidence < ?1)
+             FROM item_group_confidence c
+             JOIN item_groups ig ON ig.item_id = c.item_id AND ig.group_id = c.group_id
+             JOIN groups g ON g.id = c.group_id
+             GROUP BY g.id
+             ORDER BY SUM(c.confidence < ?1) * 1.0 / COUNT(*) DESC, g.name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map(params![below], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?;
+        rows.collect()
+    }
+
+    // --- Learned grouping (see learning.rs) ---
+
+    /// Whether filing items under user groups trains the model, and new
+    /// captures get its suggestions (`learn_groups`; on unless "0").
+    fn learning_enabled(tx: &rusqlite::Transaction<'_>) -> Result<bool> {
+        Ok(Self::get_meta_value(tx, "learn_groups")?.as_deref() != Some("0"))
+    }
+
+    /// Adds an item's tokens to a user group's counts, once per item and
+    /// group. Masked items, images, files and system groups are skipped.
+    fn learn_example(tx: &rusqlite::Transaction<'_>, item_id: i64, group_id: i64) -> Result<()> {
+        let row: Option<(String, String, bool, bool)> = tx
+            .query_row(
+                "SELECT h.content_type, h.raw_content, COALESCE(h.is_sensitive, 0), COALESCE(g.is_system, 0)
+                 FROM history h, groups g
+                 WHERE h.id = ?1 AND g.id = ?2",
+                params![item_id, group_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .ok();
+        let Some((content_type, content, is_sensitive, is_system)) = row else {
+            return Ok(());
+        };
+        if is_sensitive || is_system || !matches!(content_type.as_str(), "text" | "html" | "rtf") {
+            return Ok(());
+        }
+        let tokens = learning::tokens(&content);
+        if tokens.is_empty() {
+            return Ok(());
+        }
+        let added = tx.execute(
+            "INSERT OR IGNORE INTO learned_examples (item_id, group_id, tokens) VALUES (?1, ?2, ?3)",
+            params![item_id, group_id, tokens.join("\n")],
+        )?;
+        if added == 0 {
+            return Ok(());
+        }
+        tx.execute(
+            "INSERT INTO learned_groups (group_id, examples, tokens) VALUES (?1, 1, ?2)
+             ON CONFLICT(group_id) DO UPDATE SET
+                 examples = examples + 1,
+                 tokens = tokens + excluded.tokens",
+            params![group_id, tokens.len() as i64],
+        )?;
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO learned_tokens (group_id, token, count) VALUES (?1, ?2, 1)
+             ON CONFLICT(group_id, token) DO UPDATE SET count = count + 1",
+        )?;
+        for token in &tokens {
+            stmt.execute(params![group_id, token])?;
+        }
+        Ok(())
+    }
+
+    /// Takes back what `learn_example` added for this item and group.
+    fn unlearn_example(tx: &rusqlite::Transaction<'_>, item_id: i64, group_id: i64) -> Result<()> {
+        let tokens: Option<String> = tx
+            .query_row(
+                "SELECT tokens FROM learned_examples WHERE item_id = ?1 AND group_id = ?2",
+                params![item_id, group_id],
+                |row| row.get(0),
+            )
+            .ok();
+        let Some(tokens) = tokens else {
+            return Ok(());
+        };
+        tx.execute(
+            "DELETE FROM learned_examples WHERE item_id = ?1 AND group_id = ?2",
+            params![item_id, group_id],
+        )?;
+        let tokens: Vec<&str> = tokens.split('\n').collect();
+        tx.execute(
+            "UPDATE learned_groups
+             SET examples = examples - 1, tokens = MAX(tokens - ?2, 0)
+             WHERE group_id = ?1",
+            params![group_id, tokens.len() as i64],
+        )?;
+        let mut stmt = tx.prepare_cached(
+            "UPDATE learned_tokens SET count = count - 1 WHERE group_id = ?1 AND token = ?2",
+        )?;
+        for token in &tokens {
+            stmt.execute(params![group_id, token])?;
+        }
+        tx.execute(
+            "DELETE FROM learned_groups WHERE group_id = ?1 AND examples <= 0",
+            params![group_id],
+        )?;
+        tx.execute(
+            "DELETE FROM learned_tokens WHERE group_id = ?1 AND count <= 0",
+            params![group_id],
+        )?;
+        Ok(())
+    }
+
+    /// Forgets everything learned, then (when learning is on) learns again
+    /// from the items the user put in their groups now. Automatic memberships
+    /// (with an `item_group_confidence` row) aren't examples: the model would
+    /// learn from its own guesses.
+    fn relearn(tx: &rusqlite::Transaction<'_>) -> Result<()> {
+        tx.execute_batch(
+            "DELETE FROM learned_examples;
+             DELETE FROM learned_tokens;
+             DELETE FROM learned_groups;",
+        )?;
+        if !Self::learning_enabled(tx)? {
+            return Ok(());
+        }
+        let memberships: Vec<(i64, i64)> = {
+            let mut stmt = tx.prepare(
+                "SELECT ig.item_id, ig.group_id
+                 FROM item_groups ig
+                 JOIN groups g ON g.id = ig.group_id
+                 WHERE COALESCE(g.is_system, 0) = 0
+                   AND NOT EXISTS (
+                       SELECT 1 FROM item_group_confidence c
+                       WHERE c.item_id = ig.item_id AND c.group_id = ig.group_id
+                   )",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+        for (item_id, group_id) in memberships {
+            Self::learn_example(tx, item_id, group_id)?;
+        }
+        Ok(())
+    }
+
+    /// Learns from the groups that existed before learning did, once.
+    fn seed_learning(conn: &mut Connection) -> Result<()> {
+        let tx = conn.transaction()?;
+        if Self::get_meta_value(&tx, "learning_seeded")?.is_none() {
+            Self::relearn(&tx)?;
+            tx.execute(
+                "INSERT INTO app_meta (key, value) VALUES ('learning_seeded', '1')",
+                [],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Forgets what manual regrouping taught and starts again from the
+    /// current user groups.
+    pub fn reset_learning(&self) -> Result<()> {
+        let mut conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let tx = conn.transaction()?;
+        Self::relearn(&tx)?;
+        tx.commit()
+    }
+
+    /// The learned counts `learning::predict` needs to score `tokens`, and
+    /// the number of distinct tokens learned.
+    pub fn learned_counts(&self, tokens: &[String]) -> Result<(Vec<learning::GroupCounts>, i64)> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut groups: Vec<(i64, learning::GroupCounts)> = {
+            let mut stmt = conn.prepare(
+                "SELECT lg.group_id, g.name, lg.examples, lg.tokens
+                 FROM learned_groups lg
+                 JOIN groups g ON g.id = lg.group_id",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    learning::GroupCounts {
+                        name: row.get(1)?,
+                        examples: row.get(2)?,
+                        tokens: row.get(3)?,
+                        counts: HashMap::new(),
+                    },
+                ))
+            })?;
+            rows.collect::<Result<_>>()?
+        };
+        if groups.is_empty() || tokens.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+
+        let placeholders = vec!["?"; tokens.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT group_id, token, count FROM learned_tokens WHERE token IN ({})",
+            placeholders
+        ))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(tokens.iter()))?;
+        while let Some(row) = rows.next()? {
+            let group_id: i64 = row.get(0)?;
+            if let Some((_, group)) = groups.iter_mut().find(|(id, _)| *id == group_id) {
+                group.counts.insert(row.get(1)?, row.get(2)?);
+            }
+        }
+        let vocabulary: i64 =
+            conn.query_row("SELECT COUNT(DISTINCT token) FROM learned_tokens", [], |row| row.get(0))?;
+        Ok((groups.into_iter().map(|(_, group)| group).collect(), vocabulary))
+    }
+
+    /// Each learned group and how many examples it has, most first.
+    pub fn learning_summary(&self) -> Result<Vec<(String, i64)>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut stmt = conn.prepare(
+            "SELECT g.name, lg.examples
+             FROM learned_groups lg
+             JOIN groups g ON g.id = lg.group_id
+             ORDER BY lg.examples DESC, g.name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
     // --- App settings (key/value in app_meta) ---
 
     pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
@@ -1105,6 +1517,21 @@ impl ClipboardDB {
             .ok())
     }
 
+    /// True when `app` is on the `ignored_apps` deny-list: a JSON array of
+    /// source app ids, matched case-insensitively.
+    pub fn is_app_ignored(&self, app: &str) -> bool {
+        let Ok(Some(json)) = self.get_setting("ignored_apps") else {
+            return false;
+        };
+        match serde_json::from_str::<Vec<String>>(&json) {
+            Ok(apps) => apps.iter().any(|ignored| ignored.trim().eq_ignore_ascii_case(app)),
+            Err(e) => {
+                eprintln!("DB: ignoring invalid ignored_apps setting: {}", e);
+                false
+            }
+        }
+    }
+
     pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
         let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
         conn.execute(
@@ -1146,6 +1573,34 @@ impl ClipboardDB {
         )
     }
 
+    /// The full blob row for a hash, if it is stored.
+    pub fn get_blob_record(&self, hash: &str) -> Result<Option<Blob>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        Ok(conn
+            .query_row(
+                "SELECT hash, mime, data, thumb FROM blobs WHERE hash = ?1",
+                params![hash],
+                |row| {
+                    Ok(Blob {
+                        hash: row.get(0)?,
+                        mime: row.get(1)?,
+                        data: row.get(2)?,
+                        thumb: row.get(3)?,
+                    })
+                },
+            )
+            .ok())
+    }
+
+    pub fn has_blob(&self, hash: &str) -> Result<bool> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM blobs WHERE hash = ?1)",
+            params![hash],
+            |row| row.get(0),
+        )
+    }
+
     /// Removes blobs no longer referenced by any image history row.
     fn prune_orphan_blobs(conn: &Connection) -> Result<()> {
         conn.execute(
@@ -1180,23 +1635,13 @@ impl ClipboardDB {
             .and_then(|v| v.parse::<i64>().ok())
         };
 
-        // Retention only ever removes "ephemeral" items: NOT pinned and NOT in
-        // any user-defined (non-system) group. Pinned and grouped items are
-        // kept forever.
-        let not_curated = "is_permanent = 0
-             AND NOT EXISTS (
-                 SELECT 1 FROM item_groups ig
-                 JOIN groups g ON g.id = ig.group_id
-                 WHERE ig.item_id = history.id AND g.is_system = 0
-             )";
-
         // Age-based: delete ephemeral items older than N days (0 = keep forever).
         if let Some(days) = get_int("retention_days") {
             if days > 0 {
                 conn.execute(
                     &format!(
                         "DELETE FROM history
-                         WHERE {not_curated}
+                         WHERE {NOT_CURATED}
                            AND created_at < datetime('now', '-{days} days')"
                     ),
                     [],
@@ -1210,16 +1655,11 @@ impl ClipboardDB {
                 conn.execute(
                     &format!(
                         "DELETE FROM history
-                         WHERE {not_curated}
+                         WHERE {NOT_CURATED}
                            AND id NOT IN (
-                             SELECT id FROM history h2
-                             WHERE h2.is_permanent = 0
-                               AND NOT EXISTS (
-                                 SELECT 1 FROM item_groups ig
-                                 JOIN groups g ON g.id = ig.group_id
-                                 WHERE ig.item_id = h2.id AND g.is_system = 0
-                               )
-                             ORDER BY h2.created_at DESC
+                             SELECT id FROM history
+                             WHERE {NOT_CURATED}
+                             ORDER BY created_at DESC
                              LIMIT ?1
                            )"
                     ),
@@ -1228,10 +1668,73 @@ impl ClipboardDB {
             }
         }
 
+        // Per-app age limits (e.g. terminal output after a day), on top of the
+        // global ones.
+        let app_days = conn
+            .query_row(
+                "SELECT value FROM app_meta WHERE key = 'retention_app_days'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .ok();
+        for (app, days) in parse_app_rules::<i64>("retention_app_days", app_days.as_deref()) {
+            if days > 0 {
+                conn.execute(
+                    &format!(
+                        "DELETE FROM history
+                         WHERE {NOT_CURATED}
+                           AND source_app = ?1 COLLATE NOCASE
+                           AND created_at < datetime('now', '-{days} days')"
+                    ),
+                    params![app],
+                )?;
+            }
+        }
+
         let _ = Self::prune_orphan_blobs(&conn);
         Ok(())
     }
 
+    /// Deletes ephemeral sensitive items whose `ttl_secs` countdown has run
+    /// out, returning their ids and (encrypted) content.
+    pub fn expire_sensitive(&self, ttl_secs: i64) -> Result<Vec<(i64, String)>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut stmt = conn.prepare(&format!(
+            "DELETE FROM history
+             WHERE is_sensitive = 1
+               AND COALESCE(is_manual, 0) = 0
+               AND {NOT_CURATED}
+               AND COALESCE(sensitive_at, created_at) <= datetime('now', ?1)
+             RETURNING id, raw_content"
+        ))?;
+        let expired = stmt
+            .query_map(params![format!("-{} seconds", ttl_secs)], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+        if !expired.is_empty() {
+            let _ = Self::prune_orphan_blobs(&conn);
+        }
+        Ok(expired)
+    }
+
+    /// Seconds until the next ephemeral sensitive item expires under
+    /// `ttl_secs` (0 if one is already due), or None when there are none.
+    pub fn next_sensitive_expiry(&self, ttl_secs: i64) -> Result<Option<i64>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        conn.query_row(
+            &format!(
+                "SELECT MIN(unixepoch(COALESCE(sensitive_at, created_at))) + ?1 - unixepoch('now')
+                 FROM history
+                 WHERE is_sensitive = 1 AND COALESCE(is_manual, 0) = 0 AND {NOT_CURATED}"
+            ),
+            params![ttl_secs],
+            |row| row.get::<_, Option<i64>>(0),
+        )
+        .map(|secs| secs.map(|s| s.max(0)))
+    }
+
     pub fn get_categories(&self) -> Result<Vec<String>> {
         let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
         let mut stmt =
@@ -1284,205 +1787,365 @@ impl ClipboardDB {
 
     // --- Backup & Restore ---
 
-    pub fn get_all_data_json(&self, selected_groups: Option<Vec<String>>) -> Result<String> {
-        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
-
-        // 1. Determine which items to fetch
-        let sql = if let Some(ref groups) = selected_groups {
-            if groups.is_empty() {
-                "SELECT DISTINCT h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0)
-                  FROM history h"
-            } else {
-                "SELECT DISTINCT h.id, h.content_type, h.raw_content, h.category, h.is_permanent, h.created_at, h.description, COALESCE(h.is_manual, 0), COALESCE(h.is_sensitive, 0)
-                  FROM history h
-                  JOIN item_groups ig ON h.id = ig.item_id
-                  JOIN groups g ON ig.group_id = g.id
-                  WHERE g.name IN "
-            }
-        } else {
-            "SELECT id, content_type, raw_content, category, is_permanent, created_at, description, COALESCE(is_manual, 0), COALESCE(is_sensitive, 0) FROM history"
-        };
-
-        let mut final_sql = sql.to_string();
-        let mut params_vec: Vec<String> = Vec::new();
-
-        if let Some(ref groups) = selected_groups {
-            if !groups.is_empty() {
-                let placeholders: Vec<String> = groups.iter().map(|_| "?".to_string()).collect();
-                if final_sql.ends_with("IN ") {
-                    final_sql = format!("{} ({})", final_sql, placeholders.join(","));
-                    params_vec = groups.clone();
-                }
-            }
+    /// WHERE clause and parameters matching the items a backup of
+    /// `selected_groups` holds (everything when empty) with an id above
+    /// `after_id`.
+    fn backup_filter(selected_groups: &[String], after_id: i64) -> (String, Vec<Value>) {
+        let mut params = vec![Value::Integer(after_id)];
+        if selected_groups.is_empty() {
+            return ("h.id > ?".to_string(), params);
         }
+        let placeholders: Vec<&str> = selected_groups.iter().map(|_| "?").collect();
+        params.extend(selected_groups.iter().map(|g| Value::Text(g.clone())));
+        (
+            format!(
+                "h.id > ? AND h.id IN (
+                     SELECT ig.item_id FROM item_groups ig
+                     JOIN groups g ON ig.group_id = g.id
+                     WHERE g.name IN ({}))",
+                placeholders.join(",")
+            ),
+            params,
+        )
+    }
 
-        let mut stmt = conn.prepare(&final_sql)?;
-        let params = rusqlite::params_from_iter(params_vec.iter());
+    /// How many items a backup of `selected_groups` holds.
+    pub fn count_backup_items(&self, selected_groups: &[String]) -> Result<u64> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let (filter, params) = Self::backup_filter(selected_groups, 0);
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM history h WHERE {filter}"),
+            rusqlite::params_from_iter(params),
+            |row| row.get(0),
+        )
+    }
 
-        let history_iter = stmt.query_map(params, |row| {
-            // Backup keeps sensitive content as-is (encrypted), so a restore on
-            // the same machine (same key) round-trips it.
-            Ok(ClipboardItem {
+    /// The groups a backup of `selected_groups` carries (all when empty).
+    pub fn get_backup_groups(&self, selected_groups: &[String]) -> Result<Vec<Group>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut sql = "SELECT id, name, is_system FROM groups".to_string();
+        if !selected_groups.is_empty() {
+            let placeholders: Vec<&str> = selected_groups.iter().map(|_| "?").collect();
+            sql = format!("{sql} WHERE name IN ({})", placeholders.join(","));
+        }
+        let mut stmt = conn.prepare(&sql)?;
+        let groups = stmt.query_map(rusqlite::params_from_iter(selected_groups), |row| {
+            Ok(Group {
                 id: row.get(0)?,
-                content_type: row.get(1)?,
-                raw_content: row.get(2)?,
-                category: row.get(3)?,
-                groups: Vec::new(),
-                is_permanent: row.get(4)?,
-                created_at: row.get(5)?,
-                description: row.get(6)?,
-                is_manual: row.get(7)?,
-                is_sensitive: row.get(8)?,
+                name: row.get(1)?,
+                is_system: row.get(2)?,
             })
         })?;
-        let mut history: Vec<ClipboardItem> = history_iter.collect::<Result<_, _>>()?;
-
-        // 2. Populate groups for these items
-        if !history.is_empty() {
-            let item_ids: Vec<String> = history.iter().map(|i| i.id.to_string()).collect();
-            let placeholders: Vec<String> = item_ids.iter().map(|_| "?".to_string()).collect();
-            let sql_groups = format!(
-                "SELECT ig.item_id, g.name 
-                 FROM item_groups ig 
-                 JOIN groups g ON ig.group_id = g.id
-                 WHERE ig.item_id IN ({})",
-                placeholders.join(",")
-            );
+        groups.collect()
+    }
 
-            let mut stmt_g = conn.prepare(&sql_groups)?;
-            let params_g = rusqlite::params_from_iter(item_ids.iter());
-            let g_rows = stmt_g.query_map(params_g, |row| {
-                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
-            })?;
+    /// The next `limit` backup items after `after_id`, in id order, with
+    /// their groups and with sensitive content still encrypted under this
+    /// machine's key. Backups page through history with this so memory stays
+    /// flat and the connection is free for the listener between pages.
+    pub fn get_backup_items(
+        &self,
+        selected_groups: &[String],
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<ClipboardItem>> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let (filter, mut params) = Self::backup_filter(selected_groups, after_id);
+        params.push(Value::Integer(limit as i64));
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS} FROM history h WHERE {filter} ORDER BY h.id LIMIT ?"
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+            item_from_row(row, true)
+        })?;
+        let mut items: Vec<ClipboardItem> = rows.collect::<Result<_, _>>()?;
+        attach_groups(&conn, &mut items)?;
+        Ok(items)
+    }
 
-            let mut groups_map: HashMap<i64, Vec<String>> = HashMap::new();
-            for r in g_rows {
-                let (item_id, group_name) = r?;
-                groups_map.entry(item_id).or_default().push(group_name);
+    /// Adds the groups side of a restore to `report`: which names are new and,
+    /// for "replace", what would be wiped.
+    pub fn preview_restore_groups(
+        &self,
+        groups: &[Group],
+        mode: &str,
+        report: &mut RestoreReport,
+    ) -> Result<()> {
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut stmt = conn.prepare("SELECT name FROM groups ORDER BY name")?;
+        let existing: Vec<String> = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+        let incoming: HashSet<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+        let existing_names: HashSet<&str> = existing.iter().map(String::as_str).collect();
+        for group in groups {
+            if !existing_names.contains(group.name.as_str()) && !report.new_groups.contains(&group.name) {
+                report.new_groups.push(group.name.clone());
             }
+        }
+        if mode == "replace" {
+            report.removed_items = conn.query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))?;
+            report.removed_groups = existing
+                .into_iter()
+                .filter(|name| !incoming.contains(name.as_str()))
+                .collect();
+        }
+        Ok(())
+    }
 
-            for item in &mut history {
-                if let Some(gs) = groups_map.get(&item.id) {
-                    item.groups = gs.clone();
+    /// Adds a batch of backup items to `report`, comparing each with the
+    /// local item of the same content the way `restore_items` would match it.
+    pub fn preview_restore_items(
+        &self,
+        items: &[ClipboardItem],
+        mode: &str,
+        report: &mut RestoreReport,
+    ) -> Result<()> {
+        if mode == "replace" {
+            report.new_items += items.len() as u64;
+            return Ok(());
+        }
+        let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let mut check_stmt = conn.prepare(
+            "SELECT id, is_permanent, description, COALESCE(updated_at, created_at)
+             FROM history WHERE raw_content = ?1",
+        )?;
+        let mut groups_stmt = conn.prepare(
+            "SELECT g.name FROM item_groups ig JOIN groups g ON g.id = ig.group_id
+             WHERE ig.item_id = ?1",
+        )?;
+        for item in items {
+            let existing = check_stmt
+                .query_row(params![item.raw_content], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, bool>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                })
+                .ok();
+            let Some((id, pinned, description, edited_at)) = existing else {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                std::hash::Hash::hash(&item.raw_content, &mut hasher);
+                if report.seen.insert(std::hash::Hasher::finish(&hasher)) {
+                    report.new_items += 1;
+                } else {
+                    report.duplicates += 1;
                 }
+                continue;
+            };
+            let groups: HashSet<String> = groups_stmt
+                .query_map(params![id], |row| row.get(0))?
+                .collect::<Result<_>>()?;
+
+            let mut fields = Vec::new();
+            if item.is_permanent != pinned {
+                fields.push("pinned");
+            }
+            if non_empty(&item.description) != non_empty(&description) {
+                fields.push("description");
+            }
+            if item.groups.iter().cloned().collect::<HashSet<_>>() != groups {
+                fields.push("groups");
+            }
+            if fields.is_empty() {
+                report.duplicates += 1;
+                continue;
+            }
+            report.conflicts += 1;
+            if report.conflict_samples.len() < CONFLICT_SAMPLES {
+                let preview = if item.is_sensitive || item.content_type == "image" {
+                    String::new()
+                } else {
+                    item.raw_content.chars().take(80).collect()
+                };
+                report.conflict_samples.push(RestoreConflict {
+                    existing_id: id,
+                    content_type: item.content_type.clone(),
+                    preview,
+                    fields,
+                    backup_wins: mode == "newest" && edited_at_of(item) > db_time(&edited_at),
+                });
             }
         }
+        Ok(())
+    }
 
-        // 3. Get relevant groups
-        let group_sql = if let Some(ref groups) = selected_groups {
-            if !groups.is_empty() {
-                let placeholders: Vec<String> = groups.iter().map(|_| "?".to_string()).collect();
-                format!(
-                    "SELECT id, name, is_system FROM groups WHERE name IN ({})",
-                    placeholders.join(",")
-                )
-            } else {
-                "SELECT id, name, is_system FROM groups".to_string()
-            }
-        } else {
-            "SELECT id, name, is_system FROM groups".to_string()
-        };
+    /// Writes a whole parsed backup in one transaction (older JSON backups,
+    /// which are read into memory anyway).
+    pub fn restore_backup(&self, backup: BackupData, mode: &str) -> Result<()> {
+        let mut conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+        let tx = conn.transaction()?;
+        Self::restore_groups_tx(&tx, &backup.groups, mode)?;
+        Self::restore_items_tx(&tx, backup.history, mode)?;
+        Self::finish_restore_tx(&tx, mode)?;
+        tx.commit()
+    }
 
-        let mut stmt_grp = conn.prepare(&group_sql)?;
-        let grp_params_vec: Vec<String> = if let Some(ref groups) = selected_groups {
-            groups.clone()
-        } else {
-            Vec::new()
+    /// Copies the whole database to `dest` with SQLite's online backup API.
+    /// The copy is read through its own connection in a single step, i.e. one
+    /// read transaction: a consistent snapshot, while in WAL mode the listener
+    /// keeps writing through the main connection.
+    pub fn snapshot_to(&self, dest: &std::path::Path) -> Result<()> {
+        let source_path = {
+            let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+            conn.path().filter(|p| !p.is_empty()).map(str::to_string)
         };
-        let grp_params = rusqlite::params_from_iter(grp_params_vec.iter());
+        let mut target = Connection::open(dest)?;
+        match source_path {
+            Some(path) => {
+                let source =
+                    Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+                Self::copy_database(&source, &mut target)
+            }
+            // In-memory fallback database: copy through the main connection.
+            None => {
+                let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+                Self::copy_database(&conn, &mut target)
+            }
+        }
+    }
 
-        let groups_iter = stmt_grp.query_map(grp_params, |row| {
-            Ok(Group {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                is_system: row.get(2)?,
-            })
-        })?;
-        let groups: Vec<Group> = groups_iter.collect::<Result<_, _>>()?;
+    fn copy_database(source: &Connection, target: &mut Connection) -> Result<()> {
+        use rusqlite::backup::{Backup, StepResult};
+        let backup = Backup::new(source, target)?;
+        // Busy/Locked only while another connection holds a conflicting lock.
+        for _ in 0..50 {
+            match backup.step(-1)? {
+                StepResult::Done => return Ok(()),
+                _ => std::thread::sleep(Duration::from_millis(100)),
+            }
+        }
+        Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database stayed busy".to_string()),
+        ))
+    }
 
-        let backup = BackupData {
-            history,
-            groups,
-            exported_at: chrono::Local::now().to_rfc3339(),
+    /// Starts a streamed restore; see `RestoreSession`. It writes through
+    /// its own connection, so the main one stays free while the backup is
+    /// read: in WAL mode readers see the old data until the commit, and
+    /// writers wait up to the busy timeout.
+    pub fn begin_restore(&self) -> Result<RestoreSession<'_>> {
+        let path = {
+            let conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
+            conn.path().filter(|p| !p.is_empty()).map(str::to_string)
         };
-
-        serde_json::to_string_pretty(&backup)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+        let conn = match path {
+            Some(path) => {
+                let conn = Connection::open(path)?;
+                conn.busy_timeout(RESTORE_BUSY_TIMEOUT)?;
+                conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA temp_store = MEMORY;")?;
+                query::register_sql_functions(&conn)?;
+                RestoreConnection::Own(conn)
+            }
+            // In-memory fallback database: hold the main connection instead.
+            None => RestoreConnection::Main(
+                self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?,
+            ),
+        };
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        Ok(RestoreSession { conn, open: true })
     }
 
-    pub fn restore_from_json(&self, json_content: &str, mode: &str) -> Result<()> {
-        let backup: BackupData = serde_json::from_str(json_content)
-            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-
-        let mut conn = self.conn.lock().map_err(|_| rusqlite::Error::InvalidQuery)?;
-        let tx = conn.transaction()?;
-
+    fn restore_groups_tx(tx: &Connection, groups: &[Group], mode: &str) -> Result<()> {
         if mode == "replace" {
             // Clear existing data
             tx.execute("DELETE FROM history", [])?;
             tx.execute("DELETE FROM groups", [])?;
         }
 
-        // Restore groups
-        {
-            // use INSERT OR IGNORE to handle duplicates in merge mode
-            let mut stmt =
-                tx.prepare("INSERT OR IGNORE INTO groups (name, is_system) VALUES (?1, ?2)")?;
-
-            for group in backup.groups {
-                stmt.execute(params![group.name, group.is_system])?;
-            }
+        // use INSERT OR IGNORE to handle duplicates in merge mode
+        let mut stmt = tx.prepare("INSERT OR IGNORE INTO groups (name, is_system) VALUES (?1, ?2)")?;
+        for group in groups {
+            stmt.execute(params![group.name, group.is_system])?;
         }
+        Ok(())
+    }
 
-        // Restore history
-        {
-            let mut insert_stmt = tx.prepare(
-                "INSERT INTO history (content_type, raw_content, category, is_permanent, created_at, description, is_manual, is_sensitive)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
-            )?;
-
-            // For checking existence in Merge mode
-            let mut check_stmt = tx.prepare("SELECT id FROM history WHERE raw_content = ?1")?;
+    /// Writes restored items. "merge" skips items whose content already
+    /// exists, adding the backup's groups to them; "newest" does the same but
+    /// also takes the backup's pin and description when they were edited more
+    /// recently than the local ones.
+    fn restore_items_tx(tx: &Connection, items: Vec<ClipboardItem>, mode: &str) -> Result<()> {
+        let mut insert_stmt = tx.prepare(
+            "INSERT INTO history (content_type, raw_content, category, is_permanent, created_at, description, is_manual, is_sensitive, selection, rich_content, source_app, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)"
+        )?;
 
-            let mut group_stmt = tx.prepare(
-                "INSERT OR IGNORE INTO item_groups (item_id, group_id) 
-                 SELECT ?1, id FROM groups WHERE name = ?2",
-            )?;
+        // For checking existence in Merge mode
+        let mut check_stmt = tx.prepare(
+            "SELECT id, COALESCE(updated_at, created_at) FROM history WHERE raw_content = ?1",
+        )?;
+        let mut newer_stmt = tx.prepare(
+            "UPDATE history SET is_permanent = ?2, description = ?3, updated_at = ?4 WHERE id = ?1",
+        )?;
 
-            for item in backup.history {
-                let mut item_id = -1;
+        let mut group_stmt = tx.prepare(
+            "INSERT OR IGNORE INTO item_groups (item_id, group_id) 
+             SELECT ?1, id FROM groups WHERE name = ?2",
+        )?;
+        let mut confidence_stmt = tx.prepare(
+            "INSERT OR IGNORE INTO item_group_confidence (item_id, group_id, confidence)
+             SELECT ?1, id, ?3 FROM groups WHERE name = ?2",
+        )?;
 
-                if mode == "merge" {
-                    // Check if exists
-                    let exists: Result<i64> =
-                        check_stmt.query_row(params![item.raw_content], |row| row.get(0));
-                    if let Ok(existing_id) = exists {
-                        item_id = existing_id;
+        for item in items {
+            let mut item_id = -1;
+
+            if mode == "merge" || mode == "newest" {
+                // Check if exists
+                let exists: Result<(i64, String)> = check_stmt
+                    .query_row(params![item.raw_content], |row| Ok((row.get(0)?, row.get(1)?)));
+                if let Ok((existing_id, edited_at)) = exists {
+                    item_id = existing_id;
+                    let backup_edited_at = edited_at_of(&item);
+                    if mode == "newest" && backup_edited_at > db_time(&edited_at) {
+                        newer_stmt.execute(params![
+                            existing_id,
+                            item.is_permanent,
+                            item.description,
+                            backup_edited_at
+                        ])?;
                     }
                 }
+            }
 
-                if item_id == -1 {
-                    insert_stmt.execute(params![
-                        item.content_type,
-                        item.raw_content,
-                        item.category,
-                        item.is_permanent,
-                        item.created_at,
-                        item.description,
-                        item.is_manual,
-                        item.is_sensitive
-                    ])?;
-                    item_id = tx.last_insert_rowid();
-                }
+            if item_id == -1 {
+                insert_stmt.execute(params![
+                    item.content_type,
+                    item.raw_content,
+                    item.category,
+                    item.is_permanent,
+                    item.created_at,
+                    item.description,
+                    item.is_manual,
+                    item.is_sensitive,
+                    item.selection,
+                    item.rich_content,
+                    item.source_app,
+                    item.updated_at
+                ])?;
+                item_id = tx.last_insert_rowid();
+            }
 
-                // Restore/Merge item groups
-                for g_name in item.groups {
-                    group_stmt.execute(params![item_id, g_name])?;
-                }
+            // Restore/Merge item groups
+            for g_name in item.groups {
+                group_stmt.execute(params![item_id, g_name])?;
+            }
+            for (g_name, confidence) in item.group_confidence {
+                confidence_stmt.execute(params![item_id, g_name, confidence.clamp(0.0, 1.0)])?;
             }
         }
+        Ok(())
+    }
 
-        tx.commit()?;
+    fn finish_restore_tx(tx: &Connection, mode: &str) -> Result<()> {
+        if mode == "replace" {
+            // Images of the wiped history that the backup didn't bring back.
+            Self::prune_orphan_blobs(tx)?;
+        }
         Ok(())
     }
 
@@ -1518,3 +2181,316 @@ impl ClipboardDB {
     }
 }
 
+/// A streamed restore or import in progress, from `ClipboardDB::begin_restore`.
+/// Everything written through it lands in one transaction: `commit` applies
+/// it, and dropping the session instead (cancelled, or any error along the
+/// way) rolls the database back to how it was, "replace" included.
+pub struct RestoreSession<'a> {
+    conn: RestoreConnection<'a>,
+    open: bool,
+}
+
+/// How long the restore connection waits for a write on the main one to
+/// finish (rusqlite's default, which the main connection also uses).
+const RESTORE_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum RestoreConnection<'a> {
+    /// A second connection to the database file.
+    Own(Connection),
+    Main(MutexGuard<'a, Connection>),
+}
+
+impl std::ops::Deref for RestoreConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            RestoreConnection::Own(conn) => conn,
+            RestoreConnection::Main(conn) => conn,
+        }
+    }
+}
+
+impl RestoreSession<'_> {
+    /// Adds the backup's groups (existing names are kept); "replace" first
+    /// clears history and groups.
+    pub fn groups(&self, groups: &[Group], mode: &str) -> Result<()> {
+        ClipboardDB::restore_groups_tx(&self.conn, groups, mode)
+    }
+
+    /// Writes one batch of restored items; see `restore_items_tx`.
+    pub fn items(&self, items: Vec<ClipboardItem>, mode: &str) -> Result<()> {
+        ClipboardDB::restore_items_tx(&self.conn, items, mode)
+    }
+
+    pub fn has_blob(&self, hash: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM blobs WHERE hash = ?1)",
+            params![hash],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn insert_blob(&self, hash: &str, mime: &str, data: &[u8], thumb: Option<&[u8]>) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO blobs (hash, mime, data, thumb) VALUES (?1, ?2, ?3, ?4)",
+            params![hash, mime, data, thumb],
+        )?;
+        Ok(())
+    }
+
+    /// Finishes the restore and makes it visible.
+    pub fn commit(mut self, mode: &str) -> Result<()> {
+        ClipboardDB::finish_restore_tx(&self.conn, mode)?;
+        self.conn.execute_batch("COMMIT")?;
+        self.open = false;
+        Ok(())
+    }
+}
+
+impl Drop for RestoreSession<'_> {
+    fn drop(&mut self) {
+        if self.open {
+            let _ = self.conn.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> ClipboardDB {
+        ClipboardDB::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    /// A database in a file, for what needs a second connection.
+    fn file_db(name: &str) -> ClipboardDB {
+        let dir = std::env::temp_dir().join(format!("ortu-db-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        ClipboardDB::from_connection(Connection::open(dir.join("ortu.db")).unwrap()).unwrap()
+    }
+
+    fn item(raw: &str) -> ClipboardItem {
+        serde_json::from_value(serde_json::json!({
+            "id": 0,
+            "content_type": "text",
+            "raw_content": raw,
+            "category": null,
+            "groups": ["Restored"],
+            "is_permanent": false,
+            "created_at": "2024-01-01 00:00:00",
+            "description": null,
+            "is_manual": false,
+        }))
+        .unwrap()
+    }
+
+    fn contents(db: &ClipboardDB) -> Vec<String> {
+        let conn = db.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT raw_content FROM history ORDER BY id").unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn cancelled_replace_restore_keeps_the_old_history() {
+        let db = file_db("restore");
+        db.add_manual_item("local note".to_string(), None, Some("Work".to_string()))
+            .unwrap();
+        let restored = Group { id: 0, name: "Restored".to_string(), is_system: false };
+
+        {
+            let restore = db.begin_restore().unwrap();
+            restore.groups(std::slice::from_ref(&restored), "replace").unwrap();
+            restore.items(vec![item("first batch")], "replace").unwrap();
+            // Meanwhile the app still reads through the main connection.
+            assert_eq!(contents(&db), vec!["local note"]);
+            // Cancelled here: the session is dropped without `commit`.
+        }
+        assert_eq!(contents(&db), vec!["local note"]);
+        assert_eq!(db.get_categories().unwrap(), vec!["Work"]);
+
+        let restore = db.begin_restore().unwrap();
+        restore.groups(std::slice::from_ref(&restored), "replace").unwrap();
+        restore.items(vec![item("first batch")], "replace").unwrap();
+        restore.items(vec![item("second batch")], "replace").unwrap();
+        restore.commit("replace").unwrap();
+        assert_eq!(contents(&db), vec!["first batch", "second batch"]);
+        assert_eq!(db.get_categories().unwrap(), vec!["Restored"]);
+    }
+
+    #[test]
+    fn rotation_leaves_items_changed_since_they_were_read() {
+        let db = test_db();
+        let mut ids = Vec::new();
+        for text in ["enc:a", "enc:b", "enc:c"] {
+            let item = NewItem {
+                content_type: "text",
+                content: text.to_string(),
+                rich_content: None,
+                is_sensitive: true,
+                selection: "clipboard",
+                source_app: None,
+                masked_preview: None,
+                secrets: &[],
+            };
+            ids.push(db.insert_auto_grouped_content(item, Vec::new()).unwrap().0);
+        }
+        let updates: Vec<(i64, String, String)> = db
+            .get_sensitive_payloads()
+            .unwrap()
+            .into_iter()
+            .map(|(id, raw)| (id, raw.clone(), raw.replace("enc:", "new:")))
+            .collect();
+        // Edited and deleted while the rotation was re-encrypting.
+        db.set_raw_and_sensitive(ids[1], "enc:edited", true, None).unwrap();
+        db.delete_item(ids[2]).unwrap();
+
+        let (replaced, changed) = db.replace_sensitive_payloads(&updates).unwrap();
+        assert_eq!((replaced, changed), (1, vec![ids[1]]));
+        assert_eq!(contents(&db), vec!["new:a", "enc:edited"]);
+    }
+
+    #[test]
+    fn plain_recapture_keeps_markup_unless_sensitive() {
+        let db = test_db();
+        let capture = |rich: Option<&str>, is_sensitive: bool| {
+            let item = NewItem {
+                content_type: if rich.is_some() { "html" } else { "text" },
+                content: "hello".to_string(),
+                rich_content: rich.map(str::to_string),
+                is_sensitive,
+                selection: "primary",
+                source_app: None,
+                masked_preview: None,
+                secrets: &[],
+            };
+            let (id, _) = db.insert_auto_grouped_content(item, Vec::new()).unwrap();
+            let (content_type, _) = db.get_item_payload(id).unwrap();
+            (content_type, db.get_rich_content(id).unwrap())
+        };
+        let markup = Some("<b>hello</b>".to_string());
+        assert_eq!(capture(markup.as_deref(), false), ("html".to_string(), markup.clone()));
+        assert_eq!(capture(None, false), ("html".to_string(), markup));
+        assert_eq!(capture(None, true), ("text".to_string(), None));
+    }
+
+    #[test]
+    fn drag_through_an_older_primary_row_keeps_it() {
+        let db = test_db();
+        let highlight = |text: &str| {
+            db.insert_auto_grouped_content(
+                NewItem {
+                    content_type: "text",
+                    content: text.to_string(),
+                    rich_content: None,
+                    is_sensitive: false,
+                    selection: "primary",
+                    source_app: None,
+                    masked_preview: None,
+                    secrets: &[],
+                },
+                Vec::new(),
+            )
+            .unwrap()
+        };
+        let (older, inserted) = highlight("foo");
+        assert!(inserted);
+
+        // A later drag "fo" → "foo" → "foob", as `capture_primary` tracks it:
+        // each step drops the previous draft, and only new rows become drafts.
+        let mut draft = None;
+        for text in ["fo", "foo", "foob"] {
+            if let Some(id) = draft {
+                db.discard_partial_selection(id).unwrap();
+            }
+            let (id, inserted) = highlight(text);
+            assert_eq!(inserted, text != "foo");
+            draft = inserted.then_some(id);
+        }
+        assert_eq!(contents(&db), vec!["foo", "foob"]);
+        // Had the bumped row become a draft, "foob" would have deleted it.
+        assert!(db.discard_partial_selection(older).unwrap());
+    }
+
+    #[test]
+    fn automatic_user_groups_are_not_curated_or_learned_from() {
+        let db = test_db();
+        db.create_group("Work".to_string()).unwrap();
+        let capture = |text: &str, groups: Vec<(String, f32)>| {
+            db.insert_auto_grouped_content(
+                NewItem {
+                    content_type: "text",
+                    content: text.to_string(),
+                    rich_content: None,
+                    is_sensitive: false,
+                    selection: "clipboard",
+                    source_app: None,
+                    masked_preview: None,
+                    secrets: &[],
+                },
+                groups,
+            )
+            .unwrap()
+            .0
+        };
+        let learned = vec![("Work".to_string(), 0.8)];
+        capture("guessed", learned.clone());
+        let mine = capture("filed", Vec::new());
+        db.add_to_group(mine, "Work".to_string()).unwrap();
+        // Recapturing a hand-filed item doesn't make its group automatic.
+        capture("filed", learned);
+        db.reset_learning().unwrap();
+
+        let conn = db.conn.lock().unwrap();
+        let uncurated: Vec<String> = conn
+            .prepare(&format!("SELECT raw_content FROM history WHERE {NOT_CURATED}"))
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(uncurated, vec!["guessed"]);
+        let examples: Vec<i64> = conn
+            .prepare("SELECT item_id FROM learned_examples")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(examples, vec![mine]);
+    }
+
+    #[test]
+    fn newest_restore_compares_rfc3339_and_sqlite_times() {
+        let db = test_db();
+        let restore = db.begin_restore().unwrap();
+        restore.items(vec![item("note")], "replace").unwrap();
+        restore.commit("replace").unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE history SET updated_at = '2024-01-01 11:00:00'", [])
+            .unwrap();
+        let restore_edit = |updated_at: &str| {
+            let mut edited = item("note");
+            edited.description = Some(updated_at.to_string());
+            edited.updated_at = Some(updated_at.to_string());
+            let restore = db.begin_restore().unwrap();
+            restore.items(vec![edited], "newest").unwrap();
+            restore.commit("newest").unwrap();
+            let conn = db.conn.lock().unwrap();
+            conn.query_row("SELECT description FROM history", [], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .unwrap()
+        };
+
+        // 10:00 UTC: older, even though "T" sorts after " ".
+        assert_eq!(restore_edit("2024-01-01T12:00:00+02:00"), None);
+        let newer = "2024-01-01T12:00:00Z";
+        assert_eq!(restore_edit(newer).as_deref(), Some(newer));
+    }
+}
@@ -0,0 +1,153 @@
+// Timed expiry of sensitive items.
+//
+// With the `sensitive_ttl_seconds` setting above 0, a sensitive item (auto-
+// masked on capture or marked via `set_item_sensitive`) is deleted that many
+// seconds after it became sensitive — pinned, manual and hand-grouped items
+// excepted. Groups a capture joined on its own (app rules, similar items,
+// learned groups) don't keep it. One thread sleeps until the next item is
+// due; anything that creates a sensitive item or changes the TTL calls
+// `reschedule` so the new deadline is honoured to the second rather than at
+// the hourly sweep. If the system clipboard still holds an expired secret it
+// is cleared too. That check compares SHA-256 hashes of the plain text,
+// remembered in memory when a secret is captured or copied, so it works while
+// the vault is locked.
+
+use crate::db::ClipboardDB;
+use arboard::Clipboard;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Longest sleep between checks; `reschedule` normally wakes the thread first.
+const MAX_WAIT: Duration = Duration::from_secs(3600);
+
+/// Wake-up signal for the expiry thread (true = re-read TTL and deadlines),
+/// and the plain-text hashes of sensitive items seen this session, by id.
+#[derive(Default)]
+pub struct SensitiveExpiry {
+    signal: Arc<(Mutex<bool>, Condvar)>,
+    held: Mutex<HashMap<i64, String>>,
+}
+
+/// Starts the expiry thread and registers its handle as managed state.
+pub fn start(app: &AppHandle) {
+    let expiry = SensitiveExpiry::default();
+    let signal = expiry.signal.clone();
+    app.manage(expiry);
+
+    let app = app.clone();
+    thread::spawn(move || {
+        let (dirty, wake) = &*signal;
+        loop {
+            let wait = app
+                .try_state::<ClipboardDB>()
+                .map(|db| run_due(&app, db.inner()))
+                .unwrap_or(MAX_WAIT);
+            let Ok(mut pending) = dirty.lock() else {
+                return;
+            };
+            if !*pending {
+                pending = match wake.wait_timeout(pending, wait) {
+                    Ok((guard, _)) => guard,
+                    Err(_) => return,
+                };
+            }
+            *pending = false;
+        }
+    });
+}
+
+/// Wakes the expiry thread after an item became sensitive or the TTL changed.
+pub fn reschedule(app: &AppHandle) {
+    if let Some(expiry) = app.try_state::<SensitiveExpiry>() {
+        let (dirty, wake) = &*expiry.signal;
+        if let Ok(mut pending) = dirty.lock() {
+            *pending = true;
+            wake.notify_one();
+        }
+    }
+}
+
+/// Notes that sensitive item `id` holds text whose SHA-256 is `plain_hash`
+/// (see `crate::clipboard::sha256_hex`), so it can be cleared from the
+/// clipboard when it expires even if the key is locked away by then.
+pub fn remember(app: &AppHandle, id: i64, plain_hash: String) {
+    if let Some(expiry) = app.try_state::<SensitiveExpiry>() {
+        if let Ok(mut held) = expiry.held.lock() {
+            held.insert(id, plain_hash);
+        }
+    }
+}
+
+/// Expires what is due and returns how long to sleep until the next deadline.
+fn run_due(app: &AppHandle, db: &ClipboardDB) -> Duration {
+    let ttl = db
+        .get_setting("sensitive_ttl_seconds")
+        .ok()
+        .flatten()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .unwrap_or(0);
+    if ttl <= 0 {
+        return MAX_WAIT;
+    }
+
+    match db.expire_sensitive(ttl) {
+        Ok(expired) if !expired.is_empty() => {
+            clear_clipboard_if_holding(app, &expired);
+            let _ = app.emit("clipboard-updated", ());
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Expiry: failed to delete sensitive items: {}", e),
+    }
+
+    match db.next_sensitive_expiry(ttl) {
+        // Timestamps have one-second resolution; never spin faster than that.
+        Ok(Some(secs)) => Duration::from_secs(secs.max(1) as u64).min(MAX_WAIT),
+        Ok(None) => MAX_WAIT,
+        Err(_) => Duration::from_secs(60),
+    }
+}
+
+/// Clears the system clipboard when its text is one of the expired secrets,
+/// so an expired password can't still be pasted. Secrets remembered this
+/// session are matched by hash; older ones are decrypted when the key is
+/// available.
+fn clear_clipboard_if_holding(app: &AppHandle, expired: &[(i64, String)]) {
+    let hashes: Vec<Option<String>> = match app.try_state::<SensitiveExpiry>() {
+        Some(expiry) => match expiry.held.lock() {
+            Ok(mut held) => expired.iter().map(|(id, _)| held.remove(id)).collect(),
+            Err(_) => vec![None; expired.len()],
+        },
+        None => vec![None; expired.len()],
+    };
+
+    let Ok(mut clipboard) = Clipboard::new() else {
+        return;
+    };
+    let Ok(current) = clipboard.get_text() else {
+        return;
+    };
+    let current = current.trim();
+    if current.is_empty() {
+        return;
+    }
+    let current_hash = crate::clipboard::sha256_hex(current.as_bytes());
+    let mut key = None;
+    let holds_secret = expired.iter().zip(&hashes).any(|((_, content), hash)| {
+        if let Some(hash) = hash {
+            return *hash == current_hash;
+        }
+        if !crate::crypto::is_encrypted(content) {
+            return content == current;
+        }
+        let key = key.get_or_insert_with(|| crate::crypto::get_or_create_key(app).ok());
+        key.as_ref()
+            .and_then(|key| crate::crypto::decrypt(key, content).ok())
+            .is_some_and(|plain| plain == current)
+    });
+    if holds_secret {
+        let _ = clipboard.clear();
+    }
+}
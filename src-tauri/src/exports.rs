@@ -0,0 +1,566 @@
+// Plain-file exports of a group or the whole history.
+//
+// - text: the readable layout (dividers, indented content), newest first.
+//   Only content and descriptions import back.
+// - jsonl: one `ClipboardItem` JSON object per line, oldest first.
+// - csv: a header row (`COLUMNS`), then one row per item, oldest first. A
+//   cell lists an item's groups one per line.
+// - markdown: a section per item, oldest first, with the content in a fenced
+//   code block tagged with its detected language and the other fields as JSON
+//   in an HTML comment (`<!-- ortu {…} -->`) just above the fence.
+//
+// JSONL, CSV and Markdown keep every field, so `read` returns what was
+// exported. Image items are left out of every format, since only backups
+// carry their pixels. Masked items are written as stored, encrypted under this
+// machine's key.
+
+use crate::db::ClipboardItem;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Text,
+    Jsonl,
+    Csv,
+    Markdown,
+}
+
+/// CSV header row; `read` matches columns by name, so they may be reordered.
+const COLUMNS: [&str; 13] = [
+    "created_at",
+    "updated_at",
+    "content_type",
+    "content",
+    "rich_content",
+    "description",
+    "groups",
+    "category",
+    "pinned",
+    "manual",
+    "sensitive",
+    "selection",
+    "source_app",
+];
+
+const META_OPEN: &str = "<!-- ortu ";
+const META_CLOSE: &str = " -->";
+
+impl Format {
+    /// The format called `name`, else the one `path`'s extension implies,
+    /// else text.
+    pub fn pick(name: Option<&str>, path: &Path) -> Result<Format, String> {
+        match name.filter(|n| !n.is_empty()) {
+            Some(name) => serde_json::from_value(serde_json::Value::String(name.to_string()))
+                .map_err(|_| format!("Unknown export format: {}", name)),
+            None => Ok(Self::from_extension(path).unwrap_or(Format::Text)),
+        }
+    }
+
+    pub fn from_extension(path: &Path) -> Option<Format> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "txt" => Some(Format::Text),
+            "jsonl" | "ndjson" => Some(Format::Jsonl),
+            "csv" => Some(Format::Csv),
+            "md" | "markdown" => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of an export from its first line.
+    pub fn sniff(text: &str) -> Option<Format> {
+        let start = text.trim_start_matches('\u{FEFF}').trim_start();
+        if start.starts_with('{') {
+            Some(Format::Jsonl)
+        } else if start.starts_with("# Ortu export") {
+            Some(Format::Markdown)
+        } else if start.starts_with(&format!("{},", COLUMNS[0])) {
+            Some(Format::Csv)
+        } else if start.starts_with(&divider()) {
+            Some(Format::Text)
+        } else {
+            None
+        }
+    }
+}
+
+fn divider() -> String {
+    "═".repeat(72)
+}
+
+fn thin_divider() -> String {
+    "─".repeat(72)
+}
+
+/// Writes `items` (in id order, as `get_backup_items` pages them) to `out`.
+/// `scope` is the exported group, or None for the whole history.
+pub fn write<W: Write>(
+    out: &mut W,
+    format: Format,
+    scope: Option<&str>,
+    items: &[ClipboardItem],
+) -> Result<(), String> {
+    let items: Vec<&ClipboardItem> = items
+        .iter()
+        .filter(|item| item.content_type != "image")
+        .collect();
+    let written = match format {
+        Format::Text => write_text(out, scope, &items),
+        Format::Jsonl => write_jsonl(out, &items),
+        Format::Csv => write_csv(out, &items),
+        Format::Markdown => write_markdown(out, scope, &items),
+    };
+    written.map_err(|e| e.to_string())
+}
+
+fn write_text<W: Write>(
+    out: &mut W,
+    scope: Option<&str>,
+    items: &[&ClipboardItem],
+) -> std::io::Result<()> {
+    let total = items.len();
+    let exported_at = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+    let divider = divider();
+    let thin = thin_divider();
+
+    out.write_all("\u{FEFF}".as_bytes())?; // UTF-8 BOM so text editors detect encoding correctly
+    writeln!(out, "{divider}")?;
+    match scope {
+        Some(name) => {
+            writeln!(out, "  ORTU GROUP EXPORT  —  {name}")?;
+            writeln!(out, "  Exported : {exported_at}")?;
+            writeln!(out, "  Items    : {total}")?;
+        }
+        None => {
+            writeln!(out, "  ORTU CLIPBOARD EXPORT")?;
+            writeln!(out, "  Exported : {exported_at}")?;
+            writeln!(out, "  Total    : {total} item(s)")?;
+        }
+    }
+    writeln!(out, "{divider}")?;
+
+    for (i, item) in items.iter().rev().enumerate() {
+        if i > 0 {
+            writeln!(out, "{thin}")?;
+        }
+        if let Some(desc) = item.description.as_deref().filter(|d| !d.trim().is_empty()) {
+            writeln!(out, "Description: {desc}")?;
+        }
+        writeln!(out)?;
+        for line in item.raw_content.lines() {
+            writeln!(out, "  {line}")?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "{divider}")?;
+    match scope {
+        Some(name) => writeln!(out, "  End of export  —  {name}  ({total} items)")?,
+        None => writeln!(out, "  End of export — {total} item(s)")?,
+    }
+    writeln!(out, "{divider}")
+}
+
+fn write_jsonl<W: Write>(out: &mut W, items: &[&ClipboardItem]) -> std::io::Result<()> {
+    for item in items {
+        serde_json::to_writer(&mut *out, item)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(out: &mut W, items: &[&ClipboardItem]) -> std::io::Result<()> {
+    out.write_all("\u{FEFF}".as_bytes())?; // so spreadsheets read it as UTF-8
+    let mut csv = csv::Writer::from_writer(&mut *out);
+    csv.write_record(COLUMNS)?;
+    for item in items {
+        let flag = |b: bool| if b { "true" } else { "false" };
+        csv.write_record([
+            item.created_at.as_str(),
+            item.updated_at.as_deref().unwrap_or(""),
+            item.content_type.as_str(),
+            item.raw_content.as_str(),
+            item.rich_content.as_deref().unwrap_or(""),
+            item.description.as_deref().unwrap_or(""),
+            item.groups.join("\n").as_str(),
+            item.category.as_deref().unwrap_or(""),
+            flag(item.is_permanent),
+            flag(item.is_manual),
+            flag(item.is_sensitive),
+            item.selection.as_str(),
+            item.source_app.as_deref().unwrap_or(""),
+        ])?;
+    }
+    csv.flush()
+}
+
+fn write_markdown<W: Write>(
+    out: &mut W,
+    scope: Option<&str>,
+    items: &[&ClipboardItem],
+) -> std::io::Result<()> {
+    writeln!(out, "# Ortu export — {}", scope.unwrap_or("All history"))?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Exported {} · {} item(s)",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        items.len()
+    )?;
+
+    for item in items {
+        writeln!(out)?;
+        let pinned = if item.is_permanent { " · pinned" } else { "" };
+        writeln!(out, "## {}{pinned}", item.created_at)?;
+        writeln!(out)?;
+        if let Some(desc) = item.description.as_deref().filter(|d| !d.trim().is_empty()) {
+            for line in desc.lines() {
+                writeln!(out, "> {line}")?;
+            }
+            writeln!(out)?;
+        }
+        if !item.groups.is_empty() {
+            writeln!(out, "*{}*", item.groups.join(", "))?;
+            writeln!(out)?;
+        }
+
+        // The comment must not close early, so no raw '>' in it; JSON allows
+        // any character as a \u escape inside strings, the only place '>' can
+        // appear.
+        let meta = ClipboardItem {
+            raw_content: String::new(),
+            ..(*item).clone()
+        };
+        let meta = serde_json::to_string(&meta)?.replace('>', "\\u003e");
+        writeln!(out, "{META_OPEN}{meta}{META_CLOSE}")?;
+
+        let fence = fence_for(&item.raw_content);
+        writeln!(out, "{fence}{}", fence_language(item).unwrap_or(""))?;
+        writeln!(out, "{}", item.raw_content)?;
+        writeln!(out, "{fence}")?;
+    }
+    Ok(())
+}
+
+/// A backtick fence longer than any run of backticks in `content`.
+fn fence_for(content: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat((longest + 1).max(3))
+}
+
+fn fence_language(item: &ClipboardItem) -> Option<&'static str> {
+    if item.is_sensitive {
+        return None;
+    }
+    match item.content_type.as_str() {
+        "files" => Some("json"),
+        "text" | "html" | "rtf" => {
+            Some(match crate::classifier::code_language(&item.raw_content)? {
+                "C/C++" => "cpp",
+                "TypeScript" => "typescript",
+                "JavaScript" => "javascript",
+                "Python" => "python",
+                "Rust" => "rust",
+                "Go" => "go",
+                "Java" => "java",
+                _ => return None,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Reads an export back into items, oldest first, with ids zeroed. `format`
+/// None guesses it from the text; anything unrecognised is read as the old
+/// `---`-separated plain text.
+pub fn read(text: &str, format: Option<Format>) -> Result<Vec<ClipboardItem>, String> {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+    let mut items = match format.or_else(|| Format::sniff(text)) {
+        Some(Format::Jsonl) => read_jsonl(text)?,
+        Some(Format::Csv) => read_csv(text)?,
+        Some(Format::Markdown) => read_markdown(text)?,
+        Some(Format::Text) if text.starts_with(&divider()) => read_text(text),
+        _ => read_plain(text),
+    };
+    for item in &mut items {
+        item.id = 0;
+    }
+    Ok(items)
+}
+
+fn plain_item(content: String, description: Option<String>) -> ClipboardItem {
+    ClipboardItem {
+        id: 0,
+        content_type: "text".to_string(),
+        raw_content: content,
+        category: None,
+        groups: Vec::new(),
+        group_confidence: BTreeMap::new(),
+        is_permanent: false,
+        created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        description,
+        is_manual: false,
+        is_sensitive: false,
+        selection: "clipboard".to_string(),
+        rich_content: None,
+        source_app: None,
+        updated_at: None,
+    }
+}
+
+/// The readable layout: the items sit between the second and third heavy
+/// dividers, separated by light ones, newest first.
+fn read_text(text: &str) -> Vec<ClipboardItem> {
+    let (divider, thin) = (divider(), thin_divider());
+    let body = text
+        .lines()
+        .skip_while(|l| *l == divider)
+        .skip_while(|l| *l != divider)
+        .skip(1);
+    let mut entries: Vec<Vec<&str>> = vec![Vec::new()];
+    for line in body.take_while(|l| *l != divider) {
+        if line == thin {
+            entries.push(Vec::new());
+        } else if let Some(entry) = entries.last_mut() {
+            entry.push(line);
+        }
+    }
+
+    let mut items: Vec<ClipboardItem> = entries
+        .into_iter()
+        .filter_map(|mut lines| {
+            let description = lines
+                .first()
+                .and_then(|l| l.strip_prefix("Description: "))
+                .map(str::to_string);
+            if description.is_some() {
+                lines.remove(0);
+            }
+            // Blank lines the layout puts around the content.
+            if lines.first() == Some(&"") {
+                lines.remove(0);
+            }
+            if lines.last() == Some(&"") {
+                lines.pop();
+            }
+            let content: Vec<&str> = lines
+                .iter()
+                .map(|l| l.strip_prefix("  ").unwrap_or(l))
+                .collect();
+            let content = content.join("\n");
+            (!content.trim().is_empty()).then(|| plain_item(content, description))
+        })
+        .collect();
+    items.reverse();
+    items
+}
+
+/// Plain text with entries separated by `---` lines.
+fn read_plain(text: &str) -> Vec<ClipboardItem> {
+    text.split("\n---\n")
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| plain_item(entry.to_string(), None))
+        .collect()
+}
+
+fn read_jsonl(text: &str) -> Result<Vec<ClipboardItem>, String> {
+    let mut items = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if n == 0 && line.contains("\"ortu-archive\"") {
+            return Err("This is an Ortu backup; use Restore to open it".to_string());
+        }
+        let item: ClipboardItem =
+            serde_json::from_str(line).map_err(|e| format!("Line {}: {}", n + 1, e))?;
+        items.push(item);
+    }
+    Ok(items)
+}
+
+fn read_csv(text: &str) -> Result<Vec<ClipboardItem>, String> {
+    let mut csv = csv::Reader::from_reader(text.as_bytes());
+    let headers = csv.headers().map_err(|e| e.to_string())?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let content = column("content").ok_or("The CSV file has no \"content\" column")?;
+    let columns: Vec<Option<usize>> = COLUMNS.iter().map(|name| column(name)).collect();
+
+    let mut items = Vec::new();
+    for (n, record) in csv.records().enumerate() {
+        let record = record.map_err(|e| format!("Row {}: {}", n + 2, e))?;
+        let field = |name: &str| {
+            let i = COLUMNS
+                .iter()
+                .position(|c| *c == name)
+                .and_then(|i| columns[i])?;
+            record.get(i).filter(|v| !v.is_empty())
+        };
+        let flag = |name: &str| matches!(field(name), Some("true" | "1" | "yes"));
+        let text = record.get(content).unwrap_or_default();
+        if text.is_empty() {
+            continue;
+        }
+        let mut item = plain_item(text.to_string(), field("description").map(str::to_string));
+        if let Some(created_at) = field("created_at") {
+            item.created_at = created_at.to_string();
+        }
+        item.updated_at = field("updated_at").map(str::to_string);
+        if let Some(content_type) = field("content_type") {
+            item.content_type = content_type.to_string();
+        }
+        item.rich_content = field("rich_content").map(str::to_string);
+        item.groups = field("groups")
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|g| !g.is_empty())
+            .map(str::to_string)
+            .collect();
+        item.category = field("category").map(str::to_string);
+        item.is_permanent = flag("pinned");
+        item.is_manual = flag("manual");
+        item.is_sensitive = flag("sensitive");
+        if let Some(selection) = field("selection") {
+            item.selection = selection.to_string();
+        }
+        item.source_app = field("source_app").map(str::to_string);
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// Each item is its metadata comment followed by a fenced block; everything
+/// else in the file is decoration.
+fn read_markdown(text: &str) -> Result<Vec<ClipboardItem>, String> {
+    let mut items = Vec::new();
+    let mut lines = text.split('\n').enumerate();
+    while let Some((n, line)) = lines.next() {
+        let Some(meta) = line
+            .trim_end_matches('\r')
+            .strip_prefix(META_OPEN)
+            .and_then(|l| l.strip_suffix(META_CLOSE))
+        else {
+            continue;
+        };
+        let mut item: ClipboardItem =
+            serde_json::from_str(meta).map_err(|e| format!("Line {}: {}", n + 1, e))?;
+
+        let opening = lines.next().map_or("", |(_, l)| l.trim_end_matches('\r'));
+        let ticks = opening.len() - opening.trim_start_matches('`').len();
+        if ticks < 3 {
+            return Err(format!("Line {}: expected a code block", n + 2));
+        }
+        let fence = &opening[..ticks];
+        let mut content = Vec::new();
+        let mut closed = false;
+        for (_, line) in lines.by_ref() {
+            if line.trim_end_matches('\r') == fence {
+                closed = true;
+                break;
+            }
+            content.push(line);
+        }
+        if !closed {
+            return Err(format!("Line {}: code block is never closed", n + 2));
+        }
+        item.raw_content = content.join("\n");
+        items.push(item);
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<ClipboardItem> {
+        let mut first = plain_item(
+            "SELECT *\r\nFROM t -- a > b".to_string(),
+            Some("query\nsecond line".to_string()),
+        );
+        first.id = 3;
+        first.created_at = "2026-01-02 03:04:05".to_string();
+        first.groups = vec!["SQL".to_string(), "Work; misc".to_string()];
+        first.category = Some("SQL".to_string());
+        first.is_permanent = true;
+        first.source_app = Some("org.example.Editor".to_string());
+        first.updated_at = Some("2026-02-01 00:00:00".to_string());
+
+        let mut second = plain_item(
+            "```rust\nfn main() {}\n```\n\n  indented, \"quoted\"\n".to_string(),
+            None,
+        );
+        second.id = 9;
+        second.content_type = "html".to_string();
+        second.rich_content = Some("<b>--></b>".to_string());
+        second.selection = "primary".to_string();
+        second.is_manual = true;
+
+        let mut image = plain_item("abc123".to_string(), None);
+        image.content_type = "image".to_string();
+        vec![first, second, image]
+    }
+
+    fn export(format: Format, items: &[ClipboardItem]) -> String {
+        let mut out = Vec::new();
+        write(&mut out, format, Some("Work"), items).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn structured_formats_round_trip() {
+        let items = sample();
+        let expected: Vec<ClipboardItem> = items[..2]
+            .iter()
+            .map(|item| ClipboardItem {
+                id: 0,
+                ..item.clone()
+            })
+            .collect();
+        for format in [Format::Jsonl, Format::Csv, Format::Markdown] {
+            let text = export(format, &items);
+            assert!(!text.contains("abc123"), "{format:?} exported an image");
+            assert_eq!(Format::sniff(&text), Some(format));
+            let back = read(&text, None).unwrap();
+            assert_eq!(
+                serde_json::to_value(&back).unwrap(),
+                serde_json::to_value(&expected).unwrap(),
+                "{format:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn markdown_fences_outlast_the_content() {
+        let text = export(Format::Markdown, &sample());
+        assert!(text.contains("\n````"));
+        assert!(text.contains("> query\n> second line\n"));
+        assert!(!text.contains("<b>--></b>"));
+    }
+
+    #[test]
+    fn text_layout_and_plain_text_import_back() {
+        let mut items = sample();
+        items[0].description = Some("query".to_string());
+        let text = export(Format::Text, &items);
+        assert!(text.contains("ORTU GROUP EXPORT  —  Work"));
+        let back = read(&text, Some(Format::Text)).unwrap();
+        let contents: Vec<&str> = back.iter().map(|i| i.raw_content.as_str()).collect();
+        assert_eq!(
+            contents,
+            [
+                "SELECT *\nFROM t -- a > b",
+                "```rust\nfn main() {}\n```\n\n  indented, \"quoted\""
+            ]
+        );
+        assert_eq!(back[0].description.as_deref(), Some("query"));
+
+        let back = read("one\n---\ntwo\nlines\n---\n", None).unwrap();
+        let contents: Vec<&str> = back.iter().map(|i| i.raw_content.as_str()).collect();
+        assert_eq!(contents, ["one", "two\nlines"]);
+    }
+}
@@ -0,0 +1,1173 @@
+// Importers for other clipboard managers' history.
+//
+// Each reader turns one source file into `ClipboardItem`s plus the groups they
+// belong to. The result is written through the backup restore path in "merge"
+// mode, so content already in history is matched rather than duplicated, and a
+// dry run of the same path is the preview. Only text (and file lists) are
+// imported; images and other payloads are counted as skipped.
+//
+// Supported sources:
+// - CopyQ: `.cpq` exports ("CopyQ v3", CopyQ 3.0+). Tabs become groups; notes,
+//   tags and pins are kept. CopyQ keeps no timestamps.
+// - Maccy: its `Storage.sqlite` (Core Data) database, with copy times, pins
+//   and source apps.
+// - Ditto: its `Ditto.db` SQLite database, with dates, groups and
+//   "never auto delete" as pins.
+// - Clipy: the snippets XML export. Clipy's history is a Realm database, which
+//   isn't readable here; snippet folders become groups.
+// - GPaste: `history.xml`. Passwords are imported as sensitive items.
+// - Klipper: `history2.lst` (KDE). Klipper keeps no timestamps.
+// - Ortu's own exports (JSON Lines, CSV, Markdown or text; see exports.rs),
+//   which keep their fields and groups.
+
+use crate::db::{ClipboardItem, Group};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::Path;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    CopyQ,
+    Maccy,
+    Ditto,
+    Clipy,
+    GPaste,
+    Klipper,
+    Ortu,
+}
+
+/// What a source file holds, ready for `ClipboardDB::restore_items`. Items
+/// are oldest first, so history order survives the import.
+#[derive(Debug)]
+pub struct Imported {
+    pub source: Source,
+    pub items: Vec<ClipboardItem>,
+    pub groups: Vec<Group>,
+    /// Entries with nothing importable (images, unknown payloads).
+    pub skipped: u64,
+}
+
+/// Reads `path` as `source`, or as whatever format it looks like when None.
+pub fn read(path: &Path, source: Option<Source>) -> Result<Imported, String> {
+    let source = match source {
+        Some(source) => source,
+        None => detect(path)?,
+    };
+    let mut out = Imported {
+        source,
+        items: Vec::new(),
+        groups: Vec::new(),
+        skipped: 0,
+    };
+    match source {
+        Source::CopyQ => read_copyq(&std::fs::read(path).map_err(|e| e.to_string())?, &mut out)?,
+        Source::Maccy => read_maccy(&open_sqlite(path)?, &mut out).map_err(|e| e.to_string())?,
+        Source::Ditto => read_ditto(&open_sqlite(path)?, &mut out).map_err(|e| e.to_string())?,
+        Source::Clipy => read_clipy(&read_text(path)?, &mut out)?,
+        Source::GPaste => read_gpaste(&read_text(path)?, &mut out)?,
+        Source::Klipper => {
+            read_klipper(&std::fs::read(path).map_err(|e| e.to_string())?, &mut out)?
+        }
+        Source::Ortu => read_ortu(path, &mut out)?,
+    }
+    out.finish();
+    Ok(out)
+}
+
+/// Guesses the format from the file's first bytes.
+pub fn detect(path: &Path) -> Result<Source, String> {
+    if crate::exports::Format::from_extension(path).is_some() {
+        return Ok(Source::Ortu);
+    }
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|f| f.take(4096).read_to_end(&mut head))
+        .map_err(|e| e.to_string())?;
+
+    if head.starts_with(b"SQLite format 3\0") {
+        let conn = open_sqlite(path)?;
+        let has_table = |name: &str| {
+            conn.query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [name],
+                |_| Ok(()),
+            )
+            .is_ok()
+        };
+        if has_table("ZHISTORYITEM") {
+            return Ok(Source::Maccy);
+        }
+        if has_table("Main") && has_table("Data") {
+            return Ok(Source::Ditto);
+        }
+        return Err("Not a Maccy or Ditto database".to_string());
+    }
+    if head.get(4..11) == Some(b"CopyQ v".as_slice()) {
+        return Ok(Source::CopyQ);
+    }
+    let text = String::from_utf8_lossy(&head);
+    if text.contains("<history") {
+        return Ok(Source::GPaste);
+    }
+    if text.contains("<folders") {
+        return Ok(Source::Clipy);
+    }
+    if crate::exports::Format::sniff(&text).is_some() {
+        return Ok(Source::Ortu);
+    }
+    // Klipper's file is a bare Qt data stream: CRC, then the history blob
+    // starting with the Klipper version string.
+    if head.get(12) == Some(&b'v') {
+        return Ok(Source::Klipper);
+    }
+    Err("Unrecognised file; choose the format it was exported from".to_string())
+}
+
+impl Imported {
+    /// Files every item under user group `name` as well.
+    pub fn add_to_group(&mut self, name: &str) {
+        self.group(name, false);
+        for item in &mut self.items {
+            if !item.groups.iter().any(|g| g == name) {
+                item.groups.push(name.to_string());
+            }
+        }
+    }
+
+    fn group(&mut self, name: &str, is_system: bool) {
+        if !self.groups.iter().any(|g| g.name == name) {
+            self.groups.push(Group {
+                id: 0,
+                name: name.to_string(),
+                is_system,
+            });
+        }
+    }
+
+    /// Adds a text entry; empty text counts as skipped.
+    fn text(&mut self, text: String, created_at: Option<String>) -> Option<&mut ClipboardItem> {
+        if text.trim().is_empty() {
+            self.skipped += 1;
+            return None;
+        }
+        self.items.push(entry("text", text, created_at));
+        self.items.last_mut()
+    }
+
+    /// Fills in categories and the automatic groups a capture would give, and
+    /// makes sure every group an item names is created.
+    fn finish(&mut self) {
+        let mut names = BTreeSet::new();
+        for item in &mut self.items {
+            if item.category.is_some() {
+                // Already filed (Ortu's own exports).
+            } else if item.content_type == "files" {
+                item.category = Some("Files".to_string());
+            } else if !item.is_sensitive {
+                let auto = crate::clipboard::auto_groups(item.raw_content.trim());
+                item.category = auto.first().map(|(name, _)| name.clone());
+                for (name, _) in auto {
+                    if !item.groups.contains(&name) {
+                        item.groups.push(name);
+                    }
+                }
+            }
+            names.extend(item.groups.iter().cloned());
+        }
+        for name in names {
+            let system = !self.groups.iter().any(|g| g.name == name);
+            self.group(&name, system);
+        }
+    }
+}
+
+fn entry(content_type: &str, raw_content: String, created_at: Option<String>) -> ClipboardItem {
+    ClipboardItem {
+        id: 0,
+        content_type: content_type.to_string(),
+        raw_content,
+        category: None,
+        groups: Vec::new(),
+        group_confidence: BTreeMap::new(),
+        is_permanent: false,
+        created_at: created_at.unwrap_or_else(now),
+        description: None,
+        is_manual: false,
+        is_sensitive: false,
+        selection: "clipboard".to_string(),
+        rich_content: None,
+        source_app: None,
+        updated_at: None,
+    }
+}
+
+/// `created_at` for sources without timestamps (same format as SQLite's
+/// CURRENT_TIMESTAMP).
+fn now() -> String {
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn from_unix(secs: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp(secs, 0).map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// A file list item, as the listener stores copied files: a JSON array of
+/// paths.
+fn files_entry(uris: &[String], created_at: Option<String>) -> Option<ClipboardItem> {
+    let paths: Option<Vec<String>> = uris
+        .iter()
+        .map(|uri| {
+            let path = uri.strip_prefix("file://")?;
+            urlencoding::decode(path).ok().map(|p| p.into_owned())
+        })
+        .collect();
+    let paths = paths.filter(|p| !p.is_empty())?;
+    let json = serde_json::to_string(&paths).ok()?;
+    Some(entry("files", json, created_at))
+}
+
+fn read_text(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| e.to_string())
+}
+
+/// Ortu's exports. Only group names survive an export, so any group the
+/// classifier wouldn't have given the item comes back as a user group.
+fn read_ortu(path: &Path, out: &mut Imported) -> Result<(), String> {
+    let text = read_text(path)?;
+    let format = crate::exports::Format::from_extension(path);
+    for item in crate::exports::read(&text, format)? {
+        if item.content_type == "image" {
+            out.skipped += 1;
+            continue;
+        }
+        let mut auto: Vec<String> = item.category.iter().cloned().collect();
+        if !item.is_sensitive {
+            auto.extend(
+                crate::clipboard::auto_groups(item.raw_content.trim())
+                    .into_iter()
+                    .map(|(name, _)| name),
+            );
+        }
+        for name in &item.groups {
+            if !auto.contains(name) {
+                out.group(name, false);
+            }
+        }
+        out.items.push(item);
+    }
+    Ok(())
+}
+
+fn open_sqlite(path: &Path) -> Result<Connection, String> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| e.to_string())
+}
+
+// ── Qt data streams (CopyQ, Klipper) ────────────────────────────────────────
+
+/// Reads QDataStream's big-endian encoding of the few Qt types these files
+/// use.
+struct QtStream<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+/// The QVariant types CopyQ writes in its export header. Numbers and flags
+/// (tab settings) are read past but not kept.
+#[derive(Debug)]
+enum Variant {
+    Null,
+    Scalar,
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Variant>),
+    Map(Vec<(String, Variant)>),
+}
+
+impl Variant {
+    fn get(&self, key: &str) -> Option<&Variant> {
+        match self {
+            Variant::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+const DAMAGED: &str = "The file is damaged or not in the expected format";
+/// How deeply lists and maps may nest in a QVariant. CopyQ's header goes two
+/// levels deep; the limit keeps a crafted file from overflowing the stack.
+const MAX_VARIANT_DEPTH: usize = 32;
+/// Largest payload `qt_uncompress` will inflate, whatever the file claims.
+const MAX_UNCOMPRESSED: u32 = 256 * 1024 * 1024;
+/// Most it will inflate across one file.
+#[cfg(not(test))]
+const MAX_UNCOMPRESSED_TOTAL: u64 = 1024 * 1024 * 1024;
+#[cfg(test)]
+const MAX_UNCOMPRESSED_TOTAL: u64 = 1024 * 1024;
+const TOO_LARGE: &str = "The file expands to more data than can be imported";
+
+impl<'a> QtStream<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        QtStream { buf, pos: 0 }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len());
+        let end = end.ok_or_else(|| DAMAGED.to_string())?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    /// QByteArray (also `const char *`); a null array reads as empty.
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        match self.u32()? {
+            u32::MAX => Ok(&[]),
+            len => self.take(len as usize),
+        }
+    }
+
+    /// QString: UTF-16BE with a byte length.
+    fn string(&mut self) -> Result<String, String> {
+        let raw = self.bytes()?;
+        if raw.len() % 2 != 0 {
+            return Err(DAMAGED.to_string());
+        }
+        let units: Vec<u16> = raw
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16(&units).map_err(|_| DAMAGED.to_string())
+    }
+
+    fn variant(&mut self) -> Result<Variant, String> {
+        self.variant_at(0)
+    }
+
+    fn variant_at(&mut self, depth: usize) -> Result<Variant, String> {
+        if depth > MAX_VARIANT_DEPTH {
+            return Err(DAMAGED.to_string());
+        }
+        let kind = self.u32()?;
+        let _is_null = self.u8()?;
+        Ok(match kind {
+            0 => Variant::Null,
+            1 => self.take(1).map(|_| Variant::Scalar)?,
+            2 | 3 => self.take(4).map(|_| Variant::Scalar)?,
+            4..=6 => self.take(8).map(|_| Variant::Scalar)?,
+            8 => {
+                let len = self.u32()?;
+                let mut entries = Vec::new();
+                for _ in 0..len {
+                    let key = self.string()?;
+                    entries.push((key, self.variant_at(depth + 1)?));
+                }
+                Variant::Map(entries)
+            }
+            9 => {
+                let len = self.u32()?;
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    items.push(self.variant_at(depth + 1)?);
+                }
+                Variant::List(items)
+            }
+            10 => Variant::String(self.string()?),
+            11 => {
+                let len = self.u32()?;
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    items.push(Variant::String(self.string()?));
+                }
+                Variant::List(items)
+            }
+            12 => Variant::Bytes(self.bytes()?.to_vec()),
+            other => return Err(format!("Unsupported value type {} in the file", other)),
+        })
+    }
+
+    /// Skips a PNG written straight into the stream (how QImage serialises),
+    /// by walking its chunks to IEND.
+    fn skip_png(&mut self) -> Result<(), String> {
+        if self.take(8)? != b"\x89PNG\r\n\x1a\n" {
+            return Err(DAMAGED.to_string());
+        }
+        loop {
+            let len = self.u32()? as usize;
+            let kind = self.take(4)?;
+            self.take(len + 4)?;
+            if kind == b"IEND" {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// `qUncompress`: a 4-byte expected length, then a zlib stream. Inflates no
+/// more than that length (and never past `MAX_UNCOMPRESSED`), so a small
+/// crafted stream can't expand without bound; what it inflates is taken out
+/// of `budget`, the allowance left for the whole file.
+fn qt_uncompress(data: &[u8], budget: &mut u64) -> Result<Vec<u8>, String> {
+    let head = data.get(..4).ok_or_else(|| DAMAGED.to_string())?;
+    let expected = u32::from_be_bytes(head.try_into().unwrap());
+    if expected > MAX_UNCOMPRESSED {
+        return Err(DAMAGED.to_string());
+    }
+    if u64::from(expected) > *budget {
+        return Err(TOO_LARGE.to_string());
+    }
+    let mut out = Vec::new();
+    flate2::read::ZlibDecoder::new(&data[4..])
+        .take(u64::from(expected) + 1)
+        .read_to_end(&mut out)
+        .map_err(|_| DAMAGED.to_string())?;
+    if out.len() as u64 > u64::from(expected) {
+        return Err(DAMAGED.to_string());
+    }
+    *budget -= out.len() as u64;
+    Ok(out)
+}
+
+// ── CopyQ ───────────────────────────────────────────────────────────────────
+
+const COPYQ_NOTES: &str = "application/x-copyq-item-notes";
+const COPYQ_TAGS: &str = "application/x-copyq-tags";
+const COPYQ_PINNED: &str = "application/x-copyq-item-pinned";
+
+/// CopyQ shortens MIME types in item data with a one-character prefix code.
+fn copyq_mime(stored: &str) -> String {
+    if stored.contains('/') {
+        return stored.to_string();
+    }
+    let mut chars = stored.chars();
+    let prefix = match chars.next() {
+        Some('0') => "application/x-copyq-",
+        Some('1') => "text/",
+        Some('2') => "application/",
+        Some('3') => "image/",
+        _ => "",
+    };
+    format!("{}{}", prefix, chars.as_str())
+}
+
+/// The item formats an import uses; the rest (images, other payloads) are
+/// skipped without being inflated.
+fn copyq_wanted(mime: &str) -> bool {
+    mime.starts_with("text/") || [COPYQ_NOTES, COPYQ_TAGS, COPYQ_PINNED].contains(&mime)
+}
+
+fn read_copyq(data: &[u8], out: &mut Imported) -> Result<(), String> {
+    let mut stream = QtStream::new(data);
+    if stream.bytes()? != b"CopyQ v3" {
+        return Err("Only CopyQ exports from CopyQ 3.0 or later can be imported".to_string());
+    }
+    let header = stream.variant()?;
+    let Some(Variant::List(tabs)) = header.get("tabs") else {
+        return Ok(());
+    };
+    let mut budget = MAX_UNCOMPRESSED_TOTAL;
+    for tab in tabs {
+        let name = match tab.get("name") {
+            Some(Variant::String(name)) => name.replace('&', ""),
+            _ => continue,
+        };
+        let Some(Variant::Bytes(items)) = tab.get("data") else {
+            continue;
+        };
+        // CopyQ's default tab is the plain clipboard history, not a group.
+        let group = (name != "clipboard" && !name.is_empty()).then_some(name);
+        if let Some(group) = &group {
+            out.group(group, false);
+        }
+        read_copyq_tab(items, group.as_deref(), &mut budget, out)?;
+    }
+    Ok(())
+}
+
+fn read_copyq_tab(
+    data: &[u8],
+    group: Option<&str>,
+    budget: &mut u64,
+    out: &mut Imported,
+) -> Result<(), String> {
+    let mut stream = QtStream::new(data);
+    let count = stream.i32()?.max(0);
+    let mut tab = Vec::new();
+    for _ in 0..count {
+        let mut formats: Vec<(String, Vec<u8>)> = Vec::new();
+        let len = stream.i32()?;
+        if len < 0 {
+            // Versioned item data: short MIME, compressed flag, bytes.
+            for _ in 0..stream.i32()?.max(0) {
+                let mime = copyq_mime(&stream.string()?);
+                let compressed = stream.u8()? != 0;
+                let bytes = stream.bytes()?;
+                if !copyq_wanted(&mime) {
+                    continue;
+                }
+                let bytes = if compressed {
+                    qt_uncompress(bytes, budget)?
+                } else {
+                    bytes.to_vec()
+                };
+                formats.push((mime, bytes));
+            }
+        } else {
+            // Oldest layout: full MIME, always compressed.
+            for _ in 0..len {
+                let mime = stream.string()?;
+                let bytes = stream.bytes()?;
+                if copyq_wanted(&mime) {
+                    formats.push((mime, qt_uncompress(bytes, budget)?));
+                }
+            }
+        }
+        tab.push(formats);
+    }
+    // Tabs list the newest item first.
+    let mut tags = BTreeSet::new();
+    for formats in tab.into_iter().rev() {
+        let format = |wanted: &str| {
+            formats
+                .iter()
+                .find(|(mime, _)| mime.starts_with(wanted))
+                .map(|(_, bytes)| String::from_utf8_lossy(bytes).into_owned())
+        };
+        let Some(item) = out.text(format("text/plain").unwrap_or_default(), None) else {
+            continue;
+        };
+        if let Some(html) = format("text/html") {
+            item.content_type = "html".to_string();
+            item.rich_content = Some(html);
+        }
+        item.description = format(COPYQ_NOTES).filter(|n| !n.trim().is_empty());
+        item.is_permanent = format(COPYQ_PINNED).is_some();
+        item.groups.extend(group.map(str::to_string));
+        for tag in format(COPYQ_TAGS).unwrap_or_default().split([',', '\n']) {
+            let tag = tag.trim();
+            if !tag.is_empty() && !item.groups.iter().any(|g| g == tag) {
+                item.groups.push(tag.to_string());
+                tags.insert(tag.to_string());
+            }
+        }
+    }
+    for tag in tags {
+        out.group(&tag, false);
+    }
+    Ok(())
+}
+
+// ── Maccy ───────────────────────────────────────────────────────────────────
+
+/// Core Data stores dates as seconds since 2001-01-01 UTC.
+const APPLE_EPOCH: i64 = 978_307_200;
+
+fn read_maccy(conn: &Connection, out: &mut Imported) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT i.ZTITLE, i.ZLASTCOPIEDAT, i.ZPIN, i.ZAPPLICATION,
+                (SELECT c.ZVALUE FROM ZHISTORYITEMCONTENT c
+                 WHERE c.ZITEM = i.Z_PK AND c.ZTYPE = 'public.utf8-plain-text' LIMIT 1),
+                (SELECT c.ZVALUE FROM ZHISTORYITEMCONTENT c
+                 WHERE c.ZITEM = i.Z_PK AND c.ZTYPE = 'public.html' LIMIT 1),
+                (SELECT c.ZVALUE FROM ZHISTORYITEMCONTENT c
+                 WHERE c.ZITEM = i.Z_PK AND c.ZTYPE = 'public.file-url' LIMIT 1)
+         FROM ZHISTORYITEM i
+         ORDER BY i.ZLASTCOPIEDAT",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let copied_at: Option<f64> = row.get(1)?;
+        let created_at = copied_at.and_then(|t| from_unix(t as i64 + APPLE_EPOCH));
+        let text: Option<Vec<u8>> = row.get(4)?;
+        let html: Option<Vec<u8>> = row.get(5)?;
+        let file: Option<Vec<u8>> = row.get(6)?;
+
+        let item = match (text, file) {
+            (Some(text), _) => out.text(String::from_utf8_lossy(&text).into_owned(), created_at),
+            (None, Some(url)) => {
+                let url = String::from_utf8_lossy(&url).into_owned();
+                match files_entry(&[url], created_at) {
+                    Some(item) => {
+                        out.items.push(item);
+                        out.items.last_mut()
+                    }
+                    None => {
+                        out.skipped += 1;
+                        None
+                    }
+                }
+            }
+            (None, None) => {
+                out.skipped += 1;
+                None
+            }
+        };
+        let Some(item) = item else { continue };
+        if let Some(html) = html.filter(|_| item.content_type == "text") {
+            item.content_type = "html".to_string();
+            item.rich_content = Some(String::from_utf8_lossy(&html).into_owned());
+        }
+        // ZPIN holds the pin's shortcut key; any value means pinned.
+        item.is_permanent = row.get::<_, Option<String>>(2)?.is_some();
+        item.source_app = row.get::<_, Option<String>>(3)?.filter(|a| !a.is_empty());
+        let title: Option<String> = row.get(0)?;
+        // Maccy titles default to the text itself; only a changed one is a description.
+        item.description = title.filter(|t| !t.trim().is_empty() && *t != item.raw_content);
+    }
+    Ok(())
+}
+
+// ── Ditto ───────────────────────────────────────────────────────────────────
+
+fn read_ditto(conn: &Connection, out: &mut Imported) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT m.mText, m.lDate, m.lDontAutoDelete, g.mText,
+                (SELECT d.ooData FROM Data d
+                 WHERE d.lParentID = m.lID AND d.strClipBoardFormat = 'CF_UNICODETEXT' LIMIT 1),
+                (SELECT COUNT(*) FROM Data d
+                 WHERE d.lParentID = m.lID AND d.strClipBoardFormat = 'CF_TEXT')
+         FROM Main m
+         LEFT JOIN Main g ON g.lID = m.lParentID AND g.bIsGroup = 1
+         WHERE COALESCE(m.bIsGroup, 0) = 0
+         ORDER BY m.lDate, m.lID",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let summary: Option<String> = row.get(0)?;
+        let unicode: Option<Vec<u8>> = row.get(4)?;
+        let has_ansi: i64 = row.get(5)?;
+        // CF_UNICODETEXT is NUL-terminated UTF-16LE; mText is the (editable)
+        // description, which starts out as the text itself.
+        let text = match unicode {
+            Some(raw) => {
+                let units: Vec<u16> = raw
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .take_while(|&u| u != 0)
+                    .collect();
+                Some(String::from_utf16_lossy(&units))
+            }
+            None if has_ansi > 0 => summary.clone(),
+            None => None,
+        };
+        let Some(text) = text else {
+            out.skipped += 1;
+            continue;
+        };
+        let created_at = row.get::<_, Option<i64>>(1)?.and_then(from_unix);
+        let Some(item) = out.text(text, created_at) else {
+            continue;
+        };
+        item.description = summary.filter(|s| !s.trim().is_empty() && *s != item.raw_content);
+        item.is_permanent = row.get::<_, Option<i64>>(2)?.unwrap_or(0) > 0;
+        if let Some(group) = row
+            .get::<_, Option<String>>(3)?
+            .filter(|g| !g.trim().is_empty())
+        {
+            item.groups.push(group.clone());
+            out.group(&group, false);
+        }
+    }
+    Ok(())
+}
+
+// ── XML (Clipy, GPaste) ─────────────────────────────────────────────────────
+
+#[derive(Default, Debug)]
+struct Node {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+    text: String,
+}
+
+impl Node {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'n>(&'n self, name: &'n str) -> impl Iterator<Item = &'n Node> + 'n {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+/// Parses a whole document into a tree; returns the root element.
+fn parse_xml(text: &str) -> Result<Node, String> {
+    use quick_xml::events::Event;
+    let bad = |e: &dyn std::fmt::Display| format!("Invalid XML: {}", e);
+    let mut reader = quick_xml::Reader::from_str(text);
+    let mut stack = vec![Node::default()];
+    loop {
+        match reader.read_event().map_err(|e| bad(&e))? {
+            Event::Start(e) => stack.push(xml_node(&e)?),
+            Event::Empty(e) => {
+                let node = xml_node(&e)?;
+                stack.last_mut().unwrap().children.push(node);
+            }
+            Event::End(_) => {
+                let node = stack.pop().filter(|_| !stack.is_empty());
+                let node = node.ok_or_else(|| "Invalid XML: unbalanced tags".to_string())?;
+                stack.last_mut().unwrap().children.push(node);
+            }
+            Event::Text(e) => {
+                let decoded = e.decode().map_err(|e| bad(&e))?;
+                stack.last_mut().unwrap().text.push_str(&decoded);
+            }
+            Event::CData(e) => {
+                let decoded = e.decode().map_err(|e| bad(&e))?;
+                stack.last_mut().unwrap().text.push_str(&decoded);
+            }
+            Event::GeneralRef(e) => {
+                let name = e.decode().map_err(|e| bad(&e))?;
+                let reference = format!("&{};", name);
+                let resolved = quick_xml::escape::unescape(&reference).map_err(|e| bad(&e))?;
+                stack.last_mut().unwrap().text.push_str(&resolved);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    let document = stack.pop().filter(|_| stack.is_empty());
+    document
+        .and_then(|mut doc| doc.children.pop())
+        .ok_or_else(|| "Invalid XML: no document element".to_string())
+}
+
+fn xml_node(e: &quick_xml::events::BytesStart<'_>) -> Result<Node, String> {
+    let mut node = Node {
+        name: String::from_utf8_lossy(e.local_name().as_ref()).into_owned(),
+        ..Node::default()
+    };
+    for attr in e.attributes() {
+        let attr = attr.map_err(|e| format!("Invalid XML: {}", e))?;
+        let value = attr
+            .unescape_value()
+            .map_err(|e| format!("Invalid XML: {}", e))?;
+        let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+        node.attrs.push((key, value.into_owned()));
+    }
+    Ok(node)
+}
+
+/// Clipy's snippets export: `<folders><folder><title/><snippets><snippet>
+/// <title/><content/></snippet>…`. Snippets are kept like manual items:
+/// pinned, titled, in their folder's group.
+fn read_clipy(text: &str, out: &mut Imported) -> Result<(), String> {
+    let root = parse_xml(text)?;
+    if root.name != "folders" {
+        return Err("Not a Clipy snippets export".to_string());
+    }
+    for folder in root.children("folder") {
+        let group = folder.child("title").map(|t| t.text.trim().to_string());
+        let group = group.filter(|g| !g.is_empty());
+        if let Some(group) = &group {
+            out.group(group, false);
+        }
+        let snippets = folder
+            .child("snippets")
+            .into_iter()
+            .flat_map(|s| s.children("snippet"));
+        for snippet in snippets {
+            let content = snippet
+                .child("content")
+                .map(|c| c.text.clone())
+                .unwrap_or_default();
+            let Some(item) = out.text(content, None) else {
+                continue;
+            };
+            item.is_manual = true;
+            item.is_permanent = true;
+            item.description = snippet
+                .child("title")
+                .map(|t| t.text.trim().to_string())
+                .filter(|t| !t.is_empty());
+            item.groups.extend(group.clone());
+        }
+    }
+    Ok(())
+}
+
+/// GPaste's `history.xml`, newest first. Version 2 keeps each value in a
+/// `<value>` child; version 1 has the text directly in `<item>`.
+fn read_gpaste(text: &str, out: &mut Imported) -> Result<(), String> {
+    let root = parse_xml(text)?;
+    if root.name != "history" {
+        return Err("Not a GPaste history file".to_string());
+    }
+    let items: Vec<&Node> = root.children("item").collect();
+    for node in items.into_iter().rev() {
+        let value = node
+            .child("value")
+            .map_or(node.text.as_str(), |v| v.text.as_str());
+        let created_at = node
+            .attr("date")
+            .and_then(|d| d.parse::<i64>().ok())
+            .and_then(from_unix);
+        match node.attr("kind").unwrap_or("Text") {
+            "Text" => {
+                out.text(value.to_string(), created_at);
+            }
+            "Password" => {
+                if let Some(item) = out.text(value.to_string(), created_at) {
+                    item.is_sensitive = true;
+                    item.description = node
+                        .attr("name")
+                        .map(str::to_string)
+                        .filter(|n| !n.is_empty());
+                }
+            }
+            "Uris" => {
+                let uris: Vec<String> = value
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                match files_entry(&uris, created_at.clone()) {
+                    Some(item) => out.items.push(item),
+                    None => {
+                        out.text(uris.join("\n"), created_at);
+                    }
+                }
+            }
+            _ => out.skipped += 1,
+        }
+    }
+    Ok(())
+}
+
+// ── Klipper ─────────────────────────────────────────────────────────────────
+
+/// `history2.lst`: a CRC and a byte array holding the Klipper version string
+/// followed by typed entries ("string", "url", "image"), newest first.
+fn read_klipper(data: &[u8], out: &mut Imported) -> Result<(), String> {
+    let mut file = QtStream::new(data);
+    let _crc = file.u32()?;
+    let mut stream = QtStream::new(file.bytes()?);
+    let _version = stream.bytes()?;
+    let mut entries = Vec::new();
+    while !stream.at_end() {
+        match stream.string()?.as_str() {
+            "string" => entries.push(Some((stream.string()?, false))),
+            "url" => {
+                let mut urls = Vec::new();
+                for _ in 0..stream.u32()? {
+                    urls.push(String::from_utf8_lossy(stream.bytes()?).into_owned());
+                }
+                for _ in 0..stream.u32()? {
+                    stream.string()?;
+                    stream.string()?;
+                }
+                let _cut = stream.i32()?;
+                entries.push(Some((urls.join("\n"), true)));
+            }
+            "image" => {
+                if stream.i32()? != 0 {
+                    stream.skip_png()?;
+                }
+                entries.push(None);
+            }
+            other => return Err(format!("Unknown Klipper entry type \"{}\"", other)),
+        }
+    }
+    for entry in entries.into_iter().rev() {
+        match entry {
+            Some((urls, true)) => {
+                let uris: Vec<String> = urls.lines().map(str::to_string).collect();
+                match files_entry(&uris, None) {
+                    Some(item) => out.items.push(item),
+                    None => {
+                        out.text(urls, None);
+                    }
+                }
+            }
+            Some((text, false)) => {
+                out.text(text, None);
+            }
+            None => out.skipped += 1,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal QDataStream writer mirroring `QtStream`.
+    #[derive(Default)]
+    struct QtWriter(Vec<u8>);
+
+    impl QtWriter {
+        fn u32(&mut self, v: u32) -> &mut Self {
+            self.0.extend_from_slice(&v.to_be_bytes());
+            self
+        }
+        fn bytes(&mut self, b: &[u8]) -> &mut Self {
+            self.u32(b.len() as u32);
+            self.0.extend_from_slice(b);
+            self
+        }
+        fn string(&mut self, s: &str) -> &mut Self {
+            let utf16: Vec<u8> = s.encode_utf16().flat_map(|u| u.to_be_bytes()).collect();
+            self.bytes(&utf16)
+        }
+        fn variant_header(&mut self, kind: u32) -> &mut Self {
+            self.u32(kind);
+            self.0.push(0);
+            self
+        }
+    }
+
+    fn copyq_item(w: &mut QtWriter, formats: &[(&str, &[u8])]) {
+        w.u32(-2i32 as u32).u32(formats.len() as u32);
+        for (mime, bytes) in formats {
+            w.string(mime);
+            w.0.push(0);
+            w.bytes(bytes);
+        }
+    }
+
+    #[test]
+    fn copyq_export_keeps_tabs_notes_and_pins() {
+        let mut tab = QtWriter::default();
+        tab.u32(2);
+        // Newest first: "second" is pinned with a note.
+        copyq_item(
+            &mut tab,
+            &[
+                ("1plain", b"second"),
+                ("0item-notes", b"a note"),
+                ("0item-pinned", b""),
+            ],
+        );
+        copyq_item(&mut tab, &[("1plain", b"first"), ("3png", b"\x89PNG")]);
+
+        let mut file = QtWriter::default();
+        file.bytes(b"CopyQ v3").variant_header(8).u32(1);
+        file.string("tabs").variant_header(9).u32(1);
+        file.variant_header(8).u32(2);
+        file.string("data").variant_header(12).bytes(&tab.0);
+        file.string("name").variant_header(10).string("&Work");
+
+        let dir = std::env::temp_dir().join(format!("ortu-copyq-{}.cpq", std::process::id()));
+        std::fs::write(&dir, &file.0).unwrap();
+        assert_eq!(detect(&dir).unwrap(), Source::CopyQ);
+        let mut out = Imported {
+            source: Source::CopyQ,
+            items: Vec::new(),
+            groups: Vec::new(),
+            skipped: 0,
+        };
+        read_copyq(&file.0, &mut out).unwrap();
+        std::fs::remove_file(&dir).unwrap();
+
+        let texts: Vec<&str> = out.items.iter().map(|i| i.raw_content.as_str()).collect();
+        assert_eq!(texts, ["first", "second"]);
+        assert!(!out.items[0].is_permanent);
+        assert!(out.items[1].is_permanent);
+        assert_eq!(out.items[1].description.as_deref(), Some("a note"));
+        assert_eq!(out.items[1].groups, ["Work"]);
+        assert_eq!(out.groups.len(), 1);
+    }
+
+    #[test]
+    fn klipper_history_reads_newest_last_and_skips_images() {
+        let mut history = QtWriter::default();
+        history.bytes(b"v0.9.7\0");
+        history.string("string").string("newest");
+        history.string("image").u32(1);
+        history.0.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+        history.u32(0).0.extend_from_slice(b"IEND\0\0\0\0");
+        history
+            .string("url")
+            .u32(1)
+            .bytes(b"file:///tmp/a%20b.txt")
+            .u32(0)
+            .u32(0);
+        history.string("string").string("oldest");
+        let mut file = QtWriter::default();
+        file.u32(0).bytes(&history.0);
+
+        let mut out = Imported {
+            source: Source::Klipper,
+            items: Vec::new(),
+            groups: Vec::new(),
+            skipped: 0,
+        };
+        read_klipper(&file.0, &mut out).unwrap();
+        let texts: Vec<&str> = out.items.iter().map(|i| i.raw_content.as_str()).collect();
+        assert_eq!(texts, ["oldest", r#"["/tmp/a b.txt"]"#, "newest"]);
+        assert_eq!(out.items[1].content_type, "files");
+        assert_eq!(out.skipped, 1);
+    }
+
+    #[test]
+    fn qt_streams_refuse_deep_nesting_and_oversized_payloads() {
+        let mut nested = QtWriter::default();
+        for _ in 0..=MAX_VARIANT_DEPTH + 1 {
+            nested.variant_header(9).u32(1);
+        }
+        nested.variant_header(0);
+        assert!(QtStream::new(&nested.0).variant().is_err());
+
+        let mut shallow = QtWriter::default();
+        shallow.variant_header(9).u32(1).variant_header(8).u32(0);
+        assert!(QtStream::new(&shallow.0).variant().is_ok());
+
+        let compress = |expected: u32, body: &[u8]| {
+            let mut out = expected.to_be_bytes().to_vec();
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(&mut out, flate2::Compression::default());
+            std::io::Write::write_all(&mut encoder, body).unwrap();
+            encoder.finish().unwrap();
+            out
+        };
+        let zeros = vec![0u8; 1024 * 1024];
+        let mut budget = u64::MAX;
+        assert_eq!(
+            qt_uncompress(&compress(5, b"hello"), &mut budget).unwrap(),
+            b"hello"
+        );
+        // Claims 16 bytes but inflates to a megabyte.
+        assert!(qt_uncompress(&compress(16, &zeros), &mut budget).is_err());
+        assert!(qt_uncompress(&compress(MAX_UNCOMPRESSED + 1, b""), &mut budget).is_err());
+
+        // Each payload is small, but together they pass the file's allowance;
+        // images aren't inflated at all (this one isn't even valid zlib).
+        let text = vec![b'a'; MAX_UNCOMPRESSED_TOTAL as usize / 4];
+        let tab = |items: u32| {
+            let mut tab = QtWriter::default();
+            tab.u32(items);
+            for _ in 0..items {
+                tab.u32(-2i32 as u32).u32(2);
+                tab.string("1plain").0.push(1);
+                tab.bytes(&compress(text.len() as u32, &text));
+                tab.string("3png").0.push(1);
+                tab.bytes(b"\0\0\0\x10not zlib");
+            }
+            tab.0
+        };
+        let read = |data: &[u8]| {
+            let mut out = Imported {
+                source: Source::CopyQ,
+                items: Vec::new(),
+                groups: Vec::new(),
+                skipped: 0,
+            };
+            let mut budget = MAX_UNCOMPRESSED_TOTAL;
+            read_copyq_tab(data, None, &mut budget, &mut out).map(|()| out.items.len())
+        };
+        assert_eq!(read(&tab(3)), Ok(3));
+        assert_eq!(read(&tab(5)).err().as_deref(), Some(TOO_LARGE));
+    }
+
+    #[test]
+    fn sqlite_sources_map_dates_pins_and_groups() {
+        let maccy = Connection::open_in_memory().unwrap();
+        maccy
+            .execute_batch(
+                "CREATE TABLE ZHISTORYITEM (Z_PK INTEGER PRIMARY KEY, ZTITLE TEXT,
+                     ZLASTCOPIEDAT REAL, ZPIN TEXT, ZAPPLICATION TEXT);
+                 CREATE TABLE ZHISTORYITEMCONTENT (ZITEM INTEGER, ZTYPE TEXT, ZVALUE BLOB);
+                 INSERT INTO ZHISTORYITEM VALUES (1, 'note', 700000000, 'b', 'com.apple.Safari');
+                 INSERT INTO ZHISTORYITEM VALUES (2, '', 600000000, NULL, NULL);
+                 INSERT INTO ZHISTORYITEMCONTENT VALUES (1, 'public.utf8-plain-text', CAST('pinned' AS BLOB));
+                 INSERT INTO ZHISTORYITEMCONTENT VALUES (2, 'public.png', X'89504E47');",
+            )
+            .unwrap();
+        let mut out = Imported {
+            source: Source::Maccy,
+            items: Vec::new(),
+            groups: Vec::new(),
+            skipped: 0,
+        };
+        read_maccy(&maccy, &mut out).unwrap();
+        assert_eq!(out.items.len(), 1);
+        assert_eq!(out.skipped, 1);
+        let item = &out.items[0];
+        assert_eq!(item.created_at, "2023-03-08 20:26:40");
+        assert!(item.is_permanent);
+        assert_eq!(item.description.as_deref(), Some("note"));
+        assert_eq!(item.source_app.as_deref(), Some("com.apple.Safari"));
+
+        let ditto = Connection::open_in_memory().unwrap();
+        ditto
+            .execute_batch(
+                "CREATE TABLE Main (lID INTEGER PRIMARY KEY, mText TEXT, lDate INTEGER,
+                     lDontAutoDelete INTEGER, bIsGroup INTEGER, lParentID INTEGER);
+                 CREATE TABLE Data (lParentID INTEGER, strClipBoardFormat TEXT, ooData BLOB);
+                 INSERT INTO Main VALUES (1, 'Work', 0, 0, 1, -1);
+                 INSERT INTO Main VALUES (2, 'renamed', 1700000000, 1, 0, 1);
+                 INSERT INTO Data VALUES (2, 'CF_UNICODETEXT', X'680069000000');",
+            )
+            .unwrap();
+        out.items.clear();
+        read_ditto(&ditto, &mut out).unwrap();
+        let item = &out.items[0];
+        assert_eq!(item.raw_content, "hi");
+        assert_eq!(item.description.as_deref(), Some("renamed"));
+        assert!(item.is_permanent);
+        assert_eq!(item.groups, ["Work"]);
+    }
+
+    #[test]
+    fn xml_sources_map_groups_pins_and_passwords() {
+        let clipy = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <folders><folder><title>Replies</title><snippets>
+              <snippet><title>Thanks</title><content>Thanks &amp; regards</content></snippet>
+            </snippets></folder></folders>"#;
+        let mut out = Imported {
+            source: Source::Clipy,
+            items: Vec::new(),
+            groups: Vec::new(),
+            skipped: 0,
+        };
+        read_clipy(clipy, &mut out).unwrap();
+        let item = &out.items[0];
+        assert_eq!(item.raw_content, "Thanks & regards");
+        assert_eq!(item.description.as_deref(), Some("Thanks"));
+        assert!(item.is_permanent && item.is_manual);
+        assert_eq!(item.groups, ["Replies"]);
+
+        let gpaste = r#"<history version="2.0">
+              <item kind="Password" uuid="b" date="1700000100" name="wifi"><value><![CDATA[hunter2]]></value></item>
+              <item kind="Image" uuid="c"><value>/tmp/x.png</value></item>
+              <item kind="Text" uuid="a" date="1700000000"><value><![CDATA[hello <world>]]></value></item>
+            </history>"#;
+        out.items.clear();
+        read_gpaste(gpaste, &mut out).unwrap();
+        assert_eq!(out.items[0].raw_content, "hello <world>");
+        assert_eq!(out.items[0].created_at, "2023-11-14 22:13:20");
+        assert!(out.items[1].is_sensitive);
+        assert_eq!(out.items[1].description.as_deref(), Some("wifi"));
+        assert_eq!(out.skipped, 1);
+    }
+}
@@ -0,0 +1,234 @@
+// Where the sensitive-item key ring (see crypto.rs) is kept at rest.
+//
+// By default it lives in the platform credential store — Keychain on macOS,
+// Credential Manager on Windows, Secret Service over D-Bus on Linux — as
+// service `com.ortu.clipboard`, account `sensitive-item-key`. Headless setups
+// without a credential store keep the 0600 `.sensitive_key` file in the app
+// data dir; `ORTU_KEY_BACKEND=file` forces that (moving a stored key back).
+//
+// Installs that predate the credential store are migrated on first use: the
+// key file is copied into the store, read back, then wiped and deleted. An
+// empty `.sensitive_key.keychain` marker records that the key now lives in
+// the store, so a store that is only temporarily unavailable (locked login
+// keyring, no session bus) surfaces as an error instead of silently minting a
+// new key that can't decrypt existing items.
+
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const SERVICE: &str = "com.ortu.clipboard";
+#[cfg(not(test))]
+const ACCOUNT: &str = "sensitive-item-key";
+// Keeps test runs away from a developer's real key.
+#[cfg(test)]
+const ACCOUNT: &str = "sensitive-item-key-test";
+
+const KEY_FILE: &str = ".sensitive_key";
+const MARKER_FILE: &str = ".sensitive_key.keychain";
+
+/// Where new keys are written.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    /// OS credential store, falling back to the file if it refuses the key.
+    Keychain,
+    /// The 0600 key file only.
+    File,
+}
+
+impl Backend {
+    /// The credential store unless `ORTU_KEY_BACKEND=file` is set.
+    pub fn from_env() -> Self {
+        match std::env::var("ORTU_KEY_BACKEND") {
+            Ok(v) if v.eq_ignore_ascii_case("file") => Backend::File,
+            _ => Backend::Keychain,
+        }
+    }
+}
+
+fn entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(SERVICE, ACCOUNT).map_err(|e| e.to_string())
+}
+
+/// Loads the stored key bytes, migrating a legacy key file into the
+/// credential store on the way. Ok(None) means no key has been created yet.
+pub fn load(dir: &Path, backend: Backend) -> Result<Option<Zeroizing<Vec<u8>>>, String> {
+    if let Some(key) = read_key_file(dir) {
+        if backend == Backend::Keychain {
+            match store_in_keychain(dir, &key) {
+                Ok(()) => {
+                    if let Err(e) = remove_key_file(dir) {
+                        eprintln!("Keystore: {}", e);
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Keystore: keeping key file, credential store unavailable: {}",
+                    e
+                ),
+            }
+        }
+        return Ok(Some(key));
+    }
+
+    if !dir.join(MARKER_FILE).exists() {
+        return Ok(None);
+    }
+    match entry()?.get_secret() {
+        Ok(bytes) => {
+            let key = checked(bytes)?;
+            if backend == Backend::File {
+                // Forced back to the file backend: move the key out too.
+                store(dir, &key, Backend::File)?;
+            }
+            Ok(Some(key))
+        }
+        Err(keyring::Error::NoEntry) => {
+            Err("Sensitive-item key is missing from the system keychain".to_string())
+        }
+        Err(e) => Err(format!("System keychain unavailable: {}", e)),
+    }
+}
+
+/// Saves `key` with `backend`, replacing wherever it was stored before.
+pub fn store(dir: &Path, key: &[u8], backend: Backend) -> Result<(), String> {
+    if backend == Backend::Keychain {
+        match store_in_keychain(dir, key) {
+            Ok(()) => return remove_key_file(dir),
+            Err(e) => eprintln!(
+                "Keystore: credential store unavailable, using key file: {}",
+                e
+            ),
+        }
+    }
+    write_key_file(dir, key)?;
+    // The file is read first, so a stale store entry is only clutter.
+    let _ = delete_from_keychain(dir);
+    Ok(())
+}
+
+/// Removes the key from every backend (e.g. once a vault has wrapped it).
+/// Fails if a copy may be left behind.
+pub fn delete(dir: &Path) -> Result<(), String> {
+    remove_key_file(dir)?;
+    delete_from_keychain(dir)
+}
+
+fn store_in_keychain(dir: &Path, key: &[u8]) -> Result<(), String> {
+    let entry = entry()?;
+    entry.set_secret(key).map_err(|e| e.to_string())?;
+    // Some stores accept a write they can't serve back (e.g. a locked
+    // collection); only trust the store once the key round-trips.
+    let stored = Zeroizing::new(entry.get_secret().map_err(|e| e.to_string())?);
+    if stored.as_slice() != key {
+        return Err("credential store returned a different key".to_string());
+    }
+    std::fs::write(dir.join(MARKER_FILE), b"").map_err(|e| e.to_string())
+}
+
+fn delete_from_keychain(dir: &Path) -> Result<(), String> {
+    let marker = dir.join(MARKER_FILE);
+    if !marker.exists() {
+        return Ok(());
+    }
+    match entry()?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => {
+            return Err(format!(
+                "Couldn't delete the key from the system keychain: {}",
+                e
+            ))
+        }
+    }
+    std::fs::remove_file(marker).map_err(|e| e.to_string())
+}
+
+/// Key material is one or more 32-byte keys.
+fn checked(bytes: Vec<u8>) -> Result<Zeroizing<Vec<u8>>, String> {
+    let bytes = Zeroizing::new(bytes);
+    if bytes.is_empty() || !bytes.len().is_multiple_of(32) {
+        return Err("stored key has the wrong length".to_string());
+    }
+    Ok(bytes)
+}
+
+fn read_key_file(dir: &Path) -> Option<Zeroizing<Vec<u8>>> {
+    std::fs::read(dir.join(KEY_FILE))
+        .ok()
+        .and_then(|bytes| checked(bytes).ok())
+}
+
+fn write_key_file(dir: &Path, key: &[u8]) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    crate::crypto::write_private(&dir.join(KEY_FILE), key)
+}
+
+/// Overwrites the key file before unlinking it so the bytes don't linger.
+fn remove_key_file(dir: &Path) -> Result<(), String> {
+    let path = dir.join(KEY_FILE);
+    let Ok(meta) = std::fs::metadata(&path) else {
+        return Ok(());
+    };
+    let _ = std::fs::write(&path, vec![0u8; meta.len() as usize]);
+    std::fs::remove_file(&path).map_err(|e| format!("Couldn't delete the key file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loaded(dir: &Path, backend: Backend) -> Option<Vec<u8>> {
+        load(dir, backend).unwrap().map(|key| key.to_vec())
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ortu-keystore-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn file_backend_round_trips() {
+        let dir = temp_dir("file");
+        assert_eq!(loaded(&dir, Backend::File), None);
+        store(&dir, &[3u8; 32], Backend::File).unwrap();
+        assert_eq!(loaded(&dir, Backend::File), Some(vec![3u8; 32]));
+        store(&dir, &[4u8; 64], Backend::File).unwrap();
+        assert_eq!(loaded(&dir, Backend::File), Some(vec![4u8; 64]));
+        delete(&dir).unwrap();
+        assert_eq!(loaded(&dir, Backend::File), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn delete_reports_a_key_it_could_not_remove() {
+        let dir = temp_dir("undeletable");
+        // A directory where the key file belongs can't be unlinked as a file.
+        std::fs::create_dir_all(dir.join(KEY_FILE)).unwrap();
+        assert!(delete(&dir).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    // Needs a Secret Service on the session bus. To run against a throwaway
+    // one instead of your login keyring:
+    //   dbus-run-session -- sh -c 'echo -n test | gnome-keyring-daemon \
+    //     --unlock --components=secrets && cargo test keystore -- --ignored'
+    #[test]
+    #[ignore]
+    fn migrates_key_file_into_secret_service() {
+        let dir = temp_dir("migrate");
+        write_key_file(&dir, &[9u8; 32]).unwrap();
+
+        assert_eq!(loaded(&dir, Backend::Keychain), Some(vec![9u8; 32]));
+        assert!(
+            !dir.join(KEY_FILE).exists(),
+            "key file should be gone after migration"
+        );
+        assert!(dir.join(MARKER_FILE).exists());
+        assert_eq!(loaded(&dir, Backend::Keychain), Some(vec![9u8; 32]));
+
+        delete(&dir).unwrap();
+        assert_eq!(loaded(&dir, Backend::Keychain), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
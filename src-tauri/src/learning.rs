@@ -0,0 +1,202 @@
+// Learned grouping.
+//
+// When the user files an item under one of their own groups (or takes it out
+// again), the item's words are added to (or subtracted from) that group's
+// counts. New captures are then scored against every learned group with a
+// naive Bayes model over the distinct tokens in the text that some group has
+// seen, plus a "no group" class that spreads its probability evenly over the
+// vocabulary: a group only wins when the text's words are likelier under it
+// than under no group at all, so unrelated clips stay unfiled.
+//
+// This module is the pure part (tokenizing and scoring); the counts live in
+// SQLite (`learned_groups`, `learned_tokens`, `learned_examples`; see db.rs).
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Only the start of long texts is looked at.
+const MAX_TEXT_BYTES: usize = 8 * 1024;
+
+/// Distinct tokens taken from one text.
+const MAX_TOKENS: usize = 200;
+
+/// Longest token kept; longer runs are usually hashes or base64.
+const MAX_TOKEN_CHARS: usize = 40;
+
+/// A group needs this many examples before it is suggested.
+pub const MIN_EXAMPLES: i64 = 2;
+
+/// A text needs this many tokens some group has seen to be scored.
+const MIN_KNOWN_TOKENS: usize = 2;
+
+/// How sure the model must be before an item joins a group.
+const MIN_POSTERIOR: f64 = 0.7;
+
+/// Learned groups rank just below the strongest rule matches.
+const MAX_CONFIDENCE: f64 = 0.95;
+
+/// What a learned group has seen: how many examples, how many tokens in
+/// total, and the counts of the tokens the text being scored contains.
+pub struct GroupCounts {
+    pub name: String,
+    pub examples: i64,
+    pub tokens: i64,
+    pub counts: HashMap<String, i64>,
+}
+
+/// The features of `text`: its distinct lowercase words (letters, digits and
+/// `_`, 2–40 characters, not all digits), plus `^word` for the first word.
+pub fn tokens(text: &str) -> Vec<String> {
+    let mut end = text.len().min(MAX_TEXT_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let text = &text[..end];
+
+    let mut tokens = BTreeSet::new();
+    if let Some(first) = text.split_whitespace().next() {
+        let first = first.to_lowercase();
+        if first.chars().count() <= MAX_TOKEN_CHARS {
+            tokens.insert(format!("^{}", first));
+        }
+    }
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        if tokens.len() >= MAX_TOKENS {
+            break;
+        }
+        let chars = word.chars().count();
+        if !(2..=MAX_TOKEN_CHARS).contains(&chars) || word.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        tokens.insert(word.to_lowercase());
+    }
+    tokens.into_iter().collect()
+}
+
+/// The group `tokens` most likely belong in and the model's confidence, if
+/// any group is likely enough. `vocabulary` is the number of distinct tokens
+/// across all groups.
+pub fn predict(
+    tokens: &[String],
+    groups: &[GroupCounts],
+    vocabulary: i64,
+) -> Option<(String, f32)> {
+    let total: i64 = groups.iter().map(|g| g.examples).sum();
+    // Words no group has seen say nothing about which group it is.
+    let known: Vec<&String> = tokens
+        .iter()
+        .filter(|t| groups.iter().any(|g| g.counts.contains_key(*t)))
+        .collect();
+    if known.len() < MIN_KNOWN_TOKENS || total == 0 {
+        return None;
+    }
+    let vocabulary = vocabulary.max(1) as f64;
+
+    // "No group" gets an equal share of the prior; groups split the rest by
+    // how many examples they have.
+    let none_prior = 1.0 / (groups.len() + 1) as f64;
+    let background = none_prior.ln() - known.len() as f64 * vocabulary.ln();
+    let scores: Vec<f64> = groups
+        .iter()
+        .map(|g| {
+            let prior = ((1.0 - none_prior) * g.examples as f64 / total as f64).ln();
+            let denominator = (g.tokens as f64 + vocabulary).ln();
+            prior
+                + known
+                    .iter()
+                    .map(|t| (*g.counts.get(*t).unwrap_or(&0) as f64 + 1.0).ln() - denominator)
+                    .sum::<f64>()
+        })
+        .collect();
+
+    let max = scores.iter().copied().fold(background, f64::max);
+    let sum: f64 = (background - max).exp() + scores.iter().map(|s| (s - max).exp()).sum::<f64>();
+    groups
+        .iter()
+        .zip(&scores)
+        .filter(|(g, _)| g.examples >= MIN_EXAMPLES)
+        .map(|(g, score)| (g, (score - max).exp() / sum))
+        .filter(|(_, posterior)| *posterior >= MIN_POSTERIOR)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(g, posterior)| (g.name.clone(), posterior.min(MAX_CONFIDENCE) as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts as the database would hold them after learning `examples`.
+    fn train(examples: &[(&str, &str)]) -> (Vec<GroupCounts>, i64) {
+        let mut groups: Vec<GroupCounts> = Vec::new();
+        let mut vocabulary = BTreeSet::new();
+        for (group, text) in examples {
+            let index = match groups.iter().position(|g| g.name == *group) {
+                Some(index) => index,
+                None => {
+                    groups.push(GroupCounts {
+                        name: group.to_string(),
+                        examples: 0,
+                        tokens: 0,
+                        counts: HashMap::new(),
+                    });
+                    groups.len() - 1
+                }
+            };
+            let g = &mut groups[index];
+            g.examples += 1;
+            for token in tokens(text) {
+                g.tokens += 1;
+                *g.counts.entry(token.clone()).or_default() += 1;
+                vocabulary.insert(token);
+            }
+        }
+        (groups, vocabulary.len() as i64)
+    }
+
+    #[test]
+    fn tokens_are_distinct_words_and_the_first_word() {
+        assert_eq!(
+            tokens("Deploy api-gateway to prod 2024, then deploy again"),
+            ["^deploy", "again", "api", "deploy", "gateway", "prod", "then", "to"]
+        );
+        assert!(tokens("").is_empty());
+        assert_eq!(tokens(&"é".repeat(MAX_TEXT_BYTES)).len(), 0);
+    }
+
+    #[test]
+    fn similar_text_joins_the_group_and_unrelated_text_does_not() {
+        let (groups, vocabulary) = train(&[
+            (
+                "Tickets",
+                "OPS-1201 payment service returns 502 on checkout",
+            ),
+            ("Tickets", "OPS-1188 checkout page slow for EU customers"),
+            (
+                "Tickets",
+                "OPS-1232 payment retries exhaust connection pool",
+            ),
+            ("Recipes", "2 cups flour, 1 tsp baking soda, pinch of salt"),
+            ("Recipes", "whisk eggs with sugar, fold in the flour"),
+        ]);
+        let guess = |text: &str| predict(&tokens(text), &groups, vocabulary);
+
+        let (group, confidence) = guess("OPS-1240 checkout payment fails with 500").unwrap();
+        assert_eq!(group, "Tickets");
+        assert!((0.7..=0.95).contains(&confidence));
+        assert_eq!(guess("fold the flour into the eggs").unwrap().0, "Recipes");
+        assert_eq!(guess("meet Sam at the station at six"), None);
+        assert_eq!(guess("the salt shaker is empty"), None);
+    }
+
+    #[test]
+    fn a_single_example_is_not_enough() {
+        let (groups, vocabulary) = train(&[("Tickets", "OPS-1201 payment service returns 502")]);
+        assert_eq!(
+            predict(
+                &tokens("OPS-1201 payment service returns 502"),
+                &groups,
+                vocabulary
+            ),
+            None
+        );
+    }
+}
@@ -1,8 +1,23 @@
 #![allow(unexpected_cfgs)]
+mod archive;
+mod backup;
+mod classifier;
 mod clipboard;
 mod commands;
 mod crypto;
 mod db;
+mod expiry;
+mod exports;
+mod importers;
+mod keystore;
+mod learning;
+#[cfg(target_os = "linux")]
+mod linux_clipboard;
+mod migrations;
+mod query;
+mod rules;
+mod secrets;
+mod snapshots;
 
 use db::ClipboardDB;
 use std::collections::HashMap;
@@ -63,6 +78,10 @@ pub struct TitlebarDark(pub Mutex<bool>);
 /// listener thread; persisted across restarts via the `capture_paused` setting.
 pub struct CapturePaused(pub Arc<AtomicBool>);
 
+/// Set by `cancel_backup`; a running backup or restore checks it between
+/// batches and clears it when it starts.
+pub struct BackupCancel(pub AtomicBool);
+
 /// The user-rebindable global shortcut actions.
 pub const SHORTCUT_ACTIONS: [&str; 3] = ["open_popup", "copy_stack", "paste_stack"];
 
@@ -244,6 +263,8 @@ pub fn run() {
             app.manage(PasteStack(Mutex::new(Vec::new())));
             app.manage(ShortcutMap(Mutex::new(HashMap::new())));
             app.manage(TitlebarDark(Mutex::new(true)));
+            app.manage(crypto::KeyCache::default());
+            app.manage(BackupCancel(AtomicBool::new(false)));
 
             // Restore the persisted capture-paused state so a user who paused
             // capture (e.g. for privacy) stays paused after a restart.
@@ -316,6 +337,9 @@ pub fn run() {
             // the largest idle cost). toggle_popup()/show_popup() build it on
             // demand via ensure_popup_window().
 
+            // ---------------- CLASSIFIER RULES ----------------
+            rules::start(app.handle());
+
             // ---------------- CLIPBOARD LISTENER ----------------
             startup_trace("setup: start clipboard listener");
             clipboard::start_listener(app.handle().clone());
@@ -329,6 +353,12 @@ pub fn run() {
                 }
             });
 
+            // ---------------- SCHEDULED SNAPSHOTS ----------------
+            snapshots::start(app.handle());
+
+            // ---------------- SENSITIVE EXPIRY ----------------
+            expiry::start(app.handle());
+
             // Global shortcut plugin moved to builder chain.
 
             // ---------------- AUTOSTART ----------------
@@ -357,6 +387,8 @@ pub fn run() {
         .plugin(tauri_plugin_os::init())
         .invoke_handler(tauri::generate_handler![
             commands::get_history,
+            commands::get_history_page,
+            commands::check_search_query,
             commands::delete_entry,
             commands::toggle_permanent,
             commands::set_category,
@@ -372,6 +404,15 @@ pub fn run() {
             commands::get_file_thumbnail,
             commands::set_item_sensitive,
             commands::reveal_item,
+            commands::secret_detections,
+            commands::clear_secret_detections,
+            commands::vault_status,
+            commands::enable_vault,
+            commands::disable_vault,
+            commands::unlock_vault,
+            commands::lock_vault,
+            commands::change_vault_passphrase,
+            commands::rotate_sensitive_key,
             commands::get_setting,
             commands::set_setting,
             commands::get_shortcuts,
@@ -394,6 +435,17 @@ pub fn run() {
             commands::close_window,
             commands::backup_data,
             commands::restore_data,
+            commands::cancel_backup,
+            commands::preview_import,
+            commands::import_history,
+            commands::auto_backup_status,
+            commands::run_auto_backup,
+            commands::classifier_rules_status,
+            commands::open_classifier_rules,
+            commands::classify_preview,
+            commands::learning_status,
+            commands::reset_learning,
+            commands::group_confidence_summary,
             commands::add_to_group,
             commands::remove_from_group,
             commands::export_all_txt,
@@ -747,10 +799,9 @@ fn setup_mac_popup(window: &tauri::WebviewWindow) {
 
 fn show_popup(app: &tauri::AppHandle) {
     if let Some(window) = app.get_webview_window("popup") {
-        let w = window.clone();
-
         #[cfg(target_os = "macos")]
         {
+            let w = window.clone();
             if let Some(target) = get_frontmost_app_bundle_id_macos() {
                 if let Some(state) = app.try_state::<PopupPasteTarget>() {
                     if let Ok(mut guard) = state.0.lock() {
@@ -900,3 +951,191 @@ pub(crate) fn write_clipboard_file_paths(paths: &[String]) -> bool {
 pub(crate) fn write_clipboard_file_paths(_paths: &[String]) -> bool {
     false
 }
+
+/// Reads RTF from the clipboard (macOS `public.rtf`). Returns None when the
+/// clipboard holds no RTF flavor.
+#[cfg(target_os = "macos")]
+pub(crate) fn read_clipboard_rtf() -> Option<String> {
+    unsafe {
+        let pool: id = msg_send![class!(NSAutoreleasePool), new];
+        let result = (|| {
+            let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+            if pb == nil {
+                return None;
+            }
+            let rtf_type: id =
+                msg_send![class!(NSString), stringWithUTF8String: c"public.rtf".as_ptr()];
+            let data: id = msg_send![pb, dataForType: rtf_type];
+            if data == nil {
+                return None;
+            }
+            let len: usize = msg_send![data, length];
+            let bytes: *const u8 = msg_send![data, bytes];
+            if bytes.is_null() || len == 0 {
+                return None;
+            }
+            // RTF is 7-bit ASCII with escapes for everything else.
+            let slice = std::slice::from_raw_parts(bytes, len);
+            Some(String::from_utf8_lossy(slice).into_owned())
+        })();
+        let _: () = msg_send![pool, drain];
+        result
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn read_clipboard_rtf() -> Option<String> {
+    None
+}
+
+/// Writes RTF plus its plain-text fallback to the clipboard (macOS), so the
+/// paste target can pick the richest flavor it understands.
+#[cfg(target_os = "macos")]
+pub(crate) fn write_clipboard_rtf(rtf: &str, plain: &str) -> bool {
+    unsafe {
+        let pool: id = msg_send![class!(NSAutoreleasePool), new];
+        let ok = (|| {
+            let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+            if pb == nil {
+                return false;
+            }
+            let _: () = msg_send![pb, clearContents];
+            let rtf_type: id =
+                msg_send![class!(NSString), stringWithUTF8String: c"public.rtf".as_ptr()];
+            let data: id = msg_send![class!(NSData), dataWithBytes: rtf.as_ptr() length: rtf.len()];
+            let wrote_rtf: bool = msg_send![pb, setData: data forType: rtf_type];
+            let text_type: id = msg_send![
+                class!(NSString),
+                stringWithUTF8String: c"public.utf8-plain-text".as_ptr()
+            ];
+            // init_str copies by length, so text with embedded NULs survives.
+            let ns_plain: id = NSString::alloc(nil).init_str(plain);
+            let _: id = msg_send![ns_plain, autorelease];
+            let wrote_plain: bool = msg_send![pb, setString: ns_plain forType: text_type];
+            wrote_rtf && wrote_plain
+        })();
+        let _: () = msg_send![pool, drain];
+        ok
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn write_clipboard_rtf(_rtf: &str, _plain: &str) -> bool {
+    false
+}
+
+/// Identifies the frontmost application, recorded as the source of each
+/// captured clip: the bundle id on macOS, the focused window's WM_CLASS (or
+/// process name) on Linux X11, and the executable name on Windows. None when
+/// it can't be determined, including on Wayland, which hides other clients'
+/// windows.
+#[cfg(target_os = "macos")]
+pub(crate) fn frontmost_app() -> Option<String> {
+    // Runs on the listener thread, which has no autorelease pool of its own.
+    unsafe {
+        let pool: id = msg_send![class!(NSAutoreleasePool), new];
+        let app = get_frontmost_app_bundle_id_macos();
+        let _: () = msg_send![pool, drain];
+        app
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn frontmost_app() -> Option<String> {
+    linux_clipboard::frontmost_app()
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn frontmost_app() -> Option<String> {
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_invalid() {
+            return None;
+        }
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid as *mut u32));
+        if pid == 0 {
+            return None;
+        }
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        let result = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buf.as_mut_ptr()),
+            &mut len,
+        );
+        let _ = CloseHandle(process);
+        result.ok()?;
+        let path = String::from_utf16_lossy(&buf[..len as usize]);
+        path.rsplit('\\').next().map(str::to_string)
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub(crate) fn frontmost_app() -> Option<String> {
+    None
+}
+
+/// True when whoever put the current content on `selection` asked clipboard
+/// managers not to record it: the nspasteboard.org Concealed/Transient markers
+/// on macOS, KDE's password-manager hint on Linux, and
+/// ExcludeClipboardContentFromMonitorProcessing on Windows.
+#[cfg(target_os = "macos")]
+pub(crate) fn clipboard_is_concealed(_selection: clipboard::Selection) -> bool {
+    unsafe {
+        let pool: id = msg_send![class!(NSAutoreleasePool), new];
+        let concealed = (|| {
+            let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+            if pb == nil {
+                return false;
+            }
+            let types: id = msg_send![pb, types];
+            if types == nil {
+                return false;
+            }
+            [c"org.nspasteboard.ConcealedType", c"org.nspasteboard.TransientType"]
+                .iter()
+                .any(|marker| {
+                    let marker: id =
+                        msg_send![class!(NSString), stringWithUTF8String: marker.as_ptr()];
+                    let found: bool = msg_send![types, containsObject: marker];
+                    found
+                })
+        })();
+        let _: () = msg_send![pool, drain];
+        concealed
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn clipboard_is_concealed(selection: clipboard::Selection) -> bool {
+    linux_clipboard::is_concealed(selection)
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn clipboard_is_concealed(_selection: clipboard::Selection) -> bool {
+    use windows::core::w;
+    use windows::Win32::System::DataExchange::{
+        IsClipboardFormatAvailable, RegisterClipboardFormatW,
+    };
+
+    unsafe {
+        let format = RegisterClipboardFormatW(w!("ExcludeClipboardContentFromMonitorProcessing"));
+        format != 0 && IsClipboardFormatAvailable(format).is_ok()
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+pub(crate) fn clipboard_is_concealed(_selection: clipboard::Selection) -> bool {
+    false
+}
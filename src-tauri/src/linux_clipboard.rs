@@ -0,0 +1,525 @@
+// Native clipboard change notifications on Linux.
+//
+// Without these the listener has to re-read the clipboard every 350 ms. On X11
+// we ask the server for XFixes selection-owner notifications; on Wayland we bind
+// the data-control protocol (ext-data-control-v1, or wlroots'
+// zwlr_data_control_v1) and watch its `selection` / `primary_selection` events.
+// Either way the watcher only signals *which* selection changed — the listener
+// still reads the content through arboard. When neither is available (e.g.
+// GNOME on Wayland, which implements no data-control protocol)
+// `ChangeWatcher::spawn` returns None and the listener keeps polling.
+//
+// `frontmost_app` names the application a clip came from, using the EWMH
+// active window on X11. Wayland deliberately hides other clients' windows, so
+// there it returns None and the `ignored_apps` deny-list never matches.
+// `is_concealed` looks for the password-manager hint among the formats a
+// selection is offered in. Both are only asked once the listener has seen new
+// content, and share one X11 connection that is kept open between captures.
+
+use crate::clipboard::Selection;
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+type SetupResult = Result<(), Box<dyn Error>>;
+
+/// A running watcher thread. Each message on `rx` names the selection that
+/// changed; the channel disconnects if the display connection is lost.
+pub struct ChangeWatcher {
+    pub rx: Receiver<Selection>,
+    pub backend: &'static str,
+}
+
+impl ChangeWatcher {
+    /// Starts the watcher matching the current session, or None when the
+    /// session offers no change notifications.
+    pub fn spawn() -> Option<Self> {
+        let (tx, rx) = mpsc::channel();
+        let (backend, result) = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            ("wayland data-control", wayland::spawn(tx))
+        } else if std::env::var_os("DISPLAY").is_some() {
+            ("x11 xfixes", x11::spawn(tx))
+        } else {
+            return None;
+        };
+        match result {
+            Ok(()) => {
+                log::info!("Clipboard watcher: using {backend}");
+                Some(ChangeWatcher { rx, backend })
+            }
+            Err(e) => {
+                log::info!("Clipboard watcher: {backend} unavailable ({e}); polling instead");
+                None
+            }
+        }
+    }
+}
+
+/// Target / MIME type that KeePassXC, KDE Wallet and other password managers
+/// add to secrets they copy, asking clipboard managers not to record them.
+const PASSWORD_HINT: &str = "x-kde-passwordManagerHint";
+
+/// True when the current owner of `selection` offers `PASSWORD_HINT`.
+pub fn is_concealed(selection: Selection) -> bool {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        use wl_clipboard_rs::paste::{get_mime_types, ClipboardType, Seat};
+        let kind = match selection {
+            Selection::Clipboard => ClipboardType::Regular,
+            Selection::Primary => ClipboardType::Primary,
+        };
+        // Without data-control (e.g. GNOME) arboard goes through XWayland, so
+        // ask X11 instead.
+        if let Ok(types) = get_mime_types(kind, Seat::Unspecified) {
+            return types.contains(PASSWORD_HINT);
+        }
+    }
+    if std::env::var_os("DISPLAY").is_none() {
+        return false;
+    }
+    x11::selection_has_target(selection, PASSWORD_HINT).unwrap_or(false)
+}
+
+/// WM_CLASS class of the focused X11 window (e.g. "firefox"), falling back to
+/// its process name; see `crate::frontmost_app`.
+pub fn frontmost_app() -> Option<String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() || std::env::var_os("DISPLAY").is_none() {
+        return None;
+    }
+    x11::active_window_app().ok().flatten()
+}
+
+mod x11 {
+    use super::{Error, Selection, Sender, SetupResult};
+    use std::cell::RefCell;
+    use std::time::{Duration, Instant};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+    use x11rb::protocol::xproto::{
+        Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, Window, WindowClass,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::COPY_DEPTH_FROM_PARENT;
+
+    /// An invisible 1×1 input-only window, for receiving selection events.
+    fn hidden_window(conn: &RustConnection, screen_num: usize) -> Result<Window, Box<dyn Error>> {
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            0,
+            &CreateWindowAux::new(),
+        )?;
+        Ok(window)
+    }
+
+    pub fn spawn(tx: Sender<Selection>) -> SetupResult {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        conn.xfixes_query_version(5, 0)?.reply()?;
+
+        // XFixes delivers selection events to a window we own.
+        let window = hidden_window(&conn, screen_num)?;
+
+        let clipboard = conn.intern_atom(false, b"CLIPBOARD")?.reply()?.atom;
+        for selection in [clipboard, AtomEnum::PRIMARY.into()] {
+            conn.xfixes_select_selection_input(
+                window,
+                selection,
+                SelectionEventMask::SET_SELECTION_OWNER
+                    | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )?;
+        }
+        conn.flush()?;
+
+        super::thread::spawn(move || loop {
+            match conn.wait_for_event() {
+                Ok(Event::XfixesSelectionNotify(ev)) => {
+                    let changed = if ev.selection == clipboard {
+                        Selection::Clipboard
+                    } else {
+                        Selection::Primary
+                    };
+                    if tx.send(changed).is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("Clipboard watcher: X11 connection lost: {e}");
+                    return;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// The connection `selection_has_target` and `active_window_app` query
+    /// through, with the window TARGETS replies are delivered to.
+    struct Probe {
+        conn: RustConnection,
+        root: Window,
+        window: Window,
+        clipboard: Atom,
+        targets: Atom,
+        property: Atom,
+    }
+
+    impl Probe {
+        fn connect() -> Result<Self, Box<dyn Error>> {
+            let (conn, screen_num) = x11rb::connect(None)?;
+            let root = conn.setup().roots[screen_num].root;
+            let window = hidden_window(&conn, screen_num)?;
+            let clipboard = conn.intern_atom(false, b"CLIPBOARD")?.reply()?.atom;
+            let targets = conn.intern_atom(false, b"TARGETS")?.reply()?.atom;
+            let property = conn.intern_atom(false, b"ORTU_TARGETS")?.reply()?.atom;
+            Ok(Probe {
+                conn,
+                root,
+                window,
+                clipboard,
+                targets,
+                property,
+            })
+        }
+    }
+
+    thread_local! {
+        static PROBE: RefCell<Option<Probe>> = const { RefCell::new(None) };
+    }
+
+    /// Runs `query` on this thread's probe connection, opening it on first use.
+    /// After an error the connection is dropped and reopened next time.
+    fn with_probe<T>(
+        query: impl FnOnce(&Probe) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        PROBE.with(|slot| {
+            let probe = match slot.borrow_mut().take() {
+                Some(probe) => probe,
+                None => Probe::connect()?,
+            };
+            let result = query(&probe);
+            if result.is_ok() {
+                *slot.borrow_mut() = Some(probe);
+            }
+            result
+        })
+    }
+
+    /// Asks the owner of `selection` for its TARGETS and checks for `target`.
+    /// Owners that don't answer within 200 ms count as not offering it (the
+    /// connection is then reopened, so a late reply can't be mistaken for the
+    /// next one).
+    pub fn selection_has_target(
+        selection: Selection,
+        target: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        with_probe(|probe| {
+            let conn = &probe.conn;
+            // Only clients that use the target ever intern it, so usually this
+            // settles the question without talking to the owner.
+            let wanted = conn.intern_atom(true, target.as_bytes())?.reply()?.atom;
+            if wanted == x11rb::NONE {
+                return Ok(false);
+            }
+            let selection = match selection {
+                Selection::Clipboard => probe.clipboard,
+                Selection::Primary => AtomEnum::PRIMARY.into(),
+            };
+            while conn.poll_for_event()?.is_some() {}
+            conn.convert_selection(
+                probe.window,
+                selection,
+                probe.targets,
+                probe.property,
+                x11rb::CURRENT_TIME,
+            )?;
+            conn.flush()?;
+
+            let deadline = Instant::now() + Duration::from_millis(200);
+            loop {
+                match conn.poll_for_event()? {
+                    Some(Event::SelectionNotify(ev)) if ev.requestor == probe.window => {
+                        if ev.property == x11rb::NONE {
+                            return Ok(false);
+                        }
+                        let offered = conn
+                            .get_property(
+                                true,
+                                probe.window,
+                                probe.property,
+                                AtomEnum::ATOM,
+                                0,
+                                4096,
+                            )?
+                            .reply()?;
+                        return Ok(offered
+                            .value32()
+                            .is_some_and(|mut atoms| atoms.any(|atom| atom == wanted)));
+                    }
+                    Some(_) => {}
+                    None if Instant::now() >= deadline => {
+                        return Err("selection owner did not answer TARGETS".into())
+                    }
+                    None => super::thread::sleep(Duration::from_millis(5)),
+                }
+            }
+        })
+    }
+
+    pub fn active_window_app() -> Result<Option<String>, Box<dyn Error>> {
+        with_probe(|probe| active_window_app_on(&probe.conn, probe.root))
+    }
+
+    fn active_window_app_on(
+        conn: &RustConnection,
+        root: Window,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let active = conn.intern_atom(true, b"_NET_ACTIVE_WINDOW")?.reply()?.atom;
+        if active == x11rb::NONE {
+            return Ok(None);
+        }
+        let window = conn
+            .get_property(false, root, active, AtomEnum::WINDOW, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next());
+        let Some(window) = window.filter(|w| *w != x11rb::NONE) else {
+            return Ok(None);
+        };
+
+        // WM_CLASS holds "instance\0class\0"; the class names the application.
+        let wm_class = conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?
+            .reply()?
+            .value;
+        let mut names = wm_class.split(|b| *b == 0).filter(|name| !name.is_empty());
+        let instance = names.next();
+        if let Some(class) = names.next().or(instance) {
+            return Ok(Some(String::from_utf8_lossy(class).into_owned()));
+        }
+
+        let wm_pid = conn.intern_atom(true, b"_NET_WM_PID")?.reply()?.atom;
+        if wm_pid == x11rb::NONE {
+            return Ok(None);
+        }
+        let pid = conn
+            .get_property(false, window, wm_pid, AtomEnum::CARDINAL, 0, 1)?
+            .reply()?
+            .value32()
+            .and_then(|mut values| values.next());
+        Ok(pid
+            .and_then(|pid| std::fs::read_to_string(format!("/proc/{pid}/comm")).ok())
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty()))
+    }
+}
+
+mod wayland {
+    use super::{Selection, Sender, SetupResult};
+    use wayland_client::globals::{registry_queue_init, GlobalListContents};
+    use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+    use wayland_client::{event_created_child, Connection, Dispatch, QueueHandle};
+    use wayland_protocols::ext::data_control::v1::client::{
+        ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+        ext_data_control_manager_v1::ExtDataControlManagerV1,
+        ext_data_control_offer_v1::ExtDataControlOfferV1,
+    };
+    use wayland_protocols_wlr::data_control::v1::client::{
+        zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+        zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+        zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    };
+
+    struct State {
+        tx: Sender<Selection>,
+        closed: bool,
+    }
+
+    impl State {
+        fn notify(&mut self, selection: Selection) {
+            if self.tx.send(selection).is_err() {
+                self.closed = true;
+            }
+        }
+    }
+
+    pub fn spawn(tx: Sender<Selection>) -> SetupResult {
+        let conn = Connection::connect_to_env()?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn)?;
+        let qh = queue.handle();
+        let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+
+        // Prefer the standardized protocol; wlroots compositors that predate it
+        // only expose the zwlr variant (v2+ adds PRIMARY selection events).
+        if let Ok(manager) = globals.bind::<ExtDataControlManagerV1, _, _>(&qh, 1..=1, ()) {
+            manager.get_data_device(&seat, &qh, ());
+        } else {
+            let manager: ZwlrDataControlManagerV1 = globals.bind(&qh, 1..=2, ())?;
+            manager.get_data_device(&seat, &qh, ());
+        }
+
+        let mut state = State { tx, closed: false };
+        queue.roundtrip(&mut state)?;
+
+        super::thread::spawn(move || {
+            let _conn = conn;
+            while !state.closed {
+                if let Err(e) = queue.blocking_dispatch(&mut state) {
+                    log::warn!("Clipboard watcher: Wayland connection lost: {e}");
+                    return;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    impl Dispatch<WlRegistry, GlobalListContents> for State {
+        fn event(
+            _: &mut Self,
+            _: &WlRegistry,
+            _: <WlRegistry as wayland_client::Proxy>::Event,
+            _: &GlobalListContents,
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<WlSeat, ()> for State {
+        fn event(
+            _: &mut Self,
+            _: &WlSeat,
+            _: <WlSeat as wayland_client::Proxy>::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    // ── ext-data-control-v1 ────────────────────────────────────────────────────
+
+    impl Dispatch<ExtDataControlManagerV1, ()> for State {
+        fn event(
+            _: &mut Self,
+            _: &ExtDataControlManagerV1,
+            _: <ExtDataControlManagerV1 as wayland_client::Proxy>::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<ExtDataControlDeviceV1, ()> for State {
+        fn event(
+            state: &mut Self,
+            _: &ExtDataControlDeviceV1,
+            event: ext_data_control_device_v1::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            match event {
+                // We never read through the offer (arboard does), so release it.
+                ext_data_control_device_v1::Event::Selection { id } => {
+                    if let Some(offer) = id {
+                        offer.destroy();
+                    }
+                    state.notify(Selection::Clipboard);
+                }
+                ext_data_control_device_v1::Event::PrimarySelection { id } => {
+                    if let Some(offer) = id {
+                        offer.destroy();
+                    }
+                    state.notify(Selection::Primary);
+                }
+                ext_data_control_device_v1::Event::Finished => state.closed = true,
+                _ => {}
+            }
+        }
+
+        event_created_child!(State, ExtDataControlDeviceV1, [
+            ext_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ExtDataControlOfferV1, ()),
+        ]);
+    }
+
+    impl Dispatch<ExtDataControlOfferV1, ()> for State {
+        fn event(
+            _: &mut Self,
+            _: &ExtDataControlOfferV1,
+            _: <ExtDataControlOfferV1 as wayland_client::Proxy>::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    // ── zwlr_data_control_v1 ───────────────────────────────────────────────────
+
+    impl Dispatch<ZwlrDataControlManagerV1, ()> for State {
+        fn event(
+            _: &mut Self,
+            _: &ZwlrDataControlManagerV1,
+            _: <ZwlrDataControlManagerV1 as wayland_client::Proxy>::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    impl Dispatch<ZwlrDataControlDeviceV1, ()> for State {
+        fn event(
+            state: &mut Self,
+            _: &ZwlrDataControlDeviceV1,
+            event: zwlr_data_control_device_v1::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+            match event {
+                zwlr_data_control_device_v1::Event::Selection { id } => {
+                    if let Some(offer) = id {
+                        offer.destroy();
+                    }
+                    state.notify(Selection::Clipboard);
+                }
+                zwlr_data_control_device_v1::Event::PrimarySelection { id } => {
+                    if let Some(offer) = id {
+                        offer.destroy();
+                    }
+                    state.notify(Selection::Primary);
+                }
+                zwlr_data_control_device_v1::Event::Finished => state.closed = true,
+                _ => {}
+            }
+        }
+
+        event_created_child!(State, ZwlrDataControlDeviceV1, [
+            zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+        ]);
+    }
+
+    impl Dispatch<ZwlrDataControlOfferV1, ()> for State {
+        fn event(
+            _: &mut Self,
+            _: &ZwlrDataControlOfferV1,
+            _: <ZwlrDataControlOfferV1 as wayland_client::Proxy>::Event,
+            _: &(),
+            _: &Connection,
+            _: &QueueHandle<Self>,
+        ) {
+        }
+    }
+}
@@ -0,0 +1,621 @@
+// Versioned schema migrations for ortu.db.
+//
+// The schema version lives in `app_meta` under `schema_version`. On open, every
+// migration newer than the stored version runs in order inside one
+// transaction, so an upgrade either completes or leaves the database exactly as
+// it was. A database written by a newer build (stored version above
+// `SCHEMA_VERSION`) is refused rather than guessed at. Before any destructive
+// step the database file is copied next to itself with `VACUUM INTO`.
+//
+// Databases from before this runner existed have no `schema_version` and are
+// treated as version 0; every migration is written to be safe on them.
+
+use rusqlite::{params, Connection, Result, Transaction};
+
+struct Migration {
+    version: i64,
+    description: &'static str,
+    /// Rewrites or drops existing data; triggers a file backup first.
+    destructive: bool,
+    up: fn(&Transaction<'_>) -> Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline schema",
+        destructive: false,
+        up: baseline,
+    },
+    Migration {
+        version: 2,
+        description: "repair item_groups foreign key",
+        destructive: true,
+        up: repair_item_groups_fk,
+    },
+    Migration {
+        version: 3,
+        description: "record capture selection",
+        destructive: false,
+        up: add_selection,
+    },
+    Migration {
+        version: 4,
+        description: "store rich-text flavor",
+        destructive: false,
+        up: add_rich_content,
+    },
+    Migration {
+        version: 5,
+        description: "index history by (created_at, id)",
+        destructive: false,
+        up: add_history_page_index,
+    },
+    Migration {
+        version: 6,
+        description: "record source application",
+        destructive: false,
+        up: add_source_app,
+    },
+    Migration {
+        version: 7,
+        description: "record when items became sensitive",
+        destructive: false,
+        up: add_sensitive_at,
+    },
+    Migration {
+        version: 8,
+        description: "record when pin or description changed",
+        destructive: false,
+        up: add_updated_at,
+    },
+    Migration {
+        version: 9,
+        description: "learn groups from manual regrouping",
+        destructive: false,
+        up: add_learning,
+    },
+    Migration {
+        version: 10,
+        description: "record secret detections",
+        destructive: false,
+        up: add_secret_detections,
+    },
+];
+
+/// Schema version this build writes. Bump it by appending to `MIGRATIONS`.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Returned when the database was written by a newer Ortu. Opening it anyway
+/// could silently drop columns or data this build doesn't know about.
+#[derive(Debug)]
+pub struct NewerSchemaError {
+    pub found: i64,
+    pub supported: i64,
+}
+
+impl std::fmt::Display for NewerSchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "database schema version {} is newer than this version of Ortu supports ({}); \
+             please update Ortu",
+            self.found, self.supported
+        )
+    }
+}
+
+impl std::error::Error for NewerSchemaError {}
+
+/// Current schema version of `conn` (0 for a new or pre-migration database).
+pub fn schema_version(conn: &Connection) -> Result<i64> {
+    let has_meta: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'app_meta')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_meta {
+        return Ok(0);
+    }
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM app_meta WHERE key = 'schema_version'",
+            [],
+            |row| row.get(0),
+        )
+        .ok();
+    Ok(value.and_then(|v| v.parse().ok()).unwrap_or(0))
+}
+
+/// Brings `conn` up to `SCHEMA_VERSION`. Returns the version it started from.
+pub fn run(conn: &mut Connection) -> Result<i64> {
+    let from = schema_version(conn)?;
+    if from > SCHEMA_VERSION {
+        return Err(rusqlite::Error::ToSqlConversionFailure(Box::new(
+            NewerSchemaError {
+                found: from,
+                supported: SCHEMA_VERSION,
+            },
+        )));
+    }
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > from).collect();
+    if pending.is_empty() {
+        return Ok(from);
+    }
+
+    if pending.iter().any(|m| m.destructive) {
+        backup_before_upgrade(conn, from)?;
+    }
+
+    // Table rebuilds need foreign keys off, and the pragma is a no-op inside a
+    // transaction, so toggle it around the whole run.
+    conn.execute_batch("PRAGMA foreign_keys = OFF;")?;
+    let result = apply(conn, &pending);
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    result?;
+    Ok(from)
+}
+
+fn apply(conn: &mut Connection, pending: &[&Migration]) -> Result<()> {
+    let tx = conn.transaction()?;
+    for migration in pending {
+        log::info!(
+            "DB: migrating to v{} ({})",
+            migration.version,
+            migration.description
+        );
+        (migration.up)(&tx)?;
+    }
+    tx.execute(
+        "INSERT INTO app_meta (key, value) VALUES ('schema_version', ?1)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![SCHEMA_VERSION.to_string()],
+    )?;
+    // The rebuilt table must satisfy its foreign keys before we commit.
+    let violations: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM pragma_foreign_key_check('item_groups')",
+        [],
+        |row| row.get(0),
+    )?;
+    if violations > 0 {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+            Some(format!(
+                "migration left {} foreign key violation(s)",
+                violations
+            )),
+        ));
+    }
+    tx.commit()
+}
+
+/// Copies a non-empty file-backed database to `<db>.v<from>.bak` before a
+/// destructive upgrade. An existing backup for the same version is kept, so a
+/// retried upgrade never overwrites the original copy.
+fn backup_before_upgrade(conn: &Connection, from: i64) -> Result<()> {
+    let path = match conn.path() {
+        Some(p) if !p.is_empty() => std::path::PathBuf::from(p),
+        _ => return Ok(()), // in-memory
+    };
+    let has_history: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'history')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_history {
+        return Ok(());
+    }
+    let mut backup = path.into_os_string();
+    backup.push(format!(".v{}.bak", from));
+    let backup = std::path::PathBuf::from(backup);
+    if backup.exists() {
+        return Ok(());
+    }
+    log::info!("DB: backing up to '{}' before upgrading", backup.display());
+    conn.execute("VACUUM INTO ?1", params![backup.to_string_lossy()])?;
+    Ok(())
+}
+
+fn has_column(tx: &Transaction<'_>, table: &str, column: &str) -> Result<bool> {
+    tx.query_row(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        params![table, column],
+        |row| row.get(0),
+    )
+}
+
+fn add_column(tx: &Transaction<'_>, table: &str, column: &str, decl: &str) -> Result<()> {
+    if !has_column(tx, table, column)? {
+        tx.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl};"))?;
+    }
+    Ok(())
+}
+
+// ── Migrations ─────────────────────────────────────────────────────────────
+
+/// v1: every table, column and index that predates the migration runner. Safe
+/// on a fresh database and on any older layout.
+fn baseline(tx: &Transaction<'_>) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS groups (
+            id INTEGER PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL,
+            is_system BOOLEAN DEFAULT 0
+        );
+
+        CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY,
+            content_type TEXT NOT NULL,
+            raw_content TEXT NOT NULL,
+            category TEXT,
+            is_permanent BOOLEAN DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS item_groups (
+            item_id INTEGER NOT NULL,
+            group_id INTEGER NOT NULL,
+            PRIMARY KEY (item_id, group_id),
+            FOREIGN KEY(item_id) REFERENCES history(id) ON DELETE CASCADE,
+            FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS item_group_confidence (
+            item_id INTEGER NOT NULL,
+            group_id INTEGER NOT NULL,
+            confidence REAL NOT NULL,
+            PRIMARY KEY (item_id, group_id),
+            FOREIGN KEY(item_id) REFERENCES history(id) ON DELETE CASCADE,
+            FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS app_meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS snippets (
+            id INTEGER PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL,
+            body TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+
+        -- Content-addressed binary store for clipboard images. A history row of
+        -- content_type 'image' references a blob by storing its hash in
+        -- raw_content; identical images are deduplicated by hash.
+        CREATE TABLE IF NOT EXISTS blobs (
+            hash TEXT PRIMARY KEY,
+            mime TEXT NOT NULL,
+            data BLOB NOT NULL,
+            thumb BLOB,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+    )?;
+
+    add_column(tx, "history", "description", "TEXT")?;
+    add_column(tx, "history", "is_manual", "BOOLEAN DEFAULT 0")?;
+    add_column(tx, "history", "is_sensitive", "BOOLEAN DEFAULT 0")?;
+
+    // The single `category` column predates many-to-many groups; carry it over.
+    tx.execute_batch(
+        "INSERT OR IGNORE INTO groups (name)
+         SELECT DISTINCT category FROM history WHERE category IS NOT NULL;
+
+         INSERT OR IGNORE INTO item_groups (item_id, group_id)
+         SELECT h.id, g.id
+         FROM history h
+         JOIN groups g ON h.category = g.name
+         WHERE h.category IS NOT NULL;
+
+         CREATE INDEX IF NOT EXISTS idx_created_at ON history(created_at DESC);
+         CREATE INDEX IF NOT EXISTS idx_item_groups_item_id ON item_groups(item_id);
+         CREATE INDEX IF NOT EXISTS idx_item_groups_group_id ON item_groups(group_id);
+         CREATE INDEX IF NOT EXISTS idx_item_group_confidence_item_id ON item_group_confidence(item_id);",
+    )
+}
+
+/// v2: some early builds created `item_groups` with its item FK pointing at a
+/// `clipboard_items` table that no longer exists. Rebuild it against `history`,
+/// keeping every membership whose item and group still exist.
+fn repair_item_groups_fk(tx: &Transaction<'_>) -> Result<()> {
+    let broken: bool = tx.query_row(
+        "SELECT EXISTS (
+            SELECT 1 FROM pragma_foreign_key_list('item_groups')
+            WHERE \"table\" = 'clipboard_items'
+         )",
+        [],
+        |row| row.get(0),
+    )?;
+    if !broken {
+        return Ok(());
+    }
+    tx.execute_batch(
+        "CREATE TABLE item_groups_new (
+            item_id INTEGER NOT NULL,
+            group_id INTEGER NOT NULL,
+            PRIMARY KEY (item_id, group_id),
+            FOREIGN KEY(item_id) REFERENCES history(id) ON DELETE CASCADE,
+            FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+        );
+
+        INSERT OR IGNORE INTO item_groups_new (item_id, group_id)
+        SELECT ig.item_id, ig.group_id
+        FROM item_groups ig
+        WHERE EXISTS (SELECT 1 FROM history h WHERE h.id = ig.item_id)
+          AND EXISTS (SELECT 1 FROM groups g WHERE g.id = ig.group_id);
+
+        DROP TABLE item_groups;
+        ALTER TABLE item_groups_new RENAME TO item_groups;
+        CREATE INDEX IF NOT EXISTS idx_item_groups_item_id ON item_groups(item_id);
+        CREATE INDEX IF NOT EXISTS idx_item_groups_group_id ON item_groups(group_id);",
+    )
+}
+
+/// v3: which system selection ('clipboard' / 'primary') an item came from.
+fn add_selection(tx: &Transaction<'_>) -> Result<()> {
+    add_column(
+        tx,
+        "history",
+        "selection",
+        "TEXT NOT NULL DEFAULT 'clipboard'",
+    )
+}
+
+/// v4: HTML/RTF markup for 'html' / 'rtf' items; raw_content keeps the plain
+/// text, which is all the FTS index sees.
+fn add_rich_content(tx: &Transaction<'_>) -> Result<()> {
+    add_column(tx, "history", "rich_content", "TEXT")
+}
+
+/// v5: covers the newest-first keyset order used by paginated history.
+fn add_history_page_index(tx: &Transaction<'_>) -> Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_history_created_at_id ON history(created_at DESC, id DESC);",
+    )
+}
+
+/// v6: the frontmost application when an item was captured (bundle id on
+/// macOS, WM_CLASS or process name on Linux, executable name on Windows).
+fn add_source_app(tx: &Transaction<'_>) -> Result<()> {
+    add_column(tx, "history", "source_app", "TEXT")?;
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_history_source_app ON history(source_app COLLATE NOCASE);",
+    )
+}
+
+/// v7: when an item was captured as or marked sensitive, the start of its
+/// `sensitive_ttl_seconds` countdown. Existing sensitive rows count from their
+/// capture time.
+fn add_sensitive_at(tx: &Transaction<'_>) -> Result<()> {
+    add_column(tx, "history", "sensitive_at", "TEXT")?;
+    tx.execute_batch("UPDATE history SET sensitive_at = created_at WHERE is_sensitive = 1;")
+}
+
+/// v8: when an item's pin or description last changed (NULL = never since
+/// capture), so a "newest" restore can tell which side's edits are newer. A
+/// trigger stamps it unless the update sets it explicitly, as restore does.
+fn add_updated_at(tx: &Transaction<'_>) -> Result<()> {
+    add_column(tx, "history", "updated_at", "TEXT")?;
+    tx.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS history_touch_updated_at
+         AFTER UPDATE OF description, is_permanent ON history
+         WHEN NEW.updated_at IS OLD.updated_at
+              AND (NEW.description IS NOT OLD.description
+                   OR NEW.is_permanent IS NOT OLD.is_permanent)
+         BEGIN
+             UPDATE history SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+         END;",
+    )
+}
+
+/// v9: the learned-grouping model (see learning.rs). `learned_groups` and
+/// `learned_tokens` hold per-group example and token counts; they outlive the
+/// items they were learned from. `learned_examples` records the tokens each
+/// item contributed, so taking it out of the group subtracts exactly those.
+fn add_learning(tx: &Transaction<'_>) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS learned_groups (
+            group_id INTEGER PRIMARY KEY,
+            examples INTEGER NOT NULL,
+            tokens INTEGER NOT NULL,
+            FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS learned_tokens (
+            group_id INTEGER NOT NULL,
+            token TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (group_id, token),
+            FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS learned_examples (
+            item_id INTEGER NOT NULL,
+            group_id INTEGER NOT NULL,
+            tokens TEXT NOT NULL,
+            PRIMARY KEY (item_id, group_id),
+            FOREIGN KEY(item_id) REFERENCES history(id) ON DELETE CASCADE,
+            FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_learned_tokens_token ON learned_tokens(token);",
+    )
+}
+
+/// v10: `masked_preview` is (the start of) a masked item's text with only its
+/// secrets hidden, so the UI can show the rest. Detections outlive their items
+/// for auditing and never include the secret itself.
+fn add_secret_detections(tx: &Transaction<'_>) -> Result<()> {
+    add_column(tx, "history", "masked_preview", "TEXT")?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS secret_detections (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            item_id INTEGER,
+            detector TEXT NOT NULL,
+            span_start INTEGER NOT NULL,
+            span_end INTEGER NOT NULL,
+            masked BOOLEAN NOT NULL,
+            source_app TEXT,
+            detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY(item_id) REFERENCES history(id) ON DELETE SET NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_secret_detections_item_id ON secret_detections(item_id);",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY_BROKEN_FK: &str = include_str!("../tests/fixtures/legacy_broken_fk.sql");
+    const LEGACY_CATEGORY: &str = include_str!("../tests/fixtures/legacy_category.sql");
+
+    fn fixture(sql: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(sql).unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn fresh_database_reaches_current_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run(&mut conn).unwrap(), 0);
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        // A second open is a no-op.
+        assert_eq!(run(&mut conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn editing_pin_or_description_stamps_updated_at() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO history (id, content_type, raw_content) VALUES (1, 'text', 'a');
+             UPDATE history SET raw_content = 'b' WHERE id = 1;",
+        )
+        .unwrap();
+        let stamped = "SELECT COUNT(*) FROM history WHERE updated_at IS NOT NULL";
+        assert_eq!(count(&conn, stamped), 0, "content edits aren't metadata");
+
+        conn.execute("UPDATE history SET is_permanent = 1 WHERE id = 1", [])
+            .unwrap();
+        assert_eq!(count(&conn, stamped), 1);
+
+        // An explicit timestamp (restore) is kept as given.
+        conn.execute(
+            "UPDATE history SET description = 'x', updated_at = '2020-01-01 00:00:00' WHERE id = 1",
+            [],
+        )
+        .unwrap();
+        let kept: String = conn
+            .query_row("SELECT updated_at FROM history WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(kept, "2020-01-01 00:00:00");
+    }
+
+    #[test]
+    fn secret_detections_outlive_their_items() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             INSERT INTO history (id, content_type, raw_content) VALUES (1, 'text', 'enc:v1:x');
+             INSERT INTO secret_detections (item_id, detector, span_start, span_end, masked)
+                 VALUES (1, 'aws-access-key-id', 0, 20, 1);
+             DELETE FROM history WHERE id = 1;",
+        )
+        .unwrap();
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM secret_detections WHERE item_id IS NULL"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn broken_item_groups_fk_is_rebuilt_without_losing_memberships() {
+        let mut conn = fixture(LEGACY_BROKEN_FK);
+        run(&mut conn).unwrap();
+
+        let target: String = conn
+            .query_row(
+                "SELECT \"table\" FROM pragma_foreign_key_list('item_groups') WHERE \"from\" = 'item_id'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(target, "history");
+        // Both valid memberships survive; the one for a missing item is dropped.
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM item_groups"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM history"), 2);
+    }
+
+    #[test]
+    fn legacy_columns_and_categories_are_migrated() {
+        let mut conn = fixture(LEGACY_CATEGORY);
+        run(&mut conn).unwrap();
+
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM history WHERE selection = 'clipboard'"
+            ),
+            2
+        );
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM item_groups ig JOIN groups g ON g.id = ig.group_id
+                 WHERE g.name = 'Work'"
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn).unwrap();
+        conn.execute(
+            "UPDATE app_meta SET value = ?1 WHERE key = 'schema_version'",
+            params![(SCHEMA_VERSION + 1).to_string()],
+        )
+        .unwrap();
+
+        let err = run(&mut conn).unwrap_err();
+        assert!(err.to_string().contains("newer than this version"));
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION + 1);
+    }
+
+    #[test]
+    fn destructive_upgrade_backs_up_the_file_first() {
+        let dir = std::env::temp_dir().join(format!("ortu-migrate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("ortu.db");
+        let _ = std::fs::remove_file(&db_path);
+        let backup_path = dir.join("ortu.db.v0.bak");
+        let _ = std::fs::remove_file(&backup_path);
+
+        let mut conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(LEGACY_BROKEN_FK).unwrap();
+        run(&mut conn).unwrap();
+
+        let backup = Connection::open(&backup_path).unwrap();
+        assert_eq!(schema_version(&backup).unwrap(), 0);
+        assert_eq!(count(&backup, "SELECT COUNT(*) FROM item_groups"), 3);
+        drop(backup);
+        drop(conn);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,799 @@
+// Search query language for history.
+//
+//   kubectl "exact phrase" /[0-9a-f]{40}/ type:image pinned:yes sensitive:no
+//   before:2026-09-01 after:7d group:Work -group:Shell app:firefox
+//   confidence:<0.6
+//
+// Terms are ANDed and a leading `-` negates any of them. Bare words use the
+// FTS5 index (prefix match) when it is available; quoted phrases match the text
+// literally; `/regex/` (optionally `/regex/i`) runs through the REGEXP SQL
+// function. `parse` validates the whole query up front so the UI can point at
+// the offending term, and `to_sql` compiles it into WHERE conditions with
+// positional parameters, so user input never ends up in the SQL text.
+
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+
+/// A parse error, with the character range of the offending term so the UI can
+/// highlight it.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TermKind {
+    Word(String),
+    Phrase(String),
+    Regex(String),
+    ContentTypes(&'static [&'static str]),
+    Pinned(bool),
+    Sensitive(bool),
+    /// Bound as a UTC `YYYY-MM-DD HH:MM:SS` string, the format of `created_at`.
+    Before(String),
+    After(String),
+    Group(String),
+    /// Substring of the source application.
+    App(String),
+    /// An automatic group attached with a confidence below (or above) the
+    /// value, 0–1.
+    Confidence {
+        below: bool,
+        value: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    negated: bool,
+    kind: TermKind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    terms: Vec<Term>,
+}
+
+const TEXT_TYPES: &[&str] = &["text", "html", "rtf"];
+
+impl Query {
+    /// The query as plain words when it is nothing but free text; such
+    /// searches are fuzzy-ranked instead of listed by date.
+    pub fn free_text(&self) -> Option<String> {
+        let mut words = Vec::new();
+        for term in &self.terms {
+            match &term.kind {
+                TermKind::Word(w) if !term.negated => words.push(w.as_str()),
+                _ => return None,
+            }
+        }
+        if words.is_empty() {
+            None
+        } else {
+            Some(words.join(" "))
+        }
+    }
+
+    /// True when matching runs the REGEXP function over raw content, which
+    /// can't use an index and has to be time-boxed.
+    pub fn uses_regex(&self) -> bool {
+        self.terms
+            .iter()
+            .any(|term| matches!(term.kind, TermKind::Regex(_)))
+    }
+
+    /// Compiles the query into ANDed WHERE conditions on `history h`, with
+    /// values for their `?` placeholders in order.
+    pub fn to_sql(&self, fts_enabled: bool) -> (Vec<String>, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        // `confidence:` only looks at the groups the query asks for, if any,
+        // so `group:X confidence:<0.6` reviews that one group.
+        let groups: Vec<&str> = self
+            .terms
+            .iter()
+            .filter_map(|term| match &term.kind {
+                TermKind::Group(name) if !term.negated => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        for term in &self.terms {
+            let condition = term_sql(&term.kind, fts_enabled, &groups, &mut values);
+            // Nullable columns make a condition NULL rather than false; treat
+            // that as "no match" so negating it keeps the row.
+            conditions.push(if term.negated {
+                format!("NOT COALESCE(({}), 0)", condition)
+            } else {
+                condition
+            });
+        }
+        (conditions, values)
+    }
+}
+
+/// Matches an item whose group name satisfies `op` (e.g. `= ?`, `LIKE ?`).
+fn in_group_sql(op: &str) -> String {
+    format!(
+        "EXISTS (
+            SELECT 1
+            FROM item_groups ig
+            JOIN groups g ON ig.group_id = g.id
+            WHERE ig.item_id = h.id AND g.name {op}
+         )"
+    )
+}
+
+fn term_sql(
+    kind: &TermKind,
+    fts_enabled: bool,
+    groups: &[&str],
+    values: &mut Vec<Value>,
+) -> String {
+    let text = |s: &str| Value::Text(s.to_string());
+    match kind {
+        TermKind::Word(word) => {
+            let pattern = format!("%{}%", escape_like(word));
+            // Content/description through FTS5 when possible; category and
+            // group names always by LIKE so `work` still finds the Work group.
+            match build_fts_query(word).filter(|_| fts_enabled) {
+                Some(fts) => {
+                    values.extend([Value::Text(fts), text(&pattern), text(&pattern)]);
+                    format!(
+                        "(h.id IN (SELECT rowid FROM history_fts WHERE history_fts MATCH ?)
+                          OR h.category LIKE ? ESCAPE '\\'
+                          OR {})",
+                        in_group_sql("LIKE ? ESCAPE '\\'")
+                    )
+                }
+                None => {
+                    for _ in 0..4 {
+                        values.push(text(&pattern));
+                    }
+                    format!(
+                        "(h.raw_content LIKE ? ESCAPE '\\'
+                          OR h.description LIKE ? ESCAPE '\\'
+                          OR h.category LIKE ? ESCAPE '\\'
+                          OR {})",
+                        in_group_sql("LIKE ? ESCAPE '\\'")
+                    )
+                }
+            }
+        }
+        TermKind::Phrase(phrase) => {
+            let pattern = format!("%{}%", escape_like(phrase));
+            values.extend([text(&pattern), text(&pattern)]);
+            "(h.raw_content LIKE ? ESCAPE '\\' OR COALESCE(h.description, '') LIKE ? ESCAPE '\\')"
+                .to_string()
+        }
+        TermKind::Regex(pattern) => {
+            // Sensitive rows hold ciphertext; matching it would be meaningless.
+            values.push(text(pattern));
+            "(COALESCE(h.is_sensitive, 0) = 0 AND h.raw_content REGEXP ?)".to_string()
+        }
+        TermKind::ContentTypes(types) => {
+            values.extend(types.iter().map(|t| text(t)));
+            let placeholders = vec!["?"; types.len()].join(", ");
+            format!("h.content_type IN ({placeholders})")
+        }
+        TermKind::Pinned(yes) => {
+            values.push(Value::Integer(*yes as i64));
+            "COALESCE(h.is_permanent, 0) = ?".to_string()
+        }
+        TermKind::Sensitive(yes) => {
+            values.push(Value::Integer(*yes as i64));
+            "COALESCE(h.is_sensitive, 0) = ?".to_string()
+        }
+        TermKind::Before(at) => {
+            values.push(text(at));
+            "h.created_at < ?".to_string()
+        }
+        TermKind::After(at) => {
+            values.push(text(at));
+            "h.created_at >= ?".to_string()
+        }
+        TermKind::App(app) => {
+            values.push(text(&format!("%{}%", escape_like(app))));
+            "h.source_app LIKE ? ESCAPE '\\'".to_string()
+        }
+        TermKind::Group(name) => {
+            // The sidebar's built-in filters predate real groups and keep their
+            // content-based meaning.
+            let lower = name.to_ascii_lowercase();
+            match lower.as_str() {
+                "text" => term_sql(
+                    &TermKind::ContentTypes(TEXT_TYPES),
+                    fts_enabled,
+                    groups,
+                    values,
+                ),
+                "images" => term_sql(
+                    &TermKind::ContentTypes(&["image"]),
+                    fts_enabled,
+                    groups,
+                    values,
+                ),
+                "files" => term_sql(
+                    &TermKind::ContentTypes(&["files"]),
+                    fts_enabled,
+                    groups,
+                    values,
+                ),
+                "url" | "urls" => format!(
+                    "({}
+                      OR h.raw_content LIKE 'http://%'
+                      OR h.raw_content LIKE 'https://%'
+                      OR h.raw_content LIKE 'ftp://%')",
+                    in_group_sql("= 'URL'")
+                ),
+                _ => {
+                    values.push(text(name));
+                    in_group_sql("= ? COLLATE NOCASE")
+                }
+            }
+        }
+        TermKind::Confidence { below, value } => {
+            values.push(Value::Real(*value));
+            let mut sql = format!(
+                "EXISTS (
+                    SELECT 1
+                    FROM item_group_confidence c
+                    JOIN item_groups ig ON ig.item_id = c.item_id AND ig.group_id = c.group_id
+                    JOIN groups g ON g.id = c.group_id
+                    WHERE c.item_id = h.id AND c.confidence {} ?",
+                if *below { "<" } else { ">" }
+            );
+            if !groups.is_empty() {
+                values.extend(groups.iter().map(|name| text(name)));
+                sql.push_str(&format!(
+                    " AND g.name COLLATE NOCASE IN ({})",
+                    vec!["?"; groups.len()].join(", ")
+                ));
+            }
+            sql.push_str("\n )");
+            sql
+        }
+    }
+}
+
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Builds an FTS5 MATCH query from free user text. Each alphanumeric token
+/// becomes a quoted prefix term so partial words match. Returns None when there
+/// are no usable tokens (caller falls back to LIKE).
+fn build_fts_query(input: &str) -> Option<String> {
+    let tokens: Vec<String> = input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("\"{}\"*", t.replace('"', "\"\"")))
+        .collect();
+    if tokens.is_empty() {
+        None
+    } else {
+        Some(tokens.join(" "))
+    }
+}
+
+// ── Parsing ────────────────────────────────────────────────────────────────
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn at_term_end(&self) -> bool {
+        self.peek().is_none_or(char::is_whitespace)
+    }
+
+    /// Reads a `"…"` string starting at the opening quote; `\"` and `\\`
+    /// escape a quote and a backslash.
+    fn quoted(&mut self, term_start: usize) -> Result<String, QueryError> {
+        self.pos += 1;
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' if matches!(self.peek(), Some('"' | '\\')) => {
+                    out.extend(self.peek());
+                    self.pos += 1;
+                }
+                _ => out.push(c),
+            }
+        }
+        Err(QueryError {
+            message: "Unclosed quote".to_string(),
+            start: term_start,
+            end: self.pos,
+        })
+    }
+
+    /// Reads a `/…/` pattern starting at the opening slash, plus an optional
+    /// `i` flag; `\/` escapes a slash.
+    fn regex(&mut self, term_start: usize) -> Result<String, QueryError> {
+        self.pos += 1;
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '/' => {
+                    if self.peek() == Some('i') {
+                        self.pos += 1;
+                        out.insert_str(0, "(?i)");
+                    }
+                    return Ok(out);
+                }
+                '\\' if self.peek() == Some('/') => {
+                    out.push('/');
+                    self.pos += 1;
+                }
+                _ => out.push(c),
+            }
+        }
+        Err(QueryError {
+            message: "Unclosed regex: end it with /".to_string(),
+            start: term_start,
+            end: self.pos,
+        })
+    }
+
+    /// Reads a bare word, or `key:value` where the value may be quoted.
+    /// Returns (text before the colon, value) for filters.
+    fn word(&mut self, term_start: usize) -> Result<(String, Option<String>), QueryError> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            if c == ':' && is_filter_key(&word) {
+                self.pos += 1;
+                let value = if self.peek() == Some('"') {
+                    self.quoted(term_start)?
+                } else {
+                    let start = self.pos;
+                    while !self.at_term_end() {
+                        self.pos += 1;
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                };
+                return Ok((word, Some(value)));
+            }
+            word.push(c);
+            self.pos += 1;
+        }
+        Ok((word, None))
+    }
+}
+
+const FILTER_KEYS: &[&str] = &[
+    "type",
+    "pinned",
+    "sensitive",
+    "before",
+    "after",
+    "group",
+    "category",
+    "app",
+    "confidence",
+];
+
+/// Treats `word:` as a filter only for known keys, so URLs (`https://…`),
+/// times (`12:30`) and prose (`Note:`) still search as text.
+fn is_filter_key(word: &str) -> bool {
+    FILTER_KEYS.iter().any(|key| key.eq_ignore_ascii_case(word))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" | "true" | "1" => Some(true),
+        "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// `<0.6`, `>0.9`, `<60%`.
+fn parse_confidence(value: &str) -> Option<TermKind> {
+    let (below, number) = if let Some(rest) = value.strip_prefix('<') {
+        (true, rest)
+    } else {
+        (false, value.strip_prefix('>')?)
+    };
+    let value = match number.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().ok()? / 100.0,
+        None => number.parse::<f64>().ok()?,
+    };
+    (0.0..=1.0)
+        .contains(&value)
+        .then_some(TermKind::Confidence { below, value })
+}
+
+fn to_db_time(at: chrono::DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn local_midnight(date: NaiveDate) -> Option<chrono::DateTime<Utc>> {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Parses `before:`/`after:` values: a local date (`2026-09-01`), a local date
+/// and time (`2026-09-01T14:30`), `today`, `yesterday`, or an age such as
+/// `30m`, `12h`, `7d`, `2w`.
+fn parse_time(value: &str) -> Option<String> {
+    let lower = value.to_ascii_lowercase();
+    let today = Local::now().date_naive();
+    match lower.as_str() {
+        "today" => return local_midnight(today).map(to_db_time),
+        "yesterday" => return local_midnight(today.pred_opt()?).map(to_db_time),
+        _ => {}
+    }
+    if let Some(unit) = lower.chars().last().filter(|c| "mhdw".contains(*c)) {
+        if let Ok(n) = lower[..lower.len() - 1].parse::<i64>() {
+            let age = match unit {
+                'm' => Duration::try_minutes(n)?,
+                'h' => Duration::try_hours(n)?,
+                'd' => Duration::try_days(n)?,
+                _ => Duration::try_weeks(n)?,
+            };
+            return Utc::now().checked_sub_signed(age).map(to_db_time);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return local_midnight(date).map(to_db_time);
+    }
+    let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M").ok()?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|t| to_db_time(t.with_timezone(&Utc)))
+}
+
+fn filter_term(key: &str, value: &str) -> Result<TermKind, String> {
+    let key = key.to_ascii_lowercase();
+    if value.is_empty() {
+        return Err(format!("Missing value after '{}:'", key));
+    }
+    match key.as_str() {
+        "type" => {
+            let types: &'static [&'static str] = match value.to_ascii_lowercase().as_str() {
+                "text" => TEXT_TYPES,
+                "rich" => &["html", "rtf"],
+                "html" => &["html"],
+                "rtf" => &["rtf"],
+                "image" | "images" => &["image"],
+                "file" | "files" => &["files"],
+                _ => {
+                    return Err(format!(
+                        "Unknown type '{}': use text, rich, html, rtf, image or file",
+                        value
+                    ))
+                }
+            };
+            Ok(TermKind::ContentTypes(types))
+        }
+        "pinned" | "sensitive" => {
+            let yes = parse_bool(value)
+                .ok_or_else(|| format!("'{}:' takes yes or no, not '{}'", key, value))?;
+            Ok(if key == "pinned" {
+                TermKind::Pinned(yes)
+            } else {
+                TermKind::Sensitive(yes)
+            })
+        }
+        "before" | "after" => {
+            let at = parse_time(value).ok_or_else(|| {
+                format!(
+                    "Can't read '{}' as a time: use a date like 2026-09-01, today, or an age like 7d",
+                    value
+                )
+            })?;
+            Ok(if key == "before" {
+                TermKind::Before(at)
+            } else {
+                TermKind::After(at)
+            })
+        }
+        "group" | "category" => Ok(TermKind::Group(value.to_string())),
+        "app" => Ok(TermKind::App(value.to_string())),
+        "confidence" => parse_confidence(value).ok_or_else(|| {
+            format!(
+                "Can't read '{}' as a confidence: use <0.6 or >0.9 (or a percentage like <60%)",
+                value
+            )
+        }),
+        _ => Err(format!("Unknown filter '{}:'", key)),
+    }
+}
+
+/// Parses a search string. An empty or all-whitespace string yields an empty
+/// query that matches everything.
+pub fn parse(input: &str) -> Result<Query, QueryError> {
+    let mut lexer = Lexer {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    let mut terms = Vec::new();
+
+    loop {
+        while lexer.peek().is_some_and(char::is_whitespace) {
+            lexer.pos += 1;
+        }
+        let start = lexer.pos;
+        let Some(first) = lexer.peek() else { break };
+
+        // A lone "-" (or "-" before a space) is just text.
+        let negated = first == '-'
+            && lexer
+                .chars
+                .get(start + 1)
+                .is_some_and(|c| !c.is_whitespace());
+        if negated {
+            lexer.pos += 1;
+        }
+
+        let kind = match lexer.peek() {
+            Some('"') => {
+                let phrase = lexer.quoted(start)?;
+                if phrase.is_empty() {
+                    None
+                } else {
+                    Some(TermKind::Phrase(phrase))
+                }
+            }
+            Some('/')
+                if lexer
+                    .chars
+                    .get(lexer.pos + 1)
+                    .is_some_and(|c| !c.is_whitespace()) =>
+            {
+                let pattern = lexer.regex(start)?;
+                check_regex(&pattern, start, lexer.pos)?;
+                Some(TermKind::Regex(pattern))
+            }
+            _ => {
+                let (word, value) = lexer.word(start)?;
+                match value {
+                    Some(value) => {
+                        let kind = filter_term(&word, &value).map_err(|message| QueryError {
+                            message,
+                            start,
+                            end: lexer.pos,
+                        })?;
+                        Some(kind)
+                    }
+                    None => Some(TermKind::Word(word)),
+                }
+            }
+        };
+        if !lexer.at_term_end() {
+            return Err(QueryError {
+                message: "Expected a space after this term".to_string(),
+                start,
+                end: lexer.pos,
+            });
+        }
+        if let Some(kind) = kind {
+            terms.push(Term { negated, kind });
+        }
+    }
+
+    Ok(Query { terms })
+}
+
+/// Parses the whole input as one regular expression, as typed in the search
+/// box's regex mode (no slashes needed; equivalent to a single `/…/` term).
+pub fn parse_regex(pattern: &str) -> Result<Query, QueryError> {
+    if pattern.is_empty() {
+        return Ok(Query::default());
+    }
+    check_regex(pattern, 0, pattern.chars().count())?;
+    Ok(Query {
+        terms: vec![Term {
+            negated: false,
+            kind: TermKind::Regex(pattern.to_string()),
+        }],
+    })
+}
+
+/// Upper bound on a compiled pattern, so something like `\w{1000}{1000}` is
+/// rejected up front instead of allocating gigabytes per row.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+fn compile_regex(pattern: &str) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+fn check_regex(pattern: &str, start: usize, end: usize) -> Result<(), QueryError> {
+    match compile_regex(pattern) {
+        Ok(_) => Ok(()),
+        Err(regex::Error::CompiledTooBig(_)) => Err(QueryError {
+            message: "Regex is too complex".to_string(),
+            start,
+            end,
+        }),
+        Err(e) => {
+            // The regex crate's message spans several lines; its last line
+            // says what is wrong.
+            let detail = e.to_string();
+            let detail = detail.lines().last().unwrap_or("").trim().to_string();
+            Err(QueryError {
+                message: format!("Invalid regex: {}", detail),
+                start,
+                end,
+            })
+        }
+    }
+}
+
+/// Registers `REGEXP` (used by `/regex/` terms) on a connection. Compiled
+/// patterns are cached per statement.
+pub fn register_sql_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let re = ctx.get_or_create_aux(
+                0,
+                |pattern| -> Result<regex::Regex, Box<dyn std::error::Error + Send + Sync>> {
+                    Ok(compile_regex(pattern.as_str()?)?)
+                },
+            )?;
+            let text = match ctx.get_raw(1).as_str_or_null()? {
+                Some(text) => text,
+                None => return Ok(false),
+            };
+            Ok(re.is_match(text))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<(bool, TermKind)> {
+        parse(input)
+            .unwrap()
+            .terms
+            .into_iter()
+            .map(|t| (t.negated, t.kind))
+            .collect()
+    }
+
+    #[test]
+    fn parses_filters_phrases_and_negation() {
+        assert_eq!(
+            kinds(r#"kubectl "get pods" -group:Shell type:image pinned:yes /[0-9a-f]{40}/i"#),
+            vec![
+                (false, TermKind::Word("kubectl".into())),
+                (false, TermKind::Phrase("get pods".into())),
+                (true, TermKind::Group("Shell".into())),
+                (false, TermKind::ContentTypes(&["image"])),
+                (false, TermKind::Pinned(true)),
+                (false, TermKind::Regex("(?i)[0-9a-f]{40}".into())),
+            ]
+        );
+        assert_eq!(
+            kinds(r#"group:"My Notes" https://example.com 12:30 Note:"#),
+            vec![
+                (false, TermKind::Group("My Notes".into())),
+                (false, TermKind::Word("https://example.com".into())),
+                (false, TermKind::Word("12:30".into())),
+                (false, TermKind::Word("Note:".into())),
+            ]
+        );
+        assert_eq!(
+            kinds(r#"category:"say \"hi\" C:\\""#),
+            vec![(false, TermKind::Group(r#"say "hi" C:\"#.into()))]
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_term() {
+        assert!(parse("app:").is_err());
+        assert!(parse("confidence:0.6").is_err());
+        assert!(parse("confidence:<1.5").is_err());
+        let err = parse("foo type:imgae").unwrap_err();
+        assert_eq!((err.start, err.end), (4, 14));
+        assert!(err.message.contains("imgae"));
+
+        let err = parse(r#"ok "unclosed"#).unwrap_err();
+        assert_eq!((err.start, err.message.as_str()), (3, "Unclosed quote"));
+
+        let err = parse("/a(b/").unwrap_err();
+        assert!(err.message.starts_with("Invalid regex"));
+        assert_eq!(parse_regex("a/b"), parse(r"/a\/b/"));
+        let err = parse_regex(r"\w{1000}{1000}").unwrap_err();
+        assert_eq!(err.message, "Regex is too complex");
+
+        assert!(parse("before:someday").is_err());
+        assert!(parse("sensitive:maybe").is_err());
+    }
+
+    #[test]
+    fn only_plain_words_are_fuzzy_ranked() {
+        assert_eq!(
+            parse("git log").unwrap().free_text().as_deref(),
+            Some("git log")
+        );
+        assert_eq!(parse("git type:text").unwrap().free_text(), None);
+        assert_eq!(parse("  ").unwrap().free_text(), None);
+    }
+
+    #[test]
+    fn compiled_query_runs_against_history() {
+        let conn = Connection::open_in_memory().unwrap();
+        register_sql_functions(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE groups (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE item_groups (item_id INTEGER, group_id INTEGER);
+             CREATE TABLE item_group_confidence (item_id INTEGER, group_id INTEGER, confidence REAL);
+             INSERT INTO groups VALUES (1, 'Version Control'), (2, 'Text'), (3, 'Work');
+             INSERT INTO item_groups VALUES (1, 1), (1, 2), (2, 2), (2, 3), (3, 1);
+             INSERT INTO item_group_confidence VALUES (1, 1, 0.8), (1, 2, 0.4), (2, 2, 0.4), (3, 1, 0.95);
+             CREATE TABLE history (
+                 id INTEGER PRIMARY KEY, content_type TEXT, raw_content TEXT, category TEXT,
+                 description TEXT, is_permanent BOOLEAN, is_sensitive BOOLEAN, created_at TEXT,
+                 source_app TEXT
+             );
+             INSERT INTO history VALUES
+                 (1, 'text', 'deadbeefdeadbeefdeadbeefdeadbeefdeadbeef', NULL, NULL, 0, 0, '2026-01-01 00:00:00', 'org.mozilla.firefox'),
+                 (2, 'text', '100% done', NULL, NULL, 1, 0, '2026-06-01 00:00:00', 'kitty'),
+                 (3, 'text', 'enc:v1:deadbeefdeadbeefdeadbeefdeadbeefdeadbeef', NULL, NULL, 0, 1, '2026-06-01 00:00:00', NULL);",
+        )
+        .unwrap();
+
+        let ids = |input: &str| -> Vec<i64> {
+            let (conditions, values) = parse(input).unwrap().to_sql(false);
+            let sql = format!(
+                "SELECT h.id FROM history h WHERE {} ORDER BY h.id",
+                if conditions.is_empty() {
+                    "1".to_string()
+                } else {
+                    conditions.join(" AND ")
+                }
+            );
+            let mut stmt = conn.prepare(&sql).unwrap();
+            stmt.query_map(rusqlite::params_from_iter(values), |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+
+        assert_eq!(ids("/^[0-9a-f]{40}$/"), vec![1]);
+        assert_eq!(ids(r#""100%""#), vec![2]);
+        assert_eq!(ids("-pinned:yes sensitive:no"), vec![1]);
+        assert_eq!(ids("before:2026-03-01"), vec![1]);
+        assert_eq!(ids("-done"), vec![1, 3]);
+        assert_eq!(ids("app:Firefox"), vec![1]);
+        assert_eq!(ids("-app:firefox"), vec![2, 3]);
+        assert_eq!(ids("confidence:<0.5"), vec![1, 2]);
+        assert_eq!(ids("confidence:<85%"), vec![1, 2]);
+        assert_eq!(ids(r#"group:"version control" confidence:<0.85"#), vec![1]);
+        assert_eq!(ids("confidence:>0.9"), vec![3]);
+        assert_eq!(ids("-confidence:<0.5"), vec![3]);
+    }
+}
@@ -0,0 +1,183 @@
+// Classifier rules files.
+//
+// The classifier (classifier.rs) runs the built-in rules unless the user
+// has a `rules.toml` in the app data directory, which can add rules, replace
+// built-in ones (a rule with the same name), turn them off
+// (`disable = ["name", …]`) or, with `replace_defaults = true`, stand in for
+// the whole built-in set. The file is checked every couple of seconds and
+// reloaded when it changes; if it doesn't parse or validate, the previous
+// rules stay active and the error is reported (status and a
+// "classifier-rules-updated" event).
+
+use crate::classifier::{Ruleset, DEFAULT_RULES};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// User rules file, in the app data directory.
+const RULES_FILE: &str = "rules.toml";
+
+/// Copy of the built-in rules written next to it, for reference.
+const DEFAULTS_FILE: &str = "default-rules.toml";
+
+/// How often the rules file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What a new rules file starts with.
+const STARTER: &str = r#"# Ortu classifier rules. Changes apply a couple of seconds after saving.
+#
+# The built-in rules are in default-rules.toml next to this file. A rule here
+# with the same name as a built-in one replaces it.
+#
+# replace_defaults = true       # use only the rules in this file
+# disable = ["phone-number"]    # turn off built-in rules by name
+#
+# [[rule]]
+# name = "ticket-id"
+# group = "Tickets"
+# confidence = 0.95
+# regex = '^[A-Z][A-Z0-9]{1,9}-\d+$'
+#
+# [[rule]]
+# name = "acme-cli"
+# group = "Acme"
+# confidence = 0.9
+# also = { DevOps = 0.8 }
+# prefix = ["acme", "acmectl"]
+"#;
+
+static ACTIVE: RwLock<Option<Arc<Ruleset>>> = RwLock::new(None);
+static STATUS: Mutex<Option<RulesStatus>> = Mutex::new(None);
+
+/// The rules auto-grouping uses right now.
+pub fn active() -> Arc<Ruleset> {
+    ACTIVE
+        .read()
+        .ok()
+        .and_then(|active| active.clone())
+        .unwrap_or_else(Ruleset::defaults)
+}
+
+/// What the settings page shows about the rules.
+#[derive(Serialize, Clone, Default, Debug)]
+pub struct RulesStatus {
+    /// The user rules file, and whether it exists.
+    pub path: String,
+    pub exists: bool,
+    /// Rules in use, and how many of them come from the file.
+    pub rules: usize,
+    pub custom: usize,
+    /// Why the file was rejected; the previous rules are still in use.
+    pub error: Option<String>,
+    /// Unix seconds of the last successful load.
+    pub loaded_at: Option<i64>,
+}
+
+fn rules_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(RULES_FILE))
+        .map_err(|e| e.to_string())
+}
+
+/// Loads the rules file and keeps watching it for changes.
+pub fn start(app: &AppHandle) {
+    let path = match rules_path(app) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Rules: no app data directory: {}", e);
+            return;
+        }
+    };
+    let app = app.clone();
+    thread::spawn(move || {
+        let mut loaded = None;
+        loop {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            if loaded != Some(modified) {
+                let first = loaded.is_none();
+                loaded = Some(modified);
+                let status = reload(&path);
+                if let Some(error) = &status.error {
+                    eprintln!("Rules: {} rejected: {}", path.display(), error);
+                }
+                if !first {
+                    let _ = app.emit("classifier-rules-updated", status);
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+/// Rereads the rules file, switching to it only if it is valid.
+fn reload(path: &Path) -> RulesStatus {
+    let loaded = match std::fs::read_to_string(path) {
+        Ok(text) => Ruleset::with_user_rules(&text).map(Arc::new),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Ruleset::defaults()),
+        Err(e) => Err(e.to_string()),
+    };
+    let mut status = STATUS
+        .lock()
+        .ok()
+        .and_then(|s| s.clone())
+        .unwrap_or_default();
+    status.path = path.to_string_lossy().into_owned();
+    status.exists = path.exists();
+    match loaded {
+        Ok(ruleset) => {
+            if let Ok(mut active) = ACTIVE.write() {
+                *active = Some(ruleset);
+            }
+            status.error = None;
+            status.loaded_at = Some(chrono::Utc::now().timestamp());
+        }
+        Err(e) => status.error = Some(e),
+    }
+    let active = active();
+    status.rules = active.rule_count();
+    status.custom = active.custom();
+    if let Ok(mut current) = STATUS.lock() {
+        *current = Some(status.clone());
+    }
+    status
+}
+
+pub fn status(app: &AppHandle) -> RulesStatus {
+    match STATUS.lock().ok().and_then(|s| s.clone()) {
+        Some(status) => status,
+        None => {
+            let active = active();
+            RulesStatus {
+                path: rules_path(app)
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                rules: active.rule_count(),
+                custom: active.custom(),
+                ..Default::default()
+            }
+        }
+    }
+}
+
+/// The user rules file, created from a commented template if missing, with
+/// a fresh copy of the built-in rules beside it for reference. With
+/// `builtin`, that copy instead.
+pub fn file_to_edit(app: &AppHandle, builtin: bool) -> Result<PathBuf, String> {
+    let path = rules_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let defaults = path.with_file_name(DEFAULTS_FILE);
+    std::fs::write(&defaults, DEFAULT_RULES).map_err(|e| e.to_string())?;
+    if builtin {
+        return Ok(defaults);
+    }
+    if !path.exists() {
+        std::fs::write(&path, STARTER).map_err(|e| e.to_string())?;
+    }
+    Ok(path)
+}
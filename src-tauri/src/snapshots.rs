@@ -0,0 +1,272 @@
+// Scheduled local snapshots of ortu.db.
+//
+// With `auto_backup_interval` set to "daily" or "weekly", a thread copies the
+// database into `auto_backup_dir` (by default a `backups` folder next to
+// ortu.db) once per interval and deletes all but the newest
+// `auto_backup_keep` snapshots. The copy is made with SQLite's online backup
+// API, so it is consistent even while the listener is writing. Snapshots are
+// plain database files: sensitive items stay encrypted under this machine's
+// key, exactly as in ortu.db. The outcome of the last run is kept in app_meta
+// for the settings page.
+
+use crate::db::ClipboardDB;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Longest sleep between checks, so a suspended machine catches up within an
+/// hour of waking; `reschedule` wakes the thread sooner.
+const MAX_WAIT: Duration = Duration::from_secs(3600);
+
+/// Wait after a failed snapshot before trying again.
+const RETRY_SECS: i64 = 3600;
+
+const DEFAULT_KEEP: usize = 7;
+
+/// Snapshot file names: `ortu-snapshot-YYYYMMDD-HHMMSS.db`, so sorting by
+/// name sorts by age. Rotation only ever touches files named like this.
+const PREFIX: &str = "ortu-snapshot-";
+const SUFFIX: &str = ".db";
+
+const STATUS_KEY: &str = "auto_backup_status";
+
+/// What the settings page shows about scheduled backups.
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone, Debug)]
+pub struct SnapshotStatus {
+    /// Unix seconds of the last attempt and of the last successful snapshot.
+    pub last_run: Option<i64>,
+    pub last_success: Option<i64>,
+    /// The newest snapshot written.
+    pub last_file: Option<String>,
+    /// Why the last attempt failed; None when it succeeded.
+    pub error: Option<String>,
+    /// Unix seconds of the next scheduled run; None when turned off.
+    #[serde(skip_deserializing)]
+    pub next_run: Option<i64>,
+}
+
+/// Wake-up signal for the snapshot thread (true = settings changed).
+#[derive(Default)]
+pub struct SnapshotSchedule(Arc<(Mutex<bool>, Condvar)>);
+
+/// Held while a snapshot is being written, so a manual run and the schedule
+/// never write at the same time.
+static RUNNING: Mutex<()> = Mutex::new(());
+
+/// Starts the snapshot thread and registers its handle as managed state.
+pub fn start(app: &AppHandle) {
+    let schedule = SnapshotSchedule::default();
+    let signal = schedule.0.clone();
+    app.manage(schedule);
+
+    let app = app.clone();
+    thread::spawn(move || {
+        let (dirty, wake) = &*signal;
+        loop {
+            let wait = app
+                .try_state::<ClipboardDB>()
+                .map(|db| run_due(&app, db.inner()))
+                .unwrap_or(MAX_WAIT);
+            let Ok(mut pending) = dirty.lock() else {
+                return;
+            };
+            if !*pending {
+                pending = match wake.wait_timeout(pending, wait) {
+                    Ok((guard, _)) => guard,
+                    Err(_) => return,
+                };
+            }
+            *pending = false;
+        }
+    });
+}
+
+/// Wakes the snapshot thread after an `auto_backup_*` setting changed.
+pub fn reschedule(app: &AppHandle) {
+    if let Some(schedule) = app.try_state::<SnapshotSchedule>() {
+        let (dirty, wake) = &*schedule.0;
+        if let Ok(mut pending) = dirty.lock() {
+            *pending = true;
+            wake.notify_one();
+        }
+    }
+}
+
+/// Takes a snapshot if one is due and returns how long to sleep until the
+/// next one.
+fn run_due(app: &AppHandle, db: &ClipboardDB) -> Duration {
+    let Some(due) = status(db).next_run else {
+        return MAX_WAIT;
+    };
+    let now = chrono::Utc::now().timestamp();
+    if now < due {
+        return Duration::from_secs((due - now) as u64).min(MAX_WAIT);
+    }
+    if let Err(e) = run(app, db) {
+        eprintln!("Snapshots: scheduled backup failed: {}", e);
+    }
+    match status(db).next_run {
+        Some(due) => Duration::from_secs((due - now).max(1) as u64).min(MAX_WAIT),
+        None => MAX_WAIT,
+    }
+}
+
+fn interval_secs(db: &ClipboardDB) -> Option<i64> {
+    match db
+        .get_setting("auto_backup_interval")
+        .ok()
+        .flatten()
+        .as_deref()
+    {
+        Some("daily") => Some(24 * 3600),
+        Some("weekly") => Some(7 * 24 * 3600),
+        _ => None,
+    }
+}
+
+/// The last run's outcome, with when the next scheduled run is due.
+pub fn status(db: &ClipboardDB) -> SnapshotStatus {
+    let mut status: SnapshotStatus = db
+        .get_setting(STATUS_KEY)
+        .ok()
+        .flatten()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    status.next_run = interval_secs(db).map(|interval| {
+        let now = chrono::Utc::now().timestamp();
+        let mut due = status.last_success.map_or(now, |t| t + interval);
+        if let (Some(_), Some(last_run)) = (&status.error, status.last_run) {
+            due = due.max(last_run + RETRY_SECS);
+        }
+        due
+    });
+    status
+}
+
+/// Writes a snapshot now, rotates old ones and records the outcome. Returns
+/// the new file.
+pub fn run(app: &AppHandle, db: &ClipboardDB) -> Result<PathBuf, String> {
+    let _running = RUNNING.lock().map_err(|_| "lock".to_string())?;
+    let result = snapshot_dir(app, db).and_then(|dir| {
+        let path = write_snapshot(db, &dir)?;
+        rotate(&dir, keep(db)).map_err(|e| e.to_string())?;
+        Ok(path)
+    });
+
+    let mut status = status(db);
+    let now = chrono::Utc::now().timestamp();
+    status.last_run = Some(now);
+    match &result {
+        Ok(path) => {
+            status.last_success = Some(now);
+            status.last_file = Some(path.to_string_lossy().into_owned());
+            status.error = None;
+        }
+        Err(e) => status.error = Some(e.clone()),
+    }
+    status.next_run = None;
+    if let Ok(json) = serde_json::to_string(&status) {
+        let _ = db.set_setting(STATUS_KEY, &json);
+    }
+    result
+}
+
+fn snapshot_dir(app: &AppHandle, db: &ClipboardDB) -> Result<PathBuf, String> {
+    match db.get_setting("auto_backup_dir").ok().flatten() {
+        Some(dir) if !dir.trim().is_empty() => Ok(PathBuf::from(dir.trim())),
+        _ => app
+            .path()
+            .app_data_dir()
+            .map(|dir| dir.join("backups"))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn keep(db: &ClipboardDB) -> usize {
+    db.get_setting("auto_backup_keep")
+        .ok()
+        .flatten()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_KEEP)
+}
+
+/// Copies the database to a `.part` file in `dir`, renamed into place once
+/// complete so a half-written snapshot never counts towards rotation.
+fn write_snapshot(db: &ClipboardDB, dir: &Path) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let name = format!(
+        "{PREFIX}{}{SUFFIX}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let path = dir.join(&name);
+    let part = dir.join(format!("{name}.part"));
+    let _ = std::fs::remove_file(&part);
+    let written = db
+        .snapshot_to(&part)
+        .map_err(|e| e.to_string())
+        .and_then(|_| std::fs::rename(&part, &path).map_err(|e| e.to_string()));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&part);
+    }
+    written.map(|_| path)
+}
+
+/// Deletes all but the newest `keep` snapshots in `dir`.
+fn rotate(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut snapshots: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with(PREFIX) && name.ends_with(SUFFIX)
+        })
+        .map(|entry| entry.path())
+        .collect();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(keep);
+    for old in &snapshots[..excess] {
+        std::fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_keeps_the_newest_snapshots_only() {
+        let dir = std::env::temp_dir().join(format!("ortu-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let names = [
+            "ortu-snapshot-20260101-000000.db",
+            "ortu-snapshot-20260102-000000.db",
+            "ortu-snapshot-20260103-000000.db",
+            "ortu-snapshot-20260104-000000.db.part",
+            "notes.db",
+        ];
+        for name in names {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        rotate(&dir, 2).unwrap();
+        let mut left: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                "notes.db",
+                "ortu-snapshot-20260102-000000.db",
+                "ortu-snapshot-20260103-000000.db",
+                "ortu-snapshot-20260104-000000.db.part",
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
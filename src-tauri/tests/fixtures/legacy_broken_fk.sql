@@ -0,0 +1,38 @@
+-- ortu.db as written by an early build: no schema_version, no description /
+-- is_manual / is_sensitive columns, and item_groups.item_id pointing at a
+-- `clipboard_items` table that doesn't exist.
+PRAGMA foreign_keys = OFF;
+
+CREATE TABLE groups (
+    id INTEGER PRIMARY KEY,
+    name TEXT UNIQUE NOT NULL,
+    is_system BOOLEAN DEFAULT 0
+);
+
+CREATE TABLE history (
+    id INTEGER PRIMARY KEY,
+    content_type TEXT NOT NULL,
+    raw_content TEXT NOT NULL,
+    category TEXT,
+    is_permanent BOOLEAN DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE item_groups (
+    item_id INTEGER NOT NULL,
+    group_id INTEGER NOT NULL,
+    PRIMARY KEY (item_id, group_id),
+    FOREIGN KEY(item_id) REFERENCES clipboard_items(id) ON DELETE CASCADE,
+    FOREIGN KEY(group_id) REFERENCES groups(id) ON DELETE CASCADE
+);
+
+CREATE TABLE app_meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
+INSERT INTO groups (id, name, is_system) VALUES (1, 'Work', 0), (2, 'URL', 1);
+INSERT INTO history (id, content_type, raw_content) VALUES
+    (1, 'text', 'kubectl get pods'),
+    (2, 'text', 'https://example.com');
+INSERT INTO item_groups (item_id, group_id) VALUES (1, 1), (2, 2), (99, 1);
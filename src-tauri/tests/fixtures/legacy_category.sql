@@ -0,0 +1,14 @@
+-- ortu.db from before groups were many-to-many: items carry a single
+-- `category`, there is no item_groups table and no schema_version.
+CREATE TABLE history (
+    id INTEGER PRIMARY KEY,
+    content_type TEXT NOT NULL,
+    raw_content TEXT NOT NULL,
+    category TEXT,
+    is_permanent BOOLEAN DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO history (id, content_type, raw_content, category) VALUES
+    (1, 'text', 'meeting notes', 'Work'),
+    (2, 'text', 'plain text', NULL);
@@ -1,10 +1,15 @@
 export function buildSearchQuery(
   selectedGroup: string | null,
-  searchQuery: string
+  searchQuery: string,
+  regex = false
 ): string {
-  const q = searchQuery.trim();
+  // Regex mode searches the raw text as one `/pattern/` term.
+  const q = regex
+    ? searchQuery && `/${searchQuery.replace(/\//g, "\\/")}/`
+    : searchQuery.trim();
   if (!selectedGroup) return q;
-  return `category:${selectedGroup} ${q}`.trim();
+  const group = selectedGroup.replace(/\\/g, "\\\\").replace(/"/g, '\\"');
+  return `category:"${group}" ${q}`.trim();
 }
 
 export function clipPreview(raw: string, contentType: string): string {
@@ -25,3 +30,10 @@ export function clipPreview(raw: string, contentType: string): string {
   if (contentType === "rtf") return "[RTF]";
   return raw;
 }
+
+/** Short display name for a source app: "com.apple.Terminal" → "Terminal", "firefox.exe" → "firefox". */
+export function appLabel(sourceApp: string): string {
+  if (/\.exe$/i.test(sourceApp)) return sourceApp.slice(0, -4);
+  const parts = sourceApp.split(".");
+  return parts.length > 2 ? parts[parts.length - 1] : sourceApp;
+}
@@ -1,14 +1,151 @@
 export interface ClipboardItem {
     id: number;
-    content_type: string;
-    raw_content: string;
+    content_type: string; // "text" | "html" | "rtf" | "image" | "files"
+    raw_content: string; // for masked items, the text with its secrets hidden, or ""
     category: string | null;
     groups: string[];
+    group_confidence?: Record<string, number>; // automatic groups only, 0–1
     is_permanent: boolean;
     created_at: string;
     description: string | null;
     is_manual: boolean;
     is_sensitive: boolean;
+    selection: "clipboard" | "primary";
+    source_app: string | null; // bundle id (macOS), WM_CLASS (Linux), executable (Windows)
+    updated_at?: string; // last pin/description edit
+}
+
+export interface HistoryCursor {
+    created_at: string;
+    id: number;
+}
+
+export interface HistoryPage {
+    items: ClipboardItem[];
+    next_cursor: HistoryCursor | null;
+}
+
+/** A search syntax error; `start`..`end` are character offsets into the query. */
+export interface SearchQueryError {
+    message: string;
+    start: number;
+    end: number;
+}
+
+/** Error returned by commands that need the sensitive-item key while the vault is locked. */
+export const VAULT_LOCKED = "Vault is locked";
+
+/** Error from `restore_data` for an encrypted backup opened without a passphrase. */
+export const BACKUP_PASSPHRASE_REQUIRED = "This backup is encrypted; enter its passphrase";
+
+export interface VaultStatus {
+    enabled: boolean;
+    unlocked: boolean;
+}
+
+/** Result of `auto_backup_status`; times are Unix seconds. */
+export interface SnapshotStatus {
+    last_run: number | null;
+    last_success: number | null;
+    last_file: string | null;
+    error: string | null;
+    next_run: number | null;
+}
+
+/** One group `classify_preview` gives an item's text, and why. */
+export interface GroupScore {
+    group: string;
+    confidence: number; // 0–1
+    rule: string;
+    reason: string;
+}
+
+/** Result of `classifier_rules_status`, and payload of `classifier-rules-updated`. */
+export interface RulesStatus {
+    path: string;
+    exists: boolean;
+    rules: number;
+    custom: number;
+    error: string | null;
+    loaded_at: number | null;
+}
+
+/** Result of `learning_status` and `reset_learning`. */
+export interface LearningStatus {
+    enabled: boolean;
+    min_examples: number;
+    groups: { name: string; examples: number }[];
+}
+
+/** One row of `group_confidence_summary`. */
+export interface GroupConfidence {
+    name: string;
+    items: number;
+    average: number; // 0–1
+    below: number;
+}
+
+/** One row of `secret_detections`; the secret itself is never recorded. */
+export interface SecretDetection {
+    id: number;
+    item_id: number | null; // null once the item is deleted
+    detector: string; // e.g. "aws-access-key-id", "github-token"
+    masked: boolean;
+    source_app: string | null;
+    detected_at: string; // UTC "YYYY-MM-DD HH:MM:SS"
+}
+
+/** Result of `rotate_sensitive_key`. */
+export interface RotationReport {
+    key_id: string;
+    rotated: number;
+    failed: { id: number; error: string }[];
+}
+
+/** Error from `backup_data` / `restore_data` after `cancel_backup`. */
+export const BACKUP_CANCELLED = "Backup cancelled";
+
+/** Payload of the "backup-progress" event. */
+export interface BackupProgress {
+    phase: "export" | "images" | "verify" | "restore";
+    done: number;
+    total: number;
+}
+
+/** What `restore_data` with `dryRun` says a restore would do. */
+export interface RestoreReport {
+    new_items: number;
+    duplicates: number;
+    conflicts: number;
+    conflict_samples: RestoreConflict[];
+    new_groups: string[];
+    removed_items: number;
+    removed_groups: string[];
+    new_images: number;
+    existing_images: number;
+}
+
+export interface RestoreConflict {
+    existing_id: number;
+    content_type: string;
+    preview: string;
+    fields: ("pinned" | "description" | "groups")[];
+    backup_wins: boolean;
+}
+
+/** Formats `preview_import` / `import_history` read. */
+export type ImportSource = "copyq" | "maccy" | "ditto" | "clipy" | "gpaste" | "klipper" | "ortu";
+
+/** Formats `export_group` / `export_all_txt` write. */
+export type ExportFormat = "text" | "jsonl" | "csv" | "markdown";
+
+/** Result of `preview_import`. */
+export interface ImportPreview {
+    source: ImportSource;
+    total: number;
+    skipped: number;
+    sample: ClipboardItem[];
+    report: RestoreReport;
 }
 
 export interface Snippet {